@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often an abortable wait checks whether it was aborted
+pub(crate) static ABORT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Aborts a `CockLock::lock_wait_abortable` from another thread
+///
+/// Clones share the same state, hand one to the thread that decides to give
+/// up. A handle stays aborted once `abort` was called.
+#[derive(Debug, Clone, Default)]
+pub struct AbortHandle {
+    aborted: Arc<AtomicBool>,
+}
+
+impl AbortHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AbortHandle;
+
+    #[test]
+    fn clones_share_the_abort() {
+        let handle = AbortHandle::new();
+        let clone = handle.clone();
+        assert!(!handle.is_aborted());
+        clone.abort();
+        assert!(handle.is_aborted());
+    }
+}
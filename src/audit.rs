@@ -0,0 +1,170 @@
+//! A log of what happened to locks
+//!
+//! The lock table only knows who holds a lock now, which is no help in a
+//! post-mortem asking who held the deploy lock at 03:00. With
+//! `CockLockBuilder::with_audit`, a trigger records every acquisition,
+//! extension, release and expiry in a `<table>_audit` table, with the time
+//! of the database, and `CockLock::audit_events` reads them back. The log
+//! grows with every lock operation and is never pruned by the crate.
+
+use std::time::SystemTime;
+
+use postgres::types::ToSql;
+use postgres::Row;
+
+use crate::errors::CockLockError;
+use crate::identity::Identity;
+use crate::lock::{CockLock, Operation};
+
+/// What happened to a lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuditAction {
+    Acquired,
+    /// The holder extended, refreshed or relocked the lock
+    Extended,
+    Released,
+    /// The lease ran out and the lock was deleted, whenever that happened
+    /// after the expiry
+    Expired,
+}
+
+impl AuditAction {
+    fn parse(event: &str) -> Option<Self> {
+        match event {
+            "acquired" => Some(AuditAction::Acquired),
+            "extended" => Some(AuditAction::Extended),
+            "released" => Some(AuditAction::Released),
+            "expired" => Some(AuditAction::Expired),
+            _ => None,
+        }
+    }
+}
+
+/// An entry of the audit log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    pub lock_name: String,
+    /// The ID of the instance holding the lock
+    pub holder: Identity,
+    pub action: AuditAction,
+    pub fencing_token: Option<i64>,
+    /// When the lease ends or ended, `None` for locks without an expiry
+    pub expires_at: Option<SystemTime>,
+    /// When the event was recorded, on the clock of the database
+    pub recorded_at: SystemTime,
+}
+
+impl AuditEvent {
+    fn from_row(row: &Row) -> Option<Self> {
+        Some(Self {
+            lock_name: row.get("lock_name"),
+            holder: row.get("client_id"),
+            action: AuditAction::parse(row.get("event"))?,
+            fencing_token: row.get("fencing_token"),
+            expires_at: row.get("expires_at"),
+            recorded_at: row.get("recorded_at"),
+        })
+    }
+}
+
+/// Which events `CockLock::audit_events` reads
+///
+/// By default the latest 100 events of every lock are read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditQuery {
+    lock_name: Option<String>,
+    since: Option<SystemTime>,
+    until: Option<SystemTime>,
+    limit: i64,
+}
+
+impl Default for AuditQuery {
+    fn default() -> Self {
+        Self {
+            lock_name: None,
+            since: None,
+            until: None,
+            limit: 100,
+        }
+    }
+}
+
+impl AuditQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only read the events of the lock `lock_name`
+    pub fn of_lock<T: ToString>(mut self, lock_name: T) -> Self {
+        self.lock_name = Some(lock_name.to_string());
+        self
+    }
+
+    /// Only read the events recorded at or after `since`
+    pub fn since(mut self, since: SystemTime) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Only read the events recorded at or before `until`
+    pub fn until(mut self, until: SystemTime) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    /// The maximum number of events to read
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = limit;
+        self
+    }
+}
+
+impl CockLock {
+    /// Read the audit log, latest event first
+    ///
+    /// Requires `CockLockBuilder::with_audit`. Every client keeps a log of
+    /// its own, this reads the first reachable one.
+    pub fn audit_events(&mut self, query: &AuditQuery) -> Result<Vec<AuditEvent>, CockLockError> {
+        self.require_table("audit logs")?;
+        let params: &[&(dyn ToSql + Sync)] =
+            &[&query.lock_name, &query.since, &query.until, &query.limit];
+        let (_, result) = self.on_available_client(Operation::Audit, |client, queries, _| {
+            client.query(&queries.audit_events, params)
+        });
+
+        Ok(result?.iter().filter_map(AuditEvent::from_row).collect())
+    }
+
+    /// Who held the lock `lock_name` at `at` according to the audit log,
+    /// `None` if nobody did
+    pub fn holder_at<T: ToString>(
+        &mut self,
+        lock_name: T,
+        at: SystemTime,
+    ) -> Result<Option<Identity>, CockLockError> {
+        let query = AuditQuery::new().of_lock(lock_name).until(at).limit(1);
+        Ok(self
+            .audit_events(&query)?
+            .into_iter()
+            .next()
+            .filter(|event| {
+                matches!(event.action, AuditAction::Acquired | AuditAction::Extended)
+                    && event.expires_at.is_none_or(|expires_at| expires_at > at)
+            })
+            .map(|event| event.holder))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AuditAction;
+
+    #[test]
+    fn events_are_parsed_from_their_names() {
+        assert_eq!(AuditAction::parse("acquired"), Some(AuditAction::Acquired));
+        assert_eq!(AuditAction::parse("extended"), Some(AuditAction::Extended));
+        assert_eq!(AuditAction::parse("released"), Some(AuditAction::Released));
+        assert_eq!(AuditAction::parse("expired"), Some(AuditAction::Expired));
+        assert_eq!(AuditAction::parse("stolen"), None);
+    }
+}
@@ -0,0 +1,247 @@
+use postgres::Client;
+
+use crate::errors::CockLockError;
+
+/// The database engine behind the clients
+///
+/// Both engines speak the Postgres protocol, but CockroachDB lacks trigger
+/// functions, so the lock table is set up and locked differently. Features
+/// that rely on triggers (fairness statistics, noticing a lock that was taken
+/// and released again in `CockLock::read_if_unlocked`) are PostgreSQL only.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Backend {
+    #[default]
+    Postgres,
+    CockroachDb,
+    /// PostgreSQL advisory locks instead of a lock table, only chosen with
+    /// `CockLockBuilder::with_backend`
+    ///
+    /// A lock is held by the connection that took it, keyed by a hash of the
+    /// table and lock name, so there is no table to write, reap or clean up.
+    /// In exchange leases don't expire: a lock is held until it is unlocked
+    /// or its connection closes, whatever its timeout, and a connection that
+    /// is replaced takes the locks with it, which are then reported as lost.
+    /// Every fencing token is 0, so writes can't be fenced. Only locking
+    /// (`lock`, `try_acquire`, `lock_wait` and the guards built on them) and
+    /// unlocking are supported, everything reading or writing the lock table
+    /// fails with `CockLockError::Unsupported`.
+    Advisory,
+}
+
+/// The oldest PostgreSQL version with `execute function` in triggers
+static MIN_POSTGRES_VERSION: u32 = 11;
+
+/// The first PostgreSQL version with `create or replace trigger`
+static CREATE_OR_REPLACE_TRIGGER_VERSION: u32 = 14;
+
+/// The engine and version a client is connected to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendInfo {
+    pub backend: Backend,
+    pub major_version: u32,
+    /// The full output of `version()`
+    pub version: String,
+}
+
+impl BackendInfo {
+    pub(crate) fn detect(client: &mut Client) -> Result<Option<Self>, postgres::Error> {
+        let version: String = client.query_one("select version();", &[])?.get(0);
+        Ok(Self::parse(&version))
+    }
+
+    /// Parse the output of `version()`, e.g. `PostgreSQL 14.5 on x86_64...`
+    /// or `CockroachDB CCL v23.1.11 (x86_64...)`
+    fn parse(version: &str) -> Option<Self> {
+        let mut words = version.split_whitespace();
+        let (backend, number) = match words.next()? {
+            "PostgreSQL" => (Backend::Postgres, words.next()?),
+            "CockroachDB" => (
+                Backend::CockroachDb,
+                words.find_map(|word| word.strip_prefix('v'))?,
+            ),
+            _ => return None,
+        };
+
+        let digits = number.split(|char: char| !char.is_ascii_digit()).next()?;
+        Some(Self {
+            backend,
+            major_version: digits.parse().ok()?,
+            version: version.to_owned(),
+        })
+    }
+}
+
+/// The flavor of SQL spoken by all clients
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Dialect {
+    pub backend: Backend,
+    /// Whether triggers must be dropped and created instead of replaced
+    pub legacy_triggers: bool,
+}
+
+impl Dialect {
+    /// Pick the dialect for the detected clients, `backend` overrides the
+    /// detected engine
+    ///
+    /// Clients whose engine could not be detected are assumed to run the
+    /// chosen one.
+    pub fn new(
+        backend: Option<Backend>,
+        infos: &[Option<BackendInfo>],
+    ) -> Result<Self, CockLockError> {
+        let detected: Vec<&BackendInfo> = infos.iter().flatten().collect();
+        let backend = match backend {
+            Some(Backend::Advisory) => {
+                if detected
+                    .iter()
+                    .any(|info| info.backend != Backend::Postgres)
+                {
+                    return Err(CockLockError::Unsupported(
+                        "advisory locks on CockroachDB".to_owned(),
+                    ));
+                }
+                Backend::Advisory
+            }
+            Some(backend) => backend,
+            None => {
+                let backend = detected
+                    .first()
+                    .map_or(Backend::default(), |info| info.backend);
+                if detected.iter().any(|info| info.backend != backend) {
+                    return Err(CockLockError::Unsupported(
+                        "clients running different database engines".to_owned(),
+                    ));
+                }
+                backend
+            }
+        };
+
+        let postgres_versions = detected
+            .iter()
+            .filter(|info| info.backend == Backend::Postgres)
+            .map(|info| info.major_version);
+        let oldest = postgres_versions.min();
+        if backend != Backend::CockroachDb {
+            if let Some(version) = oldest.filter(|version| *version < MIN_POSTGRES_VERSION) {
+                return Err(CockLockError::Unsupported(format!("PostgreSQL {version}")));
+            }
+        }
+
+        Ok(Self {
+            backend,
+            legacy_triggers: backend == Backend::Postgres
+                && oldest.is_some_and(|version| version < CREATE_OR_REPLACE_TRIGGER_VERSION),
+        })
+    }
+}
+
+/// Rewrite the `create or replace trigger` statements of a query for versions
+/// that don't support them
+///
+/// All triggers in the queries are created on `TABLE_NAME`.
+pub(crate) fn without_create_or_replace_trigger(query: &str) -> String {
+    query
+        .lines()
+        .map(
+            |line| match line.strip_prefix("create or replace trigger ") {
+                Some(name) => {
+                    format!("drop trigger if exists {name} on TABLE_NAME;\ncreate trigger {name}")
+                }
+                None => line.to_owned(),
+            },
+        )
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{without_create_or_replace_trigger, Backend, BackendInfo, Dialect};
+    use crate::lock::{AcquisitionMode, CockLockQueries};
+    use crate::table::Table;
+
+    #[test]
+    fn cockroachdb_queries_need_no_triggers() {
+        let postgres = CockLockQueries::new(
+            &Table::new(None, "_locks"),
+            Dialect::default(),
+            AcquisitionMode::Upsert,
+        );
+        assert!(postgres.create_table.contains("create or replace trigger"));
+
+        let dialect = Dialect {
+            backend: Backend::CockroachDb,
+            legacy_triggers: false,
+        };
+        let cockroach = CockLockQueries::new(
+            &Table::new(None, "_locks"),
+            dialect,
+            AcquisitionMode::Upsert,
+        );
+        for query in [&cockroach.create_table, &cockroach.clean_up] {
+            assert!(!query.contains("trigger"));
+            assert!(!query.contains("function"));
+        }
+        assert!(cockroach.lock.contains("\"_locks\".expires_at < now()"));
+    }
+
+    #[test]
+    fn versions_are_detected() {
+        let postgres = BackendInfo::parse("PostgreSQL 14.5 on x86_64-pc-linux-musl").unwrap();
+        assert_eq!(postgres.backend, Backend::Postgres);
+        assert_eq!(postgres.major_version, 14);
+
+        let beta = BackendInfo::parse("PostgreSQL 17beta1 on aarch64").unwrap();
+        assert_eq!(beta.major_version, 17);
+
+        let cockroach =
+            BackendInfo::parse("CockroachDB CCL v23.1.11 (x86_64-pc-linux-gnu, built)").unwrap();
+        assert_eq!(cockroach.backend, Backend::CockroachDb);
+        assert_eq!(cockroach.major_version, 23);
+
+        assert!(BackendInfo::parse("MySQL 8.0").is_none());
+    }
+
+    #[test]
+    fn advisory_locks_need_postgres() {
+        let postgres = BackendInfo::parse("PostgreSQL 14.5 on x86_64").unwrap();
+        let dialect = Dialect::new(Some(Backend::Advisory), &[Some(postgres), None]).unwrap();
+        assert_eq!(dialect.backend, Backend::Advisory);
+        let queries = CockLockQueries::new(
+            &Table::new(None, "_locks"),
+            dialect,
+            AcquisitionMode::Upsert,
+        );
+        assert!(queries.lock.contains("pg_try_advisory_lock"));
+        assert!(queries.lock.contains("'_locks/'"));
+        assert!(queries.unlock.contains("pg_advisory_unlock"));
+
+        let cockroach = BackendInfo::parse("CockroachDB CCL v23.1.11 (x86_64)").unwrap();
+        assert!(Dialect::new(Some(Backend::Advisory), &[Some(cockroach)]).is_err());
+    }
+
+    #[test]
+    fn old_postgres_versions_drop_and_create_triggers() {
+        let old = BackendInfo::parse("PostgreSQL 12.1 on x86_64").unwrap();
+        let dialect = Dialect::new(None, &[Some(old), None]).unwrap();
+        assert!(dialect.legacy_triggers);
+
+        let queries = CockLockQueries::new(
+            &Table::new(None, "_locks"),
+            dialect,
+            AcquisitionMode::Upsert,
+        );
+        assert!(!queries.create_table.contains("create or replace trigger"));
+        assert!(queries
+            .create_table
+            .contains("drop trigger if exists _lock_reap_trigger on \"_locks\";"));
+
+        let ancient = BackendInfo::parse("PostgreSQL 10.4 on x86_64").unwrap();
+        assert!(Dialect::new(Some(Backend::Advisory), &[Some(ancient.clone())]).is_err());
+        assert!(Dialect::new(None, &[Some(ancient)]).is_err());
+        assert_eq!(
+            without_create_or_replace_trigger("select 1;"),
+            "select 1;".to_owned()
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use crate::errors::CockLockError;
+use crate::lock::CockLock;
+use crate::notify::DEFAULT_POLL_INTERVAL;
+
+impl CockLock {
+    /// Take one of the `parties` slots of a barrier, returns the index of the
+    /// slot
+    ///
+    /// Each slot is a lock named `<barrier>/<index>`, held for `lease_ms` so
+    /// that the slots of crashed parties free up again. Fails with
+    /// `CockLockError::NotAvailable` if every slot is taken.
+    pub fn arrive_at_barrier<T: ToString>(
+        &mut self,
+        barrier: T,
+        parties: usize,
+        lease_ms: i32,
+    ) -> Result<usize, CockLockError> {
+        let barrier = barrier.to_string();
+        for index in 0..parties {
+            match self.lock(barrier_slot(&barrier, index), lease_ms) {
+                Ok(_) => return Ok(index),
+                Err(CockLockError::NotAvailable) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(CockLockError::NotAvailable)
+    }
+
+    /// The number of parties that arrived at a barrier and still hold their
+    /// slot
+    pub fn barrier_arrivals<T: ToString>(&mut self, barrier: T) -> Result<usize, CockLockError> {
+        let prefix = format!("{}/", barrier.to_string());
+        Ok(self.list_locks_prefix(prefix)?.len())
+    }
+
+    /// Arrive at a barrier and wait up to `timeout` for all `parties` to
+    /// arrive, returns whether they did
+    ///
+    /// The slot is refreshed while waiting and kept afterwards, so that late
+    /// parties still see it; the lease should outlast the time parties take
+    /// to notice each other. Release it with `leave_barrier`.
+    pub fn wait_at_barrier<T: ToString>(
+        &mut self,
+        barrier: T,
+        parties: usize,
+        lease_ms: i32,
+        timeout: Duration,
+    ) -> Result<bool, CockLockError> {
+        let barrier = barrier.to_string();
+        let slot = self.arrive_at_barrier(&barrier, parties, lease_ms)?;
+        let deadline = self.clock.now() + timeout;
+        loop {
+            if self.barrier_arrivals(&barrier)? >= parties {
+                return Ok(true);
+            }
+            if self.clock.now() >= deadline {
+                return Ok(false);
+            }
+            self.clock.sleep(DEFAULT_POLL_INTERVAL);
+            self.refresh(barrier_slot(&barrier, slot))?;
+        }
+    }
+
+    /// Release the slot this instance holds at a barrier, returns whether it
+    /// held one
+    pub fn leave_barrier<T: ToString>(&mut self, barrier: T) -> Result<bool, CockLockError> {
+        let prefix = format!("{}/", barrier.to_string());
+        Ok(!self.unlock_prefix(prefix)?.is_empty())
+    }
+}
+
+/// The lock name of a barrier slot
+fn barrier_slot(barrier: &str, index: usize) -> String {
+    format!("{barrier}/{index}")
+}
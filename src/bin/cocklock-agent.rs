@@ -0,0 +1,285 @@
+//! A sidecar exposing a CockLock instance over HTTP
+//!
+//! Services written in other languages can use the same locks through the
+//! agent instead of reimplementing the SQL. All locks taken through one agent
+//! share its holder ID, so run one agent per application instance.
+//!
+//! ```text
+//! cocklock-agent [--listen 127.0.0.1:7878] [--table _locks] <connection string>...
+//! ```
+//!
+//! | Request                                   | Effect                          |
+//! |-------------------------------------------|---------------------------------|
+//! | `POST /locks/<name>` `{"timeout_ms": 10}` | acquire the lock                |
+//! | `PUT /locks/<name>` `{"timeout_ms": 10}`  | renew the lock                  |
+//! | `DELETE /locks/<name>`                    | release the lock                |
+//! | `GET /locks/<name>/watch?timeout_ms=10`   | wait until the lock is free     |
+//!
+//! Lock names must be percent-encoded. A lock held by someone else is
+//! answered with `409 Conflict`.
+
+use std::sync::{Arc, Mutex};
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+use cocklock::errors::CockLockError;
+use cocklock::notify::{Notifier, PollingNotifier};
+use cocklock::CockLock;
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+static DEFAULT_LISTEN_ADDRESS: &str = "127.0.0.1:7878";
+
+/// How often a watched lock is checked
+static WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Deserialize)]
+struct TimeoutBody {
+    timeout_ms: i32,
+}
+
+#[derive(Serialize)]
+struct LockedBody {
+    lock_name: String,
+    /// The lease handle, for renewing the lock from another agent
+    handle: String,
+    /// To pass along with writes, see `FencingToken`
+    fencing_token: i64,
+}
+
+#[derive(Serialize)]
+struct WatchBody {
+    locked: bool,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Route {
+    Acquire(String),
+    Renew(String),
+    Release(String),
+    Watch(String, Duration),
+}
+
+/// Match a request to a route, `None` if it matches none
+fn route(method: &Method, url: &str) -> Option<Route> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let path = path.strip_prefix("/locks/")?;
+
+    if let Some(lock_name) = path.strip_suffix("/watch") {
+        if *method != Method::Get {
+            return None;
+        }
+        let timeout_ms = query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("timeout_ms="))
+            .and_then(|timeout_ms| timeout_ms.parse().ok())
+            .unwrap_or(0);
+        return Some(Route::Watch(
+            percent_decode(lock_name)?,
+            Duration::from_millis(timeout_ms),
+        ));
+    }
+
+    let lock_name = percent_decode(path)?;
+    match method {
+        Method::Post => Some(Route::Acquire(lock_name)),
+        Method::Put => Some(Route::Renew(lock_name)),
+        Method::Delete => Some(Route::Release(lock_name)),
+        _ => None,
+    }
+}
+
+/// Decode a percent-encoded path segment, `None` if it isn't valid
+fn percent_decode(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut chars = segment.bytes();
+    while let Some(byte) = chars.next() {
+        if byte == b'%' {
+            let high = (chars.next()? as char).to_digit(16)?;
+            let low = (chars.next()? as char).to_digit(16)?;
+            bytes.push((high * 16 + low) as u8);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes)
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+fn json<T: Serialize>(status: u16, body: &T) -> Response<std::io::Cursor<Vec<u8>>> {
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    Response::from_data(serde_json::to_vec(body).unwrap())
+        .with_status_code(status)
+        .with_header(header)
+}
+
+fn error(err: CockLockError) -> Response<std::io::Cursor<Vec<u8>>> {
+    let status = match err {
+        CockLockError::NotAvailable | CockLockError::AlreadyHeldByUs => 409,
+        CockLockError::InfiniteLease(_) => 400,
+        _ => 500,
+    };
+    json(
+        status,
+        &ErrorBody {
+            error: err.to_string(),
+        },
+    )
+}
+
+fn bad_request(message: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    json(
+        400,
+        &ErrorBody {
+            error: message.to_owned(),
+        },
+    )
+}
+
+fn handle(locker: &Mutex<CockLock>, mut request: Request) {
+    let route = route(request.method(), request.url());
+    let mut read_timeout = || -> Option<i32> {
+        let body: TimeoutBody = serde_json::from_reader(request.as_reader()).ok()?;
+        Some(body.timeout_ms)
+    };
+
+    let response = match route {
+        None => bad_request("Unknown route"),
+        Some(Route::Acquire(lock_name)) => match read_timeout() {
+            None => bad_request("Expected a body like {\"timeout_ms\": 10000}"),
+            Some(timeout_ms) => {
+                let mut locker = locker.lock().unwrap();
+                match locker.lock(&lock_name, timeout_ms) {
+                    Ok(fencing_token) => json(
+                        200,
+                        &LockedBody {
+                            handle: locker.lease_handle(&lock_name).to_string(),
+                            lock_name,
+                            fencing_token: fencing_token.value(),
+                        },
+                    ),
+                    Err(err) => error(err),
+                }
+            }
+        },
+        Some(Route::Renew(lock_name)) => match read_timeout() {
+            None => bad_request("Expected a body like {\"timeout_ms\": 10000}"),
+            Some(timeout_ms) => {
+                let mut locker = locker.lock().unwrap();
+                match locker.extend_many(&[&lock_name], timeout_ms) {
+                    Ok(extended) if extended.iter().all(|(_, extended)| *extended) => {
+                        Response::from_data(vec![]).with_status_code(204)
+                    }
+                    Ok(_) => error(CockLockError::NotAvailable),
+                    Err(err) => error(err),
+                }
+            }
+        },
+        Some(Route::Release(lock_name)) => match locker.lock().unwrap().unlock(&lock_name) {
+            Ok(()) => Response::from_data(vec![]).with_status_code(204),
+            Err(err) => error(err),
+        },
+        Some(Route::Watch(lock_name, timeout)) => {
+            let deadline = Instant::now() + timeout;
+            let mut notifier = PollingNotifier::new(WATCH_INTERVAL);
+            loop {
+                // The lock is only held while checking, so other requests
+                // aren't blocked by the watch
+                let result = locker.lock().unwrap().read_if_unlocked(&lock_name, || ());
+                match result {
+                    Ok(()) => break json(200, &WatchBody { locked: false }),
+                    Err(CockLockError::NotAvailable) if Instant::now() < deadline => {
+                        let remaining = deadline.saturating_duration_since(Instant::now());
+                        match notifier.wait(&lock_name, remaining) {
+                            Ok(()) => continue,
+                            Err(err) => break error(err),
+                        }
+                    }
+                    Err(CockLockError::NotAvailable) => {
+                        break json(200, &WatchBody { locked: true })
+                    }
+                    Err(err) => break error(err),
+                }
+            }
+        }
+    };
+
+    if let Err(err) = request.respond(response) {
+        eprintln!("Failed to respond: {err}");
+    }
+}
+
+fn main() {
+    let mut listen_address = DEFAULT_LISTEN_ADDRESS.to_owned();
+    let mut table_name = None;
+    let mut connection_strings = vec![];
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => listen_address = args.next().expect("--listen needs an address"),
+            "--table" => table_name = Some(args.next().expect("--table needs a name")),
+            _ => connection_strings.push(arg),
+        }
+    }
+
+    let mut builder = CockLock::builder().with_connection_strings(connection_strings);
+    if let Some(table_name) = table_name {
+        builder = builder.with_table_name(table_name);
+    }
+    let locker = match builder.build() {
+        Ok(locker) => Arc::new(Mutex::new(locker)),
+        Err(err) => {
+            eprintln!("Failed to connect: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    let server = match Server::http(&listen_address) {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("Failed to listen on {listen_address}: {err}");
+            std::process::exit(1);
+        }
+    };
+    println!("Listening on {listen_address}");
+
+    for request in server.incoming_requests() {
+        let locker = locker.clone();
+        spawn(move || handle(&locker, request));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tiny_http::Method;
+
+    use super::{route, Route};
+
+    #[test]
+    fn requests_are_routed_by_method_and_path() {
+        assert_eq!(
+            route(&Method::Post, "/locks/tenant%2F1"),
+            Some(Route::Acquire("tenant/1".to_owned()))
+        );
+        assert_eq!(
+            route(&Method::Delete, "/locks/a%20b"),
+            Some(Route::Release("a b".to_owned()))
+        );
+        assert_eq!(
+            route(&Method::Get, "/locks/job/watch?timeout_ms=500"),
+            Some(Route::Watch("job".to_owned(), Duration::from_millis(500)))
+        );
+        assert_eq!(route(&Method::Get, "/locks/job"), None);
+        assert_eq!(route(&Method::Post, "/locks/%zz"), None);
+        assert_eq!(route(&Method::Post, "/other"), None);
+    }
+}
@@ -0,0 +1,173 @@
+//! Lock change events from a CockroachDB changefeed
+//!
+//! CockroachDB has no `LISTEN`/`NOTIFY`, but a core changefeed on the lock
+//! table streams every write to it. Core changefeeds need rangefeeds to be
+//! enabled on the cluster (`set cluster setting kv.rangefeed.enabled = true`).
+
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+use postgres::fallible_iterator::FallibleIterator;
+use postgres::types::ToSql;
+use postgres::CancelToken;
+
+use crate::backend::Backend;
+use crate::connection::Connector;
+use crate::errors::CockLockError;
+use crate::lock::{CockLock, Operation};
+use crate::notify::Notifier;
+use crate::table::Table;
+
+/// Wakes waiters as soon as the changefeed reports a write to their lock
+///
+/// The changefeed runs on a connection of its own in a background thread,
+/// which stops when the notifier is dropped.
+pub struct ChangefeedNotifier {
+    events: Receiver<Result<String, postgres::Error>>,
+    connector: Connector,
+    cancel_token: CancelToken,
+    label: String,
+}
+
+impl ChangefeedNotifier {
+    fn start(connector: Connector, label: String, table: &Table) -> Result<Self, CockLockError> {
+        let mut client = connector
+            .connect()
+            .map_err(|err| CockLockError::postgres(err, Operation::Watch, &label))?;
+        let cancel_token = client.cancel_token();
+        let query = table.fill("experimental changefeed for TABLE_NAME with diff;");
+        let namespace = table.namespace.clone().unwrap_or_default();
+
+        let (sender, events) = channel();
+        spawn(move || {
+            let rows = client.query_raw(query.as_str(), std::iter::empty::<&dyn ToSql>());
+            let mut rows = match rows {
+                Ok(rows) => rows,
+                Err(err) => {
+                    let _ = sender.send(Err(err));
+                    return;
+                }
+            };
+            loop {
+                let event = match rows.next() {
+                    Ok(Some(row)) => row.get::<_, Option<Vec<u8>>>("value"),
+                    Ok(None) => return,
+                    Err(err) => {
+                        let _ = sender.send(Err(err));
+                        return;
+                    }
+                };
+                // Rows without a value are resolved timestamps
+                let lock_name = event
+                    .as_deref()
+                    .and_then(|event| changed_lock_name(event, &namespace));
+                if let Some(lock_name) = lock_name {
+                    if sender.send(Ok(lock_name)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            events,
+            connector,
+            cancel_token,
+            label,
+        })
+    }
+}
+
+impl Notifier for ChangefeedNotifier {
+    fn wait(&mut self, lock_name: &str, timeout: Duration) -> Result<(), CockLockError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.events.recv_timeout(remaining) {
+                Ok(Ok(changed)) if changed == lock_name => return Ok(()),
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => {
+                    return Err(CockLockError::postgres(err, Operation::Watch, &self.label))
+                }
+                Err(RecvTimeoutError::Timeout) => return Ok(()),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(CockLockError::ClientNotAvailable)
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ChangefeedNotifier {
+    fn drop(&mut self) {
+        let _ = self.connector.cancel(&self.cancel_token);
+    }
+}
+
+impl CockLock {
+    /// Start a changefeed on the lock table of the first client that was
+    /// given as a connection string
+    ///
+    /// Only available on CockroachDB, Postgres has `LISTEN`/`NOTIFY` instead.
+    pub fn changefeed_notifier(&self) -> Result<ChangefeedNotifier, CockLockError> {
+        if self.dialect.backend != Backend::CockroachDb {
+            return Err(CockLockError::Unsupported(
+                "changefeeds on PostgreSQL".to_owned(),
+            ));
+        }
+
+        let (connector, label) = self
+            .connectors
+            .iter()
+            .zip(&self.labels)
+            .find_map(|(connector, label)| Some((connector.clone()?, label.clone())))
+            .ok_or_else(|| {
+                CockLockError::Unsupported("changefeeds on clients added as objects".to_owned())
+            })?;
+        ChangefeedNotifier::start(connector, label, &self.table())
+    }
+}
+
+/// The name of the lock a changefeed event is about, if it is in
+/// `namespace`
+///
+/// Events look like `{"after": {...}, "before": {...}}`, deleted rows have no
+/// `after` and inserted rows have no `before`. Rows written before there were
+/// namespaces have none.
+fn changed_lock_name(value: &[u8], namespace: &str) -> Option<String> {
+    let event: serde_json::Value = serde_json::from_slice(value).ok()?;
+    let row = ["after", "before"]
+        .iter()
+        .map(|version| &event[version])
+        .find(|row| row.is_object())?;
+    if row["namespace"].as_str().unwrap_or_default() != namespace {
+        return None;
+    }
+    row["lock_name"].as_str().map(ToOwned::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::changed_lock_name;
+
+    #[test]
+    fn events_name_the_changed_lock() {
+        let released = br#"{"after": null, "before": {"lock_name": "task", "rowid": 1}}"#;
+        assert_eq!(changed_lock_name(released, ""), Some("task".to_owned()));
+
+        let locked = br#"{"after": {"lock_name": "job", "namespace": ""}, "before": null}"#;
+        assert_eq!(changed_lock_name(locked, ""), Some("job".to_owned()));
+
+        assert_eq!(changed_lock_name(br#"{"resolved": "1"}"#, ""), None);
+        assert_eq!(changed_lock_name(b"not json", ""), None);
+    }
+
+    #[test]
+    fn events_of_other_namespaces_are_skipped() {
+        let locked = br#"{"after": {"lock_name": "job", "namespace": "billing"}, "before": null}"#;
+        assert_eq!(changed_lock_name(locked, "billing"), Some("job".to_owned()));
+        assert_eq!(changed_lock_name(locked, ""), None);
+        assert_eq!(changed_lock_name(locked, "shipping"), None);
+    }
+}
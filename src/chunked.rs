@@ -0,0 +1,124 @@
+use std::fmt::{Debug, Formatter};
+use std::iter::Peekable;
+
+use crate::errors::CockLockError;
+use crate::fencing::FencingToken;
+use crate::lock::CockLock;
+
+/// How a batch run with `CockLock::with_lock_chunked` ended
+pub struct ChunkedRun<I: Iterator> {
+    /// The fencing token the lock was taken with
+    pub fencing_token: FencingToken,
+    /// The number of items that were processed under the lock
+    pub processed: usize,
+    /// The items that were left because the lock was lost, starting with the
+    /// first one that wasn't processed
+    pub remaining: Peekable<I>,
+    /// Why the run stopped early, `None` if every item was processed
+    pub stopped_by: Option<CockLockError>,
+}
+
+// Derived, `Debug` would only be required of `I` and not of its items, which
+// `Peekable` needs as well
+impl<I> Debug for ChunkedRun<I>
+where
+    I: Iterator + Debug,
+    I::Item: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkedRun")
+            .field("fencing_token", &self.fencing_token)
+            .field("processed", &self.processed)
+            .field("remaining", &self.remaining)
+            .field("stopped_by", &self.stopped_by)
+            .finish()
+    }
+}
+
+impl<I: Iterator> ChunkedRun<I> {
+    /// Whether every item was processed
+    pub fn is_complete(&self) -> bool {
+        self.stopped_by.is_none()
+    }
+}
+
+impl CockLock {
+    /// Take a lock and process `items` one at a time while holding it,
+    /// renewing the lease to `timeout_ms` before each item after the first
+    ///
+    /// Each item must fit into a single lease. If the lease ran out while an
+    /// item was processed, or it can't be renewed, the run stops before the
+    /// next item and the remaining items are handed back, so that a batch
+    /// that got slower than planned gives up its work instead of running on
+    /// without the lock. The lock is released once the run ends, also when
+    /// `work` panics. Fails without processing anything if the lock can't be
+    /// taken.
+    pub fn with_lock_chunked<T, I, F>(
+        &mut self,
+        lock_name: T,
+        timeout_ms: i32,
+        items: I,
+        mut work: F,
+    ) -> Result<ChunkedRun<I::IntoIter>, CockLockError>
+    where
+        T: ToString,
+        I: IntoIterator,
+        F: FnMut(I::Item),
+    {
+        let lock_name = lock_name.to_string();
+        if timeout_ms == 0 {
+            return Err(CockLockError::InfiniteLease(lock_name));
+        }
+        self.require_table("chunked runs")?;
+        let mut guard = self.lock_guard(&lock_name, timeout_ms)?;
+        let fencing_token = guard.fencing_token();
+
+        let mut remaining = items.into_iter().peekable();
+        let mut processed = 0;
+        let mut stopped_by = None;
+        while remaining.peek().is_some() {
+            if processed > 0 {
+                if let Err(err) = guard
+                    .cock_lock()
+                    .renew_between_items(&lock_name, timeout_ms)
+                {
+                    stopped_by = Some(err);
+                    break;
+                }
+            }
+            if let Some(item) = remaining.next() {
+                work(item);
+                processed += 1;
+            }
+        }
+
+        // A lock that was lost has nothing left to release
+        let _ = guard.release_within_timeout();
+        Ok(ChunkedRun {
+            fencing_token,
+            processed,
+            remaining,
+            stopped_by,
+        })
+    }
+
+    /// Renew a lock unless its lease already ran out
+    fn renew_between_items(
+        &mut self,
+        lock_name: &str,
+        timeout_ms: i32,
+    ) -> Result<(), CockLockError> {
+        let now = self.clock.now();
+        if self
+            .held
+            .get(lock_name)
+            .is_none_or(|lock| lock.is_expired(now))
+        {
+            return Err(CockLockError::NotAvailable);
+        }
+        match self.extend_many(&[lock_name], timeout_ms)?[..] {
+            [(_, true)] => Ok(()),
+            _ => Err(CockLockError::NotAvailable),
+        }
+    }
+}
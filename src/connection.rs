@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use postgres::config::Host;
+use postgres::{Client, Config, NoTls};
+
+use crate::tls::TlsConnector;
+
+/// Everything needed to (re-)establish the connection of a single client
+#[derive(Clone)]
+pub(crate) struct Connector {
+    config: Config,
+    tls_connector: Option<TlsConnector>,
+}
+
+impl Connector {
+    /// A connector for `config`, using TLS unless every host is a Unix
+    /// socket
+    ///
+    /// Servers and poolers listening on a socket don't offer TLS, so asking
+    /// for it there would only make `sslmode=require` fail.
+    pub fn new(config: Config, tls_connector: Option<TlsConnector>) -> Self {
+        let tls_connector = tls_connector.filter(|_| !only_unix_sockets(&config));
+        Self {
+            config,
+            tls_connector,
+        }
+    }
+
+    /// Connect to the first host of the connection string that accepts the
+    /// connection (and satisfies `target_session_attrs`, if given)
+    pub fn connect(&self) -> Result<Client, postgres::Error> {
+        match &self.tls_connector {
+            Some(connector) => connector.connect(&self.config),
+            None => self.config.connect(NoTls),
+        }
+    }
+
+    /// Cancel the statement running on a client made by this connector
+    pub fn cancel(&self, token: &postgres::CancelToken) -> Result<(), postgres::Error> {
+        match &self.tls_connector {
+            Some(connector) => connector.cancel(token),
+            None => token.cancel_query(NoTls),
+        }
+    }
+
+    /// A connector to the same hosts as another user, keeping the password
+    /// unless a new one is given
+    pub fn as_user(&self, user: &str, password: Option<&str>) -> Self {
+        let mut config = self.config.clone();
+        config.user(user);
+        if let Some(password) = password {
+            config.password(password);
+        }
+        Self {
+            config,
+            tls_connector: self.tls_connector.clone(),
+        }
+    }
+
+    /// A connector for probes, whose connections give up after `timeout`
+    /// even on a host that stopped answering, which no cancel request can
+    /// reach
+    pub fn for_probes(&self, timeout: Duration) -> Self {
+        let mut config = self.config.clone();
+        config.connect_timeout(timeout).tcp_user_timeout(timeout);
+        Self {
+            config,
+            tls_connector: self.tls_connector.clone(),
+        }
+    }
+
+    /// A label made of the hosts in the connection string, used for clients
+    /// that weren't given a label
+    pub fn hosts_label(&self) -> String {
+        self.config
+            .get_hosts()
+            .iter()
+            .map(|host| match host {
+                Host::Tcp(host) => host.clone(),
+                #[cfg(unix)]
+                Host::Unix(path) => path.display().to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+
+    /// Whether the connection string lists several hosts of the same logical
+    /// database, meaning a lost connection can fail over to another host
+    pub fn has_failover(&self) -> bool {
+        self.config.get_hosts().len() > 1
+    }
+}
+
+/// Whether every host of a configuration is a Unix socket directory
+fn only_unix_sockets(config: &Config) -> bool {
+    let hosts = config.get_hosts();
+    !hosts.is_empty() && hosts.iter().all(|host| !matches!(host, Host::Tcp(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Connector;
+    #[cfg(feature = "native-tls")]
+    use crate::tls::TlsConnector;
+
+    #[test]
+    fn multi_host_connection_strings_have_failover() {
+        let single: postgres::Config = "host=a user=postgres".parse().unwrap();
+        assert!(!Connector::new(single, None).has_failover());
+
+        let multi: postgres::Config = "host=a,b,c user=postgres target_session_attrs=read-write"
+            .parse()
+            .unwrap();
+        assert!(Connector::new(multi, None).has_failover());
+
+        let url: postgres::Config = "postgres://postgres@a:5432,b:5432/postgres"
+            .parse()
+            .unwrap();
+        let url = Connector::new(url, None);
+        assert!(url.has_failover());
+        assert_eq!(url.hosts_label(), "a,b");
+    }
+
+    #[test]
+    fn other_users_connect_to_the_same_hosts() {
+        let config: postgres::Config = "host=a,b user=app password=secret dbname=locks"
+            .parse()
+            .unwrap();
+        let admin = Connector::new(config, None).as_user("admin", Some("hunter2"));
+        assert_eq!(admin.config.get_user(), Some("admin"));
+        assert_eq!(admin.config.get_password(), Some(&b"hunter2"[..]));
+        assert_eq!(admin.config.get_dbname(), Some("locks"));
+        assert_eq!(admin.hosts_label(), "a,b");
+
+        let same_password = admin.as_user("owner", None);
+        assert_eq!(same_password.config.get_user(), Some("owner"));
+        assert_eq!(same_password.config.get_password(), Some(&b"hunter2"[..]));
+    }
+
+    #[test]
+    fn network_settings_are_left_to_the_connection_string() {
+        let config: postgres::Config = "host=a user=app".parse().unwrap();
+        let connector = Connector::new(config, None);
+        assert_eq!(connector.config.get_connect_timeout(), None);
+        assert_eq!(connector.config.get_tcp_user_timeout(), None);
+
+        let config: postgres::Config = "host=a user=app keepalives_idle=7200".parse().unwrap();
+        let connector = Connector::new(config, None);
+        assert_eq!(
+            connector.config.get_keepalives_idle(),
+            Duration::from_secs(7200)
+        );
+    }
+
+    #[cfg(all(unix, feature = "native-tls"))]
+    #[test]
+    fn unix_sockets_skip_tls() {
+        let tls = TlsConnector::NativeTls(postgres_native_tls::MakeTlsConnector::new(
+            native_tls::TlsConnector::new().unwrap(),
+        ));
+        let socket: postgres::Config = "host=/var/run/postgresql user=postgres".parse().unwrap();
+        let socket = Connector::new(socket, Some(tls.clone()));
+        assert!(socket.tls_connector.is_none());
+        assert_eq!(socket.hosts_label(), "/var/run/postgresql");
+
+        let url: postgres::Config = "postgres://postgres@%2Fvar%2Frun%2Fpostgresql/postgres"
+            .parse()
+            .unwrap();
+        assert!(Connector::new(url, Some(tls.clone()))
+            .tls_connector
+            .is_none());
+
+        let mixed: postgres::Config = "host=/var/run/postgresql,db user=postgres".parse().unwrap();
+        assert!(Connector::new(mixed, Some(tls)).tls_connector.is_some());
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+
+use crate::errors::CockLockError;
+use crate::failure::FailureAction;
+use crate::identity::Identity;
+use crate::lock::{CockLock, Operation};
+use crate::queries::PG_COLUMNS_QUERY;
+
+/// A lock whose holder is not the same on every reachable client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DivergentLock {
+    pub lock_name: String,
+    /// The holder on each client, `None` where the lock doesn't exist or the
+    /// client could not be reached
+    pub holders: Vec<Option<Identity>>,
+}
+
+/// The result of comparing the lock tables of all clients
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsistencyReport {
+    /// Indices of the clients that could not be reached, or failed with an
+    /// error the `FailurePolicy` skips. `CockLock::client_labels` names them.
+    pub unreachable_clients: Vec<usize>,
+    /// Indices of the clients whose lock table schema differs from the schema
+    /// of the first reachable client
+    pub schema_mismatches: Vec<usize>,
+    /// Active locks that are missing or held by someone else on some clients
+    pub divergent_locks: Vec<DivergentLock>,
+}
+
+impl ConsistencyReport {
+    /// Whether all reachable clients agree on the schema and the active locks
+    pub fn is_consistent(&self) -> bool {
+        self.schema_mismatches.is_empty() && self.divergent_locks.is_empty()
+    }
+}
+
+impl CockLock {
+    /// Compare the lock tables across all clients
+    ///
+    /// Meant to be run at startup when multiple clients are configured. Clients
+    /// that are part of one cluster always agree, so any divergence reported
+    /// here means the clients are actually independent databases drifting
+    /// apart. Expired locks that weren't reaped yet are ignored.
+    pub fn consistency_check(&mut self) -> Result<ConsistencyReport, CockLockError> {
+        self.require_table("consistency checks")?;
+        let client_count = self.clients.len();
+        let mut report = ConsistencyReport::default();
+        let mut reference_schema: Option<Vec<String>> = None;
+        let mut holders: BTreeMap<String, Vec<Option<Identity>>> = BTreeMap::new();
+
+        let clients = self.clients.iter_mut().zip(&self.labels).enumerate();
+        for (index, (client, label)) in clients {
+            let columns = match client.query(PG_COLUMNS_QUERY, &[&self.table_name, &self.schema]) {
+                Ok(rows) => rows.iter().map(|row| row.get(0)).collect::<Vec<String>>(),
+                Err(err) if self.failure_policy.action(&err) == FailureAction::Skip => {
+                    report.unreachable_clients.push(index);
+                    continue;
+                }
+                Err(err) => {
+                    return Err(CockLockError::postgres(
+                        err,
+                        Operation::ConsistencyCheck,
+                        label,
+                    ));
+                }
+            };
+
+            match &reference_schema {
+                Some(schema) if *schema != columns => report.schema_mismatches.push(index),
+                Some(_) => {}
+                None => reference_schema = Some(columns),
+            }
+
+            let rows = match client.query(&self.queries.active_locks, &[]) {
+                Ok(rows) => rows,
+                Err(err) if self.failure_policy.action(&err) == FailureAction::Skip => {
+                    report.unreachable_clients.push(index);
+                    continue;
+                }
+                Err(err) => {
+                    return Err(CockLockError::postgres(
+                        err,
+                        Operation::ConsistencyCheck,
+                        label,
+                    ));
+                }
+            };
+
+            for row in rows {
+                holders
+                    .entry(row.get("lock_name"))
+                    .or_insert_with(|| vec![None; client_count])[index] =
+                    Some(row.get("client_id"));
+            }
+        }
+
+        let unreachable_clients = &report.unreachable_clients;
+        let divergent_locks = holders
+            .into_iter()
+            .filter(|(_, holders)| {
+                let mut reachable = holders
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, _)| !unreachable_clients.contains(index))
+                    .map(|(_, holder)| *holder);
+                let first = reachable.next().flatten();
+                first.is_none() || reachable.any(|holder| holder != first)
+            })
+            .map(|(lock_name, holders)| DivergentLock { lock_name, holders })
+            .collect();
+        report.divergent_locks = divergent_locks;
+
+        Ok(report)
+    }
+}
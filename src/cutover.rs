@@ -0,0 +1,185 @@
+use std::time::Duration;
+
+use postgres::error::SqlState;
+
+use crate::backend::{Backend, Dialect};
+use crate::errors::CockLockError;
+use crate::lock::{AcquisitionMode, CockLock, CockLockQueries, Operation, MAX_TABLE_NAME_LENGTH};
+use crate::maintenance;
+use crate::queries::{PG_CUTOVER_COPY_QUERY, PG_CUTOVER_SYNC_QUERY};
+use crate::schema::SchemaToken;
+use crate::table::{fill, Table, TABLE_PLACEHOLDER};
+
+/// The lock that keeps instances from renaming the same table at once
+static RENAME_LOCK: &str = "cocklock/rename_table";
+
+/// How long the rename lock is held at most
+static RENAME_TIMEOUT_MS: i32 = 60_000;
+
+/// The lock table that is being migrated away from
+pub(crate) struct Cutover {
+    pub table: Table,
+    pub queries: CockLockQueries,
+}
+
+impl Cutover {
+    pub fn new(table: Table, dialect: Dialect) -> Self {
+        Self {
+            queries: CockLockQueries::new(&table, dialect, AcquisitionMode::Upsert),
+            table,
+        }
+    }
+}
+
+/// The statements copying the active leases of one table to another, along
+/// with the fencing sequence
+fn copy_leases(source_table: &Table, table: &Table) -> String {
+    fill(
+        &(PG_CUTOVER_COPY_QUERY.to_owned() + PG_CUTOVER_SYNC_QUERY),
+        &[(TABLE_PLACEHOLDER, table), ("SOURCE_TABLE", source_table)],
+    )
+}
+
+impl CockLock {
+    /// Whether locks are still written to the old table of a cutover
+    pub fn is_cutting_over(&self) -> bool {
+        self.cutover.is_some()
+    }
+
+    /// Finish the migration started with `CockLockBuilder::with_cutover_from`
+    ///
+    /// Active leases that only exist in the old table are copied to the new
+    /// table, after which this instance stops writing to the old table. Only
+    /// clean up the old table once every instance has finalized its cutover.
+    pub fn finalize_cutover(&mut self) -> Result<(), CockLockError> {
+        let cutover = match &self.cutover {
+            Some(cutover) => cutover,
+            None => return Ok(()),
+        };
+
+        let copy = copy_leases(&cutover.table, &self.table());
+        for (client, label) in self.clients.iter_mut().zip(&self.labels) {
+            client
+                .batch_execute(&copy)
+                .map_err(|err| CockLockError::postgres(err, Operation::Cutover, label))?;
+        }
+
+        self.cutover = None;
+        Ok(())
+    }
+
+    /// Move the locks to a table called `new_name` and drop the current one
+    ///
+    /// The new table is created, the active leases are copied over and the
+    /// old objects are dropped. An internal lock in the old table makes sure
+    /// only one instance migrates; every other instance calls `rename_table`
+    /// too, and those that come later find the old table gone and simply
+    /// switch over. On PostgreSQL each database migrates in a transaction
+    /// that blocks writes to the old table, so no lease taken meanwhile gets
+    /// lost, and instances still using the old table get errors until they
+    /// switch. To keep every instance working throughout, migrate with
+    /// `CockLockBuilder::with_cutover_from` instead. With a maintenance role
+    /// the new table is created as that role, like the old one was.
+    ///
+    /// The instance that migrates fails with `StaleSchemaToken` if the table
+    /// changed since `token` was issued by `verify`.
+    pub fn rename_table<T: ToString>(
+        &mut self,
+        new_name: T,
+        token: &SchemaToken,
+    ) -> Result<(), CockLockError> {
+        let new_name = new_name.to_string();
+        if new_name == self.table_name {
+            return Ok(());
+        }
+        if new_name.len() > MAX_TABLE_NAME_LENGTH {
+            return Err(CockLockError::TableNameTooLong(new_name));
+        }
+        if self.cutover.is_some()
+            || self.acquisition_mode == AcquisitionMode::AppendOnly
+            || self.dialect.backend == Backend::Advisory
+        {
+            return Err(CockLockError::Unsupported(
+                "renaming tables during a cutover, in append-only mode or with advisory locks"
+                    .to_owned(),
+            ));
+        }
+
+        let new_table = self.table().renamed(&new_name);
+        let queries = self.queries_for(&new_table);
+        let wait = Duration::from_millis(RENAME_TIMEOUT_MS.unsigned_abs().into());
+        match self.lock_wait(RENAME_LOCK, RENAME_TIMEOUT_MS, wait) {
+            Ok(_) => {}
+            Err(CockLockError::PostgresError(err, _, _))
+                if err.code() == Some(&SqlState::UNDEFINED_TABLE) =>
+            {
+                // Another instance finished the rename already
+                self.table_name = new_name;
+                self.queries = queries;
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        }
+        if let Err(err) = self.check_schema_token(token, Operation::Cutover) {
+            let _ = self.unlock(RENAME_LOCK);
+            return Err(err);
+        }
+
+        let mut statements = vec![
+            queries.create_table.clone(),
+            copy_leases(&self.table(), &new_table),
+            self.queries.clean_up.clone(),
+        ];
+        if self.fairness_stats {
+            statements.insert(1, queries.create_stats_table.clone());
+        }
+        if self.audit {
+            statements.insert(1, queries.create_audit_table.clone());
+        }
+        let lock_writes = self
+            .table()
+            .fill("lock table TABLE_NAME in exclusive mode;");
+        let clients = self
+            .clients
+            .iter_mut()
+            .zip(&self.connectors)
+            .zip(&self.labels);
+        for ((client, connector), label) in clients {
+            let mut maintenance = match &self.maintenance_role {
+                Some(role) => Some(role.connect(connector.as_ref(), label)?),
+                None => None,
+            };
+            let grants = match maintenance {
+                Some(_) => {
+                    maintenance::grants(client, &[&queries], self.fairness_stats, self.audit)
+                        .map_err(|err| CockLockError::postgres(err, Operation::Cutover, label))?
+                }
+                None => String::new(),
+            };
+            let client = maintenance.as_mut().unwrap_or(client);
+
+            let result = match self.dialect.backend {
+                Backend::Postgres | Backend::Advisory => {
+                    client.transaction().and_then(|mut transaction| {
+                        transaction.batch_execute(&lock_writes)?;
+                        for statement in statements.iter().chain([&grants]) {
+                            transaction.batch_execute(statement)?;
+                        }
+                        transaction.commit()
+                    })
+                }
+                Backend::CockroachDb => statements
+                    .iter()
+                    .chain([&grants])
+                    .try_for_each(|statement| client.batch_execute(statement)),
+            };
+            result.map_err(|err| CockLockError::postgres(err, Operation::Cutover, label))?;
+        }
+
+        self.table_name = new_name;
+        self.queries = queries;
+        // The rename lock was copied along with the other leases
+        let _ = self.unlock(RENAME_LOCK);
+        Ok(())
+    }
+}
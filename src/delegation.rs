@@ -0,0 +1,119 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::errors::CockLockError;
+use crate::identity::Identity;
+use crate::lock::{lease_ms, CockLock};
+
+/// Everything another process needs to keep a lease alive for its holder
+///
+/// A worker that can't reach the database hands its handle to an agent,
+/// either as a string (`to_string` and `parse`) or, with the `serde` feature
+/// enabled, in any serde format. The agent then renews the lease with
+/// `CockLock::extend_for` or `CockLock::refresh_for`. Agents may pass handles
+/// on to other agents in turn.
+///
+/// Anyone with the handle can renew the lease, so treat it like a secret.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LeaseHandle {
+    /// The ID of the instance holding the lock
+    pub holder: Identity,
+    pub lock_name: String,
+}
+
+impl Display for LeaseHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.holder, self.lock_name)
+    }
+}
+
+impl FromStr for LeaseHandle {
+    type Err = CockLockError;
+
+    fn from_str(handle: &str) -> Result<Self, Self::Err> {
+        let invalid = || CockLockError::InvalidLeaseHandle(handle.to_owned());
+        let (holder, lock_name) = handle.split_once(':').ok_or_else(invalid)?;
+        Ok(Self {
+            holder: Uuid::parse_str(holder).map_err(|_| invalid())?.into(),
+            lock_name: lock_name.to_owned(),
+        })
+    }
+}
+
+impl CockLock {
+    /// Get a handle that lets another process renew a lock of this instance
+    ///
+    /// The handle is valid as long as the lock is held, it doesn't check
+    /// whether the lock is held right now.
+    pub fn lease_handle<T: ToString>(&self, lock_name: T) -> LeaseHandle {
+        LeaseHandle {
+            holder: self.id,
+            lock_name: lock_name.to_string(),
+        }
+    }
+
+    /// Extend a lock on behalf of its holder
+    ///
+    /// Returns `CockLockError::NotAvailable` if the holder of the handle no
+    /// longer holds the lock.
+    pub fn extend_for(
+        &mut self,
+        handle: &LeaseHandle,
+        timeout_ms: i32,
+    ) -> Result<(), CockLockError> {
+        self.extend_handle(handle, timeout_ms.into())
+    }
+
+    /// Extend a lock on behalf of its holder like `extend_for`, to a lease
+    /// of `timeout` that is rounded like the one of `lock_for`
+    pub fn extend_for_to(
+        &mut self,
+        handle: &LeaseHandle,
+        timeout: Duration,
+    ) -> Result<(), CockLockError> {
+        self.extend_handle(handle, lease_ms(timeout))
+    }
+
+    fn extend_handle(
+        &mut self,
+        handle: &LeaseHandle,
+        timeout_ms: i64,
+    ) -> Result<(), CockLockError> {
+        let extended = self.extend_many_as(handle.holder, &[&handle.lock_name], timeout_ms)?;
+        match extended.first() {
+            Some((_, true)) => Ok(()),
+            _ => Err(CockLockError::NotAvailable),
+        }
+    }
+
+    /// Reset a lock to the timeout its holder locked it with, on behalf of the
+    /// holder
+    pub fn refresh_for(&mut self, handle: &LeaseHandle) -> Result<(), CockLockError> {
+        self.refresh_as(handle.holder, &handle.lock_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::identity::Identity;
+
+    use super::LeaseHandle;
+
+    #[test]
+    fn lease_handles_round_trip_through_strings() {
+        let handle = LeaseHandle {
+            holder: Identity::random(),
+            lock_name: "jobs:42".to_owned(),
+        };
+        assert_eq!(handle.to_string().parse::<LeaseHandle>().unwrap(), handle);
+
+        assert!("jobs:42".parse::<LeaseHandle>().is_err());
+        assert!("no separator".parse::<LeaseHandle>().is_err());
+    }
+}
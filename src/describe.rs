@@ -0,0 +1,265 @@
+//! What an instance was configured to do
+//!
+//! Configuration drifts: one replica gets a different quorum, another runs a
+//! build without a feature. `CockLock::describe` returns the effective
+//! configuration of an instance, and its `Display` prints it as a banner to
+//! log at startup, so such differences show up in the logs of the replicas.
+
+use std::fmt;
+
+use crate::backend::{Backend, BackendInfo};
+use crate::identity::Identity;
+use crate::lock::{AcquisitionMode, CockLock, InfiniteLeases, RelockPolicy};
+use crate::policy::LockPolicy;
+use crate::quorum::Quorum;
+use crate::quota::LockQuota;
+
+/// The features the crate was built with
+static FEATURES: &[(&str, bool)] = &[
+    ("native-tls", cfg!(feature = "native-tls")),
+    ("rustls", cfg!(feature = "rustls")),
+    ("serde", cfg!(feature = "serde")),
+    ("cbor", cfg!(feature = "cbor")),
+    ("msgpack", cfg!(feature = "msgpack")),
+    ("agent", cfg!(feature = "agent")),
+    ("changefeed", cfg!(feature = "changefeed")),
+    ("testing", cfg!(feature = "testing")),
+    ("r2d2", cfg!(feature = "r2d2")),
+    ("job_scheduler", cfg!(feature = "job_scheduler")),
+    ("webhooks", cfg!(feature = "webhooks")),
+    ("encryption", cfg!(feature = "encryption")),
+    ("tracing", cfg!(feature = "tracing")),
+];
+
+/// How expired locks leave the lock table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reaping {
+    /// A trigger deletes them whenever a lock is taken or extended
+    Trigger,
+    /// Only `CockLock::reap_expired` and relocking their names delete them
+    OnDemand,
+    /// There is nothing to delete, advisory locks end with their session and
+    /// append-only leases are only ever added
+    NotNeeded,
+}
+
+/// A policy set on the builder, for a name or a prefix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyDescription {
+    pub pattern: String,
+    /// Whether `pattern` is a prefix rather than a lock name
+    pub prefix: bool,
+    pub policy: LockPolicy,
+}
+
+/// The effective configuration of an instance, see `CockLock::describe`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Description {
+    pub id: Identity,
+    /// The lock table, qualified with its schema if it has one
+    pub table: String,
+    pub namespace: Option<String>,
+    pub backend: Backend,
+    /// The label and detected version of each client
+    pub clients: Vec<(String, Option<BackendInfo>)>,
+    pub acquisition_mode: AcquisitionMode,
+    pub quorum: Quorum,
+    pub relock_policy: RelockPolicy,
+    pub infinite_leases: InfiniteLeases,
+    pub reaping: Reaping,
+    pub policies: Vec<PolicyDescription>,
+    pub quota: Option<LockQuota>,
+    /// The table being migrated away from, if any
+    pub cutover_from: Option<String>,
+    pub local_fallback: bool,
+    pub fairness_stats: bool,
+    pub audit: bool,
+    pub unlock_notifications: bool,
+    pub release_on_drop: bool,
+    pub duplicate_detection: bool,
+    /// The features the crate was built with
+    pub features: Vec<&'static str>,
+}
+
+impl CockLock {
+    /// Describe the effective configuration of the instance, e.g. to log it
+    /// at startup
+    pub fn describe(&self) -> Description {
+        let reaping = match (self.dialect.backend, self.acquisition_mode) {
+            (Backend::Advisory, _) | (_, AcquisitionMode::AppendOnly) => Reaping::NotNeeded,
+            (Backend::Postgres, _) => Reaping::Trigger,
+            (Backend::CockroachDb, _) => Reaping::OnDemand,
+        };
+        let mut policies: Vec<PolicyDescription> = self
+            .policies
+            .iter()
+            .map(|(pattern, prefix, policy)| PolicyDescription {
+                pattern: pattern.to_owned(),
+                prefix,
+                policy: policy.clone(),
+            })
+            .collect();
+        policies.sort_by(|a, b| (&a.pattern, a.prefix).cmp(&(&b.pattern, b.prefix)));
+
+        Description {
+            id: self.id,
+            table: self.table().key(),
+            namespace: self.namespace.clone(),
+            backend: self.dialect.backend,
+            clients: self
+                .labels
+                .iter()
+                .cloned()
+                .zip(self.backend_info.iter().cloned())
+                .collect(),
+            acquisition_mode: self.acquisition_mode,
+            quorum: self.quorum,
+            relock_policy: self.relock_policy,
+            infinite_leases: self.infinite_leases,
+            reaping,
+            policies,
+            quota: self.quota,
+            cutover_from: self.cutover.as_ref().map(|cutover| cutover.table.key()),
+            local_fallback: self.local_fallback.is_some(),
+            fairness_stats: self.fairness_stats,
+            audit: self.audit,
+            unlock_notifications: self.unlock_notifications,
+            release_on_drop: self.release_on_drop,
+            duplicate_detection: self.duplicate_detection.is_some(),
+            features: FEATURES
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(feature, _)| *feature)
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Description {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "cocklock {} on {:?} table {}",
+            self.id, self.backend, self.table
+        )?;
+        if let Some(namespace) = &self.namespace {
+            writeln!(f, "  namespace: {namespace}")?;
+        }
+        for (label, info) in &self.clients {
+            match info {
+                Some(info) => writeln!(f, "  client {label}: {}", info.version)?,
+                None => writeln!(f, "  client {label}: unknown version")?,
+            }
+        }
+        writeln!(
+            f,
+            "  mode: {:?}, quorum: {:?}, relock: {:?}, infinite leases: {:?}, reaping: {:?}",
+            self.acquisition_mode,
+            self.quorum,
+            self.relock_policy,
+            self.infinite_leases,
+            self.reaping
+        )?;
+        for description in &self.policies {
+            let policy = &description.policy;
+            writeln!(
+                f,
+                "  policy {}{}: default timeout {:?}ms, max timeout {:?}ms, \
+                 renewal {:?}, reacquire delay {:?}",
+                description.pattern,
+                if description.prefix { "*" } else { "" },
+                policy.default_timeout_ms,
+                policy.max_timeout_ms,
+                policy.renewal_interval,
+                policy.reacquire_delay
+            )?;
+        }
+        if let Some(quota) = self.quota {
+            let enforced = if quota.in_database {
+                "in the database"
+            } else {
+                "locally"
+            };
+            writeln!(f, "  quota: {} locks, enforced {enforced}", quota.max_locks)?;
+        }
+        if let Some(table) = &self.cutover_from {
+            writeln!(f, "  cutover from: {table}")?;
+        }
+        let options = [
+            ("local fallback", self.local_fallback),
+            ("fairness stats", self.fairness_stats),
+            ("audit", self.audit),
+            ("unlock notifications", self.unlock_notifications),
+            ("release on drop", self.release_on_drop),
+            ("duplicate detection", self.duplicate_detection),
+        ];
+        let options: Vec<&str> = options
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(option, _)| *option)
+            .collect();
+        if !options.is_empty() {
+            writeln!(f, "  options: {}", options.join(", "))?;
+        }
+        write!(f, "  features: {}", self.features.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Description, PolicyDescription, Reaping};
+    use crate::backend::Backend;
+    use crate::identity::Identity;
+    use crate::lock::{AcquisitionMode, InfiniteLeases, RelockPolicy};
+    use crate::policy::LockPolicy;
+    use crate::quorum::Quorum;
+    use crate::quota::LockQuota;
+
+    #[test]
+    fn descriptions_print_as_a_banner() {
+        let description = Description {
+            id: Identity::random(),
+            table: "locks._locks".to_owned(),
+            namespace: Some("billing".to_owned()),
+            backend: Backend::Postgres,
+            clients: vec![("db-1".to_owned(), None)],
+            acquisition_mode: AcquisitionMode::Upsert,
+            quorum: Quorum::Majority,
+            relock_policy: RelockPolicy::default(),
+            infinite_leases: InfiniteLeases::Deny,
+            reaping: Reaping::Trigger,
+            policies: vec![PolicyDescription {
+                pattern: "deploy/".to_owned(),
+                prefix: true,
+                policy: LockPolicy::new().with_default_timeout(30_000),
+            }],
+            quota: Some(LockQuota::new(10)),
+            cutover_from: None,
+            local_fallback: false,
+            fairness_stats: false,
+            audit: true,
+            unlock_notifications: true,
+            release_on_drop: false,
+            duplicate_detection: false,
+            features: vec!["native-tls"],
+        };
+        let banner = description.to_string();
+
+        assert!(banner.starts_with(&format!(
+            "cocklock {} on Postgres table locks._locks\n",
+            description.id
+        )));
+        for line in [
+            "  namespace: billing\n",
+            "  client db-1: unknown version\n",
+            "quorum: Majority",
+            "infinite leases: Deny, reaping: Trigger\n",
+            "  policy deploy/*: default timeout Some(30000)ms",
+            "  quota: 10 locks, enforced locally\n",
+            "  options: audit, unlock notifications\n",
+        ] {
+            assert!(banner.contains(line), "{line:?} missing from {banner}");
+        }
+        assert!(banner.ends_with("  features: native-tls"));
+    }
+}
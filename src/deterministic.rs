@@ -0,0 +1,141 @@
+//! Building blocks for reproducible tests of lock usage
+//!
+//! Replacing the clock with a `VirtualClock` (`CockLockBuilder::with_clock`,
+//! `RenewalScheduler::with_clock`) makes retries and renewals independent of
+//! wall time, and seeding the instance IDs (`CockLockBuilder::with_seed`)
+//! makes every run hand out the same IDs.
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+/// The source of time for retries and renewals
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+/// The real clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+/// A clock that only moves when told to
+///
+/// Sleeping moves the clock forward instead of blocking. Clones share the
+/// same time.
+#[derive(Debug, Clone)]
+pub struct VirtualClock {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    /// The time that passed since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
+
+/// A small seeded random number generator (SplitMix64)
+///
+/// Not suitable for anything security related, it only exists so that tests
+/// get the same sequence on every run.
+#[derive(Debug, Clone)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A version 4 UUID made of the next random numbers
+    pub fn uuid(&mut self) -> Uuid {
+        let high = self.next_u64().to_be_bytes();
+        let low = self.next_u64().to_be_bytes();
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&high);
+        bytes[8..].copy_from_slice(&low);
+        uuid::Builder::from_random_bytes(bytes).into_uuid()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Clock, SeededRng, VirtualClock};
+
+    #[test]
+    fn virtual_clocks_only_move_when_told() {
+        let clock = VirtualClock::new();
+        let start = clock.now();
+        assert_eq!(clock.now(), start);
+
+        clock.sleep(Duration::from_secs(60));
+        clock.clone().advance(Duration::from_secs(1));
+        assert_eq!(clock.now() - start, Duration::from_secs(61));
+    }
+
+    #[test]
+    fn seeded_rngs_repeat_their_sequence() {
+        let ids: Vec<_> = (0..2)
+            .map(|_| {
+                let mut rng = SeededRng::new(42);
+                (rng.uuid(), rng.uuid())
+            })
+            .collect();
+        assert_eq!(ids[0], ids[1]);
+        assert_ne!(ids[0].0, ids[0].1);
+        assert_eq!(ids[0].0.get_version_num(), 4);
+    }
+}
@@ -0,0 +1,234 @@
+//! Leader election on top of a lock
+//!
+//! Every candidate campaigns for the same lock; the one holding it leads and
+//! renews it, the others keep trying in case it stops. The subtle part is
+//! knowing when to stop acting as leader: a leader that can't reach the
+//! database doesn't learn that its lease ran out, so `is_leader` turns false
+//! on its own once the lease is about to end, allowing for clock drift
+//! between the instance and the database.
+
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{spawn, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::errors::CockLockError;
+use crate::guard::DEFAULT_RELEASE_TIMEOUT;
+use crate::hooks::Transition;
+use crate::lock::{lease_ms, CockLock};
+
+type Callback = Box<dyn FnMut() + Send>;
+
+/// How to campaign for leadership, see `CockLock::campaign`
+pub struct LeaderElection {
+    lease_ms: i64,
+    interval: Duration,
+    drift: Duration,
+    on_elected: Option<Callback>,
+    on_lost: Option<Callback>,
+}
+
+impl LeaderElection {
+    /// Campaign with leases of `lease_ms`, renewed every third of the lease,
+    /// allowing for a tenth of the lease of clock drift
+    pub fn new(lease_ms: i32) -> Self {
+        Self::with_lease_ms(lease_ms.into())
+    }
+
+    /// Campaign with leases of `lease` like `new`, rounded like the ones of
+    /// `CockLock::lock_for`
+    pub fn for_lease(lease: Duration) -> Self {
+        Self::with_lease_ms(lease_ms(lease))
+    }
+
+    fn with_lease_ms(lease_ms: i64) -> Self {
+        let lease = Duration::from_millis(lease_ms.unsigned_abs());
+        Self {
+            lease_ms,
+            interval: lease / 3,
+            drift: lease / 10,
+            on_elected: None,
+            on_lost: None,
+        }
+    }
+
+    /// Change how often the leader renews its lease and the others try to
+    /// take over
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Change how much earlier than its lease a leader considers itself
+    /// deposed when it can't renew
+    pub fn with_clock_drift(mut self, drift: Duration) -> Self {
+        self.drift = drift;
+        self
+    }
+
+    /// Run a callback whenever this instance becomes the leader
+    pub fn with_on_elected<F: FnMut() + Send + 'static>(mut self, on_elected: F) -> Self {
+        self.on_elected = Some(Box::new(on_elected));
+        self
+    }
+
+    /// Run a callback whenever this instance stops being the leader,
+    /// including when it steps down
+    pub fn with_on_lost<F: FnMut() + Send + 'static>(mut self, on_lost: F) -> Self {
+        self.on_lost = Some(Box::new(on_lost));
+        self
+    }
+}
+
+/// A running campaign for leadership, see `CockLock::campaign`
+///
+/// Dropping the elector steps down, like `step_down`.
+pub struct LeaderElector {
+    lock_name: String,
+    /// Until when this instance leads, `None` while it doesn't
+    leads_until: Arc<Mutex<Option<Instant>>>,
+    stop: Option<Sender<()>>,
+    campaign: Option<JoinHandle<()>>,
+}
+
+impl LeaderElector {
+    pub fn lock_name(&self) -> &str {
+        &self.lock_name
+    }
+
+    /// Whether this instance leads right now
+    ///
+    /// Turns false as soon as a renewal finds the lock taken, or once the
+    /// lease minus the clock drift passed since the last successful renewal.
+    pub fn is_leader(&self) -> bool {
+        let leads_until = *self
+            .leads_until
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        leads_until.is_some_and(|leads_until| Instant::now() < leads_until)
+    }
+
+    /// Stop campaigning and release the leadership lock if held, waiting for
+    /// `on_lost` to run
+    pub fn step_down(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(campaign) = self.campaign.take() {
+            let _ = campaign.join();
+        }
+    }
+}
+
+impl Drop for LeaderElector {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl CockLock {
+    /// Campaign for the lock `lock_name` in the background until the
+    /// returned `LeaderElector` steps down or is dropped
+    ///
+    /// The campaign runs on a thread with connections of its own, so every
+    /// client must have been given as a connection string. The callbacks of
+    /// `election` run on that thread too. Campaigns share the ID of the
+    /// instance that started them, so every candidate needs an instance of
+    /// its own.
+    pub fn campaign<T: ToString>(
+        &self,
+        lock_name: T,
+        mut election: LeaderElection,
+    ) -> Result<LeaderElector, CockLockError> {
+        let lock_name = lock_name.to_string();
+        if election.lease_ms == 0 {
+            return Err(CockLockError::InfiniteLease(lock_name));
+        }
+        self.require_table("elections")?;
+        let mut candidate = self.sibling()?;
+        let leads_until = Arc::new(Mutex::new(None));
+        let (stop, stopped) = channel();
+
+        let name = lock_name.clone();
+        let leadership = leads_until.clone();
+        let lease = Duration::from_millis(election.lease_ms.unsigned_abs());
+        let campaign = spawn(move || {
+            let mut leading = false;
+            loop {
+                let started_at = Instant::now();
+                let renewed = if leading {
+                    candidate
+                        .hooks
+                        .reached(|| Transition::Renewing(name.clone()));
+                    candidate
+                        .extend_many_as(candidate.id, &[&name], election.lease_ms)
+                        .map(|extended| extended[0].1)
+                } else {
+                    match candidate.lock_ms(name.clone(), election.lease_ms) {
+                        Ok(_) => Ok(true),
+                        Err(CockLockError::NotAvailable) => Ok(false),
+                        Err(err) => Err(err),
+                    }
+                };
+
+                let mut leads_until = leadership.lock().unwrap_or_else(PoisonError::into_inner);
+                let still_leading = match renewed {
+                    Ok(true) => {
+                        *leads_until = Some(started_at + lease.saturating_sub(election.drift));
+                        true
+                    }
+                    Ok(false) => {
+                        *leads_until = None;
+                        false
+                    }
+                    // Unreachable databases keep the leader until its lease
+                    // is about to run out
+                    Err(_) => leads_until.is_some_and(|leads_until| Instant::now() < leads_until),
+                };
+                drop(leads_until);
+                match (leading, still_leading) {
+                    (true, true) => candidate
+                        .hooks
+                        .reached(|| Transition::Renewed(name.clone())),
+                    (true, false) => candidate
+                        .hooks
+                        .reached(|| Transition::LeaseLost(name.clone())),
+                    _ => {}
+                }
+                let callback = match (leading, still_leading) {
+                    (false, true) => election.on_elected.as_mut(),
+                    (true, false) => election.on_lost.as_mut(),
+                    _ => None,
+                };
+                if let Some(callback) = callback {
+                    callback();
+                }
+                leading = still_leading;
+
+                match stopped.recv_timeout(election.interval) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => break,
+                }
+            }
+
+            *leadership.lock().unwrap_or_else(PoisonError::into_inner) = None;
+            if leading {
+                let _ = candidate.unlock_within(&name, DEFAULT_RELEASE_TIMEOUT);
+                if let Some(on_lost) = election.on_lost.as_mut() {
+                    on_lost();
+                }
+            }
+        });
+
+        Ok(LeaderElector {
+            lock_name,
+            leads_until,
+            stop: Some(stop),
+            campaign: Some(campaign),
+        })
+    }
+}
@@ -0,0 +1,127 @@
+//! Encrypting lock metadata before it reaches the database
+//!
+//! Metadata is stored next to the lock, where everyone who can read the lock
+//! table can read it too. With a `MetadataKey`, see
+//! `CockLockBuilder::with_metadata_key`, metadata is sealed with
+//! XChaCha20-Poly1305 under a random nonce before it is sent and opened again
+//! when locks are read, so the table only ever holds ciphertext. The lock
+//! name, with its namespace, is authenticated along with the metadata, so
+//! metadata copied over to another lock doesn't open.
+
+use std::fmt::{Debug, Formatter};
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+
+use crate::errors::CockLockError;
+
+/// The length of the random nonce in front of sealed metadata
+const NONCE_LENGTH: usize = 24;
+
+/// A 256-bit key sealing the metadata of locks
+///
+/// Every instance reading or writing the metadata needs the same key.
+#[derive(Clone)]
+pub struct MetadataKey {
+    key: Key,
+}
+
+impl MetadataKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self { key: key.into() }
+    }
+
+    /// A new random key, to be stored wherever the instances get their
+    /// secrets from
+    pub fn generate() -> Self {
+        Self {
+            key: XChaCha20Poly1305::generate_key(&mut OsRng),
+        }
+    }
+
+    /// The bytes of the key
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.key.into()
+    }
+
+    /// Seal `metadata` of the lock `lock_key`, the nonce followed by the
+    /// ciphertext
+    pub(crate) fn seal(&self, lock_key: &str, metadata: &[u8]) -> Result<Vec<u8>, CockLockError> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: metadata,
+            aad: lock_key.as_bytes(),
+        };
+        let ciphertext = XChaCha20Poly1305::new(&self.key)
+            .encrypt(&nonce, payload)
+            .map_err(|_| CockLockError::MetadataError("encryption failed".to_owned()))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(sealed)
+    }
+
+    /// Open metadata of the lock `lock_key` sealed by `seal`
+    pub(crate) fn open(&self, lock_key: &str, sealed: &[u8]) -> Result<Vec<u8>, CockLockError> {
+        let error = || {
+            CockLockError::MetadataError(
+                "the metadata was sealed with another key or for another lock".to_owned(),
+            )
+        };
+        if sealed.len() < NONCE_LENGTH {
+            return Err(error());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: lock_key.as_bytes(),
+        };
+        XChaCha20Poly1305::new(&self.key)
+            .decrypt(XNonce::from_slice(nonce), payload)
+            .map_err(|_| error())
+    }
+}
+
+impl Debug for MetadataKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MetadataKey(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MetadataKey;
+    use crate::errors::CockLockError;
+
+    #[test]
+    fn metadata_only_opens_with_its_key_and_lock() {
+        let key = MetadataKey::generate();
+        let sealed = key.seal("billing\u{1f}invoice", b"customer=42").unwrap();
+        assert!(!sealed
+            .windows(b"customer=42".len())
+            .any(|window| window == b"customer=42"));
+        assert_ne!(
+            sealed,
+            key.seal("billing\u{1f}invoice", b"customer=42").unwrap()
+        );
+
+        assert_eq!(
+            key.open("billing\u{1f}invoice", &sealed).unwrap(),
+            b"customer=42"
+        );
+        assert_eq!(
+            MetadataKey::new(key.to_bytes())
+                .open("billing\u{1f}invoice", &sealed)
+                .unwrap(),
+            b"customer=42"
+        );
+        for result in [
+            key.open("invoice", &sealed),
+            MetadataKey::generate().open("billing\u{1f}invoice", &sealed),
+            key.open("billing\u{1f}invoice", &sealed[..10]),
+        ] {
+            assert!(matches!(result, Err(CockLockError::MetadataError(_))));
+        }
+        assert_eq!(format!("{key:?}"), "MetadataKey(..)");
+    }
+}
@@ -1,14 +1,43 @@
 use std::fmt::{Display, Formatter};
 
+use crate::identity::{Identity, MAX_IDENTITY_NAME_LENGTH};
+use crate::lock::{Operation, MAX_TABLE_NAME_LENGTH, MAX_TIMEOUT_MS};
+
 #[derive(Debug)]
 pub enum CockLockError {
     CertificateFileError(std::io::Error, String),
+    #[cfg(feature = "native-tls")]
     NativeTlsError(native_tls::Error, String),
-    PostgresError(postgres::Error),
+    #[cfg(feature = "rustls")]
+    RustlsError(rustls::Error, String),
+    PostgresError(postgres::Error, Operation, String),
     NoClients,
+    TableNameTooLong(String),
+    ReadOnlyClients(Vec<String>),
+    InfiniteLease(String),
+    LeaseTooLong(String, i64),
+    TimeoutTooLong(String),
+    TimeoutTooShort(String, i32),
+    NoDefaultTimeout(String),
+    ZeroInterval(String),
+    QuotaExceeded(usize),
+    InvalidLeaseHandle(String),
+    InvalidConnectionTemplate,
+    MetadataError(String),
+    LocalFallbackError(std::io::Error, String),
+    PoolError(String),
+    Unsupported(String),
     NotAvailable,
+    AlreadyHeldByUs,
+    NotHeld(String),
+    StaleSchemaToken(String),
+    DuplicateInstance(Identity),
+    QuorumAtRisk(String),
+    InvalidIdentity(String),
+    Aborted,
     ClientNotAvailable,
-    NoClientsAvailable,
+    Reentrant,
+    NoClientsAvailable(Vec<(usize, postgres::Error)>),
 }
 
 impl Display for CockLockError {
@@ -20,26 +49,154 @@ impl Display for CockLockError {
                     "Error opening certificate file: {cert_file_path:?}: {err:?}",
                 )
             }
+            #[cfg(feature = "native-tls")]
             CockLockError::NativeTlsError(err, cert_file_path) => {
                 write!(
                     f,
                     "Error when creating a new TLS certificate: {cert_file_path:?}: {err:?}",
                 )
             }
-            CockLockError::PostgresError(err) => {
-                write!(f, "Error connecting to client: {err:?}")
+            #[cfg(feature = "rustls")]
+            CockLockError::RustlsError(err, cert_file_path) => {
+                write!(
+                    f,
+                    "Error when creating a new TLS certificate: {cert_file_path:?}: {err:?}",
+                )
+            }
+            CockLockError::PostgresError(err, operation, client) => {
+                write!(
+                    f,
+                    "Error during the {operation:?} operation on client {client:?}: {err:?}"
+                )
             }
             CockLockError::NoClients => {
                 write!(f, "No clients provided to CockLock")
             }
+            CockLockError::TableNameTooLong(table_name) => {
+                write!(
+                    f,
+                    "The table name {table_name:?} is longer than {MAX_TABLE_NAME_LENGTH} bytes",
+                )
+            }
+            CockLockError::ReadOnlyClients(clients) => {
+                write!(f, "The clients {clients:?} are read-only replicas")
+            }
+            CockLockError::InfiniteLease(lock_name) => {
+                write!(
+                    f,
+                    "The lock {lock_name:?} would never expire, infinite leases must be \
+                     taken with lock_forever() and allowed by the builder"
+                )
+            }
+            CockLockError::LeaseTooLong(lock_name, max_timeout_ms) => {
+                write!(
+                    f,
+                    "The policy of the lock {lock_name:?} allows timeouts of at most \
+                     {max_timeout_ms}ms"
+                )
+            }
+            CockLockError::TimeoutTooLong(lock_name) => {
+                write!(
+                    f,
+                    "The timeout of the lock {lock_name:?} is longer than {MAX_TIMEOUT_MS}ms"
+                )
+            }
+            CockLockError::TimeoutTooShort(lock_name, min_timeout_ms) => {
+                write!(
+                    f,
+                    "The timeout of the lock {lock_name:?} must be at least {min_timeout_ms}ms"
+                )
+            }
+            CockLockError::NoDefaultTimeout(lock_name) => {
+                write!(
+                    f,
+                    "No policy sets a default timeout for the lock {lock_name:?}"
+                )
+            }
+            CockLockError::ZeroInterval(what) => {
+                write!(f, "The interval of {what} must be longer than zero")
+            }
+            CockLockError::QuotaExceeded(max_locks) => {
+                write!(
+                    f,
+                    "The instance already holds its quota of {max_locks} locks"
+                )
+            }
+            CockLockError::InvalidLeaseHandle(handle) => {
+                write!(f, "The lease handle {handle:?} is malformed")
+            }
+            CockLockError::InvalidConnectionTemplate => {
+                write!(
+                    f,
+                    "The connection string template has no {{host}} placeholder"
+                )
+            }
+            CockLockError::MetadataError(err) => {
+                write!(f, "Failed to convert lock metadata: {err}")
+            }
+            CockLockError::LocalFallbackError(err, path) => {
+                write!(f, "Error using the local lock file: {path:?}: {err:?}")
+            }
+            CockLockError::PoolError(err) => {
+                write!(f, "Failed to check out a pooled connection: {err}")
+            }
+            CockLockError::Unsupported(feature) => {
+                write!(f, "The backend doesn't support {feature}")
+            }
             CockLockError::NotAvailable => {
                 write!(f, "The namespace is already locked")
             }
+            CockLockError::AlreadyHeldByUs => {
+                write!(f, "The lock is already held by this instance")
+            }
+            CockLockError::NotHeld(lock_name) => {
+                write!(f, "The lock {lock_name:?} is not held by this instance")
+            }
+            CockLockError::StaleSchemaToken(table_name) => {
+                write!(
+                    f,
+                    "The table {table_name:?} changed since the schema token was issued, \
+                     verify it again"
+                )
+            }
+            CockLockError::DuplicateInstance(client_id) => {
+                write!(
+                    f,
+                    "Another live process uses the client ID {client_id}, \
+                     locks are not mutually exclusive"
+                )
+            }
+            CockLockError::QuorumAtRisk(lock_name) => {
+                write!(
+                    f,
+                    "Refusing to take {lock_name:?} without an expiry while the quorum is at risk"
+                )
+            }
+            CockLockError::InvalidIdentity(name) => {
+                write!(
+                    f,
+                    "The identity {name:?} must be 1 to {MAX_IDENTITY_NAME_LENGTH} bytes \
+                     without control characters"
+                )
+            }
+            CockLockError::Aborted => {
+                write!(f, "Waiting for the lock was aborted")
+            }
             CockLockError::ClientNotAvailable => {
                 write!(f, "The client was not available")
             }
-            CockLockError::NoClientsAvailable => {
-                write!(f, "All clients could not be reached")
+            CockLockError::Reentrant => {
+                write!(
+                    f,
+                    "A shared instance was used from within one of its own operations"
+                )
+            }
+            CockLockError::NoClientsAvailable(failures) => {
+                write!(f, "Too few clients could be reached")?;
+                for (index, err) in failures {
+                    write!(f, "; client {index}: {err}")?;
+                }
+                Ok(())
             }
         }
     }
@@ -47,8 +204,22 @@ impl Display for CockLockError {
 
 impl std::error::Error for CockLockError {}
 
-impl From<postgres::Error> for CockLockError {
-    fn from(err: postgres::Error) -> Self {
-        CockLockError::PostgresError(err)
+impl CockLockError {
+    /// Wrap an error returned by the statements of an operation on the client
+    /// with the given label
+    ///
+    /// Errors raised by the reap trigger surface in whichever statement fired
+    /// it, so they are attributed to the reap instead.
+    pub(crate) fn postgres(err: postgres::Error, operation: Operation, client: &str) -> Self {
+        let is_reap = err
+            .as_db_error()
+            .and_then(|db_error| db_error.where_())
+            .is_some_and(|context| context.contains("_lock_reap_"));
+
+        if is_reap {
+            CockLockError::PostgresError(err, Operation::Reap, client.to_owned())
+        } else {
+            CockLockError::PostgresError(err, operation, client.to_owned())
+        }
     }
 }
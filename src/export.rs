@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+
+use postgres::Client;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::errors::CockLockError;
+use crate::identity::Identity;
+use crate::lock::{CockLock, Operation};
+
+/// An active lease as it was found in the lock table
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ExportedLock {
+    pub client_id: Identity,
+    pub lock_name: String,
+    /// The lease left at the time of the export, `None` for locks without an
+    /// expiry
+    pub remaining_ms: Option<i64>,
+    /// The timeout originally requested by the holder, `None` for locks taken
+    /// by versions that didn't record it
+    pub ttl_ms: Option<i64>,
+}
+
+/// All active leases of a lock table
+///
+/// With the `serde` feature enabled this can be written to a file and read
+/// back by another deployment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LockExport {
+    pub table_name: String,
+    pub locks: Vec<ExportedLock>,
+}
+
+impl CockLock {
+    /// Read all active leases from the clients
+    ///
+    /// Leases are merged across clients, the first client holding a lock name
+    /// wins. Remaining lease times are computed by the database so that the
+    /// export doesn't depend on the clock of the machine running it.
+    pub fn export_locks(&mut self) -> Result<LockExport, CockLockError> {
+        self.require_table("exports")?;
+        let mut locks: BTreeMap<String, ExportedLock> = BTreeMap::new();
+        for (client, label) in self.clients.iter_mut().zip(&self.labels) {
+            let rows = client
+                .query(&self.queries.export, &[])
+                .map_err(|err| CockLockError::postgres(err, Operation::Export, label))?;
+            for row in rows {
+                let lock = ExportedLock {
+                    client_id: row.get("client_id"),
+                    lock_name: row.get("lock_name"),
+                    remaining_ms: row.get("remaining_ms"),
+                    ttl_ms: row.get("ttl_ms"),
+                };
+                locks.entry(lock.lock_name.clone()).or_insert(lock);
+            }
+        }
+
+        Ok(LockExport {
+            table_name: self.table_name.clone(),
+            locks: locks.into_values().collect(),
+        })
+    }
+
+    /// Write previously exported leases to all clients
+    ///
+    /// Holders keep their locks with the lease they had left at export time,
+    /// so they don't have to re-acquire them after a migration. Locks that
+    /// already exist in this table are left untouched. Each client imports
+    /// either all or none of the leases.
+    pub fn import_locks(&mut self, export: &LockExport) -> Result<(), CockLockError> {
+        self.require_table("imports")?;
+        for (client, label) in self.clients.iter_mut().zip(&self.labels) {
+            let import = |client: &mut Client| {
+                let mut transaction = client.transaction()?;
+                for lock in &export.locks {
+                    transaction.execute(
+                        &self.queries.import,
+                        &[
+                            &lock.client_id,
+                            &lock.lock_name,
+                            &lock.remaining_ms,
+                            &lock.ttl_ms,
+                        ],
+                    )?;
+                }
+                transaction.commit()
+            };
+            import(client).map_err(|err| CockLockError::postgres(err, Operation::Import, label))?;
+        }
+
+        Ok(())
+    }
+}
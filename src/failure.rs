@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use postgres::error::SqlState;
+
+/// What to do when a statement fails on one of the clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureAction {
+    /// Consider the client unavailable and move on to the next one
+    Skip,
+    /// Run the statements on the same client again, aborting once the
+    /// retries are used up
+    Retry,
+    /// Return the error to the caller
+    Abort,
+}
+
+/// Decides which errors mean "skip this client", "retry" or "abort"
+///
+/// Rules for a specific SQLSTATE code take precedence over rules for its
+/// class (the first two characters of the code, e.g. `"08"` for connection
+/// exceptions). Errors that match no rule use the default action.
+///
+/// The default policy skips clients whose connection was closed or whose
+/// server is shutting down and aborts on everything else.
+#[derive(Debug, Clone)]
+pub struct FailurePolicy {
+    on_closed: FailureAction,
+    codes: HashMap<String, FailureAction>,
+    classes: HashMap<String, FailureAction>,
+    default: FailureAction,
+    max_retries: u32,
+    retry_delay: Duration,
+}
+
+impl Default for FailurePolicy {
+    fn default() -> Self {
+        Self {
+            on_closed: FailureAction::Skip,
+            codes: HashMap::new(),
+            classes: HashMap::new(),
+            default: FailureAction::Abort,
+            max_retries: 3,
+            retry_delay: Duration::from_millis(100),
+        }
+        .on_code(SqlState::ADMIN_SHUTDOWN, FailureAction::Skip)
+        .on_code(SqlState::CRASH_SHUTDOWN, FailureAction::Skip)
+    }
+}
+
+impl FailurePolicy {
+    /// Start from the default policy
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the action for errors caused by a closed connection
+    pub fn on_closed(mut self, action: FailureAction) -> Self {
+        self.on_closed = action;
+        self
+    }
+
+    /// Set the action for a specific SQLSTATE code
+    pub fn on_code(mut self, code: SqlState, action: FailureAction) -> Self {
+        self.codes.insert(code.code().to_owned(), action);
+        self
+    }
+
+    /// Set the action for a SQLSTATE class, e.g. `"40"` for transaction
+    /// rollbacks such as serialization failures
+    pub fn on_class<T: ToString>(mut self, class: T, action: FailureAction) -> Self {
+        self.classes.insert(class.to_string(), action);
+        self
+    }
+
+    /// Set the action for errors that match no other rule
+    pub fn otherwise(mut self, action: FailureAction) -> Self {
+        self.default = action;
+        self
+    }
+
+    /// Change how often the statements are retried and how long to wait
+    /// between attempts
+    pub fn with_retries(mut self, max_retries: u32, retry_delay: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// The action to take for an error
+    pub fn action(&self, err: &postgres::Error) -> FailureAction {
+        if err.is_closed() {
+            return self.on_closed;
+        }
+
+        match err.code() {
+            Some(code) => self
+                .codes
+                .get(code.code())
+                .or_else(|| {
+                    code.code()
+                        .get(..2)
+                        .and_then(|class| self.classes.get(class))
+                })
+                .copied()
+                .unwrap_or(self.default),
+            None => self.default,
+        }
+    }
+
+    pub(crate) fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    pub(crate) fn retry_delay(&self) -> Duration {
+        self.retry_delay
+    }
+}
@@ -0,0 +1,103 @@
+use crate::errors::CockLockError;
+use crate::identity::Identity;
+use crate::lock::{CockLock, Operation};
+
+/// How often one instance acquired a lock
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AcquisitionStats {
+    /// The ID of the instance
+    pub holder: Identity,
+    pub acquisitions: i64,
+    /// The time since the instance last acquired the lock
+    pub since_last_ms: i64,
+}
+
+/// Who won the acquisitions of a lock, most frequent winner first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FairnessReport {
+    pub lock_name: String,
+    pub holders: Vec<AcquisitionStats>,
+}
+
+impl FairnessReport {
+    pub fn total_acquisitions(&self) -> i64 {
+        self.holders.iter().map(|stats| stats.acquisitions).sum()
+    }
+
+    /// The share of acquisitions won by the most frequent winner, between 0
+    /// and 1
+    ///
+    /// A share close to 1 on a lock that several instances compete for means
+    /// one of them is starving the others.
+    pub fn top_share(&self) -> f64 {
+        match (self.holders.first(), self.total_acquisitions()) {
+            (Some(top), total) if total > 0 => top.acquisitions as f64 / total as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+impl CockLock {
+    /// Read how often each instance acquired a lock since the statistics were
+    /// enabled or last reset
+    ///
+    /// Only acquisitions count, extending a held lock doesn't. Requires
+    /// `CockLockBuilder::with_fairness_stats`. The statistics are read from the
+    /// first reachable client.
+    pub fn fairness_stats<T: ToString>(
+        &mut self,
+        lock_name: T,
+    ) -> Result<FairnessReport, CockLockError> {
+        self.require_table("fairness statistics")?;
+        let lock_name = lock_name.to_string();
+        let (_, result) = self.on_available_client(Operation::Stats, |client, queries, _| {
+            client.query(&queries.stats, &[&lock_name])
+        });
+
+        let holders = result?
+            .iter()
+            .map(|row| AcquisitionStats {
+                holder: row.get("client_id"),
+                acquisitions: row.get("acquisitions"),
+                since_last_ms: row.get("since_last_ms"),
+            })
+            .collect();
+        Ok(FairnessReport { lock_name, holders })
+    }
+
+    /// Forget the statistics of a lock on all clients, e.g. to look at a
+    /// recent time window only
+    pub fn reset_fairness_stats<T: ToString>(&mut self, lock_name: T) -> Result<(), CockLockError> {
+        self.require_table("fairness statistics")?;
+        let lock_name = lock_name.to_string();
+        for (client, label) in self.clients.iter_mut().zip(&self.labels) {
+            client
+                .execute(&self.queries.reset_stats, &[&lock_name])
+                .map_err(|err| CockLockError::postgres(err, Operation::Stats, label))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::identity::Identity;
+
+    use super::{AcquisitionStats, FairnessReport};
+
+    #[test]
+    fn top_share_is_the_share_of_the_most_frequent_winner() {
+        let stats = |acquisitions| AcquisitionStats {
+            holder: Identity::random(),
+            acquisitions,
+            since_last_ms: 0,
+        };
+        let report = FairnessReport {
+            lock_name: "leader".to_owned(),
+            holders: vec![stats(9), stats(1)],
+        };
+        assert_eq!(report.total_acquisitions(), 10);
+        assert!((report.top_share() - 0.9).abs() < f64::EPSILON);
+        assert_eq!(FairnessReport::default().top_share(), 0.0);
+    }
+}
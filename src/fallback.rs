@@ -0,0 +1,364 @@
+//! Local locks for when no database can be reached
+//!
+//! A single-instance deployment would otherwise stop working for as long as
+//! its database is down. With a `LocalFallback` configured, `CockLock::lock`
+//! takes the lock locally instead and flags it with `FencingToken::is_local`.
+//! Local locks only exclude the instances using the same fallback: the other
+//! instances of the process, or for a directory every process on the host
+//! that points to it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use uuid::Uuid;
+
+use crate::errors::CockLockError;
+use crate::fencing::FencingToken;
+use crate::lock::CockLock;
+
+/// The locks of the `LocalFallback::Process` fallback, also held while the
+/// files of a directory are read and written
+static PROCESS_LOCKS: Mutex<BTreeMap<String, LocalLease>> = Mutex::new(BTreeMap::new());
+
+/// How long a lock file that can't be read as a lease keeps its lock, e.g.
+/// one left behind by a process that crashed while writing it
+const UNREADABLE_LEASE_GRACE: Duration = Duration::from_secs(10);
+
+/// Where locks are taken while no client can be reached, see
+/// `CockLockBuilder::with_local_fallback`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocalFallback {
+    /// In memory, shared by the instances of this process
+    Process,
+    /// In a file per lock in a directory, shared by every process on the
+    /// host that uses it
+    ///
+    /// New files are created atomically, but taking over an expired lock is
+    /// not atomic across processes. A file that can't be read as a lease
+    /// holds its lock for 10 seconds after it was last written, and is taken
+    /// over like an expired lease afterwards.
+    Directory(PathBuf),
+}
+
+/// A lease of the fallback; released leases are kept so that their lock's
+/// next token is still higher
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LocalLease {
+    holder: Uuid,
+    /// Milliseconds since the Unix epoch, `None` for leases without expiry
+    expires_at_ms: Option<u64>,
+    token: i64,
+}
+
+impl LocalLease {
+    fn is_active(&self, now_ms: u64) -> bool {
+        self.expires_at_ms
+            .is_none_or(|expires_at_ms| expires_at_ms > now_ms)
+    }
+
+    fn to_line(self) -> String {
+        let expires_at_ms = self
+            .expires_at_ms
+            .map_or_else(|| "-".to_owned(), |expires_at_ms| expires_at_ms.to_string());
+        format!("{} {} {}\n", self.holder, expires_at_ms, self.token)
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let holder = parts.next()?.parse().ok()?;
+        let expires_at_ms = match parts.next()? {
+            "-" => None,
+            expires_at_ms => Some(expires_at_ms.parse().ok()?),
+        };
+        let token = parts.next()?.parse().ok()?;
+        Some(Self {
+            holder,
+            expires_at_ms,
+            token,
+        })
+    }
+}
+
+impl LocalFallback {
+    /// Take or renew a lock for `holder`, returning the token of its lease
+    fn take(
+        &self,
+        table_name: &str,
+        lock_name: &str,
+        holder: Uuid,
+        timeout_ms: i64,
+    ) -> Result<i64, CockLockError> {
+        let mut process_locks = PROCESS_LOCKS.lock().unwrap_or_else(PoisonError::into_inner);
+        let key = format!("{table_name}/{lock_name}");
+        let now_ms = unix_ms();
+        let existing = match self {
+            LocalFallback::Process => process_locks.get(&key).copied(),
+            LocalFallback::Directory(directory) => {
+                read_lease(&lease_path(directory, table_name, lock_name))?
+            }
+        };
+
+        let token = match existing {
+            Some(lease) if lease.is_active(now_ms) && lease.holder != holder => {
+                return Err(CockLockError::NotAvailable)
+            }
+            Some(lease) if lease.is_active(now_ms) => lease.token,
+            Some(lease) => lease.token + 1,
+            None => 1,
+        };
+        let lease = LocalLease {
+            holder,
+            expires_at_ms: (timeout_ms != 0)
+                .then(|| now_ms.saturating_add(timeout_ms.unsigned_abs())),
+            token,
+        };
+        match self {
+            LocalFallback::Process => {
+                process_locks.insert(key, lease);
+            }
+            LocalFallback::Directory(directory) => write_lease(
+                &lease_path(directory, table_name, lock_name),
+                lease,
+                existing.is_none(),
+            )?,
+        }
+
+        Ok(token)
+    }
+
+    /// Release a lock of `holder`, returns whether it held the lock
+    fn release(
+        &self,
+        table_name: &str,
+        lock_name: &str,
+        holder: Uuid,
+    ) -> Result<bool, CockLockError> {
+        let mut process_locks = PROCESS_LOCKS.lock().unwrap_or_else(PoisonError::into_inner);
+        let key = format!("{table_name}/{lock_name}");
+        let now_ms = unix_ms();
+        let existing = match self {
+            LocalFallback::Process => process_locks.get(&key).copied(),
+            LocalFallback::Directory(directory) => {
+                read_lease(&lease_path(directory, table_name, lock_name))?
+            }
+        };
+
+        let lease = match existing {
+            Some(lease) if lease.holder == holder && lease.is_active(now_ms) => LocalLease {
+                expires_at_ms: Some(now_ms),
+                ..lease
+            },
+            _ => return Ok(false),
+        };
+        match self {
+            LocalFallback::Process => {
+                process_locks.insert(key, lease);
+            }
+            LocalFallback::Directory(directory) => {
+                write_lease(&lease_path(directory, table_name, lock_name), lease, false)?
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// The file of a lock, named after the hex encoded lock name since lock
+/// names may contain slashes
+fn lease_path(directory: &Path, table_name: &str, lock_name: &str) -> PathBuf {
+    let encoded: String = lock_name
+        .bytes()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    directory.join(format!("{table_name}-{encoded}.lock"))
+}
+
+/// Read the lease of a lock file
+///
+/// A file that can't be parsed counts as a lease of nobody that runs out
+/// `UNREADABLE_LEASE_GRACE` after the file was last modified.
+fn read_lease(path: &Path) -> Result<Option<LocalLease>, CockLockError> {
+    let io_error = |err| CockLockError::LocalFallbackError(err, path.display().to_string());
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(io_error(err)),
+    };
+    if let Some(lease) = LocalLease::parse(&contents) {
+        return Ok(Some(lease));
+    }
+
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(io_error)?;
+    Ok(Some(LocalLease {
+        holder: Uuid::nil(),
+        expires_at_ms: Some(unix_ms_of(modified + UNREADABLE_LEASE_GRACE)),
+        token: 0,
+    }))
+}
+
+/// Write a lease, creating its file only if no other process did meanwhile
+/// when `create` is set, and replacing it otherwise
+///
+/// The lease is written to a file of its own first and then linked or
+/// renamed into place, so that no process ever reads it half written.
+fn write_lease(path: &Path, lease: LocalLease, create: bool) -> Result<(), CockLockError> {
+    let io_error = |err| CockLockError::LocalFallbackError(err, path.display().to_string());
+    let temporary = path.with_extension(format!("{}.tmp", lease.holder));
+    fs::write(&temporary, lease.to_line()).map_err(io_error)?;
+    if !create {
+        return fs::rename(&temporary, path).map_err(io_error);
+    }
+
+    // Unlike a rename, a hard link doesn't replace a file created meanwhile
+    let linked = fs::hard_link(&temporary, path);
+    let _ = fs::remove_file(&temporary);
+    match linked {
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => Err(CockLockError::NotAvailable),
+        linked => linked.map_err(io_error),
+    }
+}
+
+fn unix_ms() -> u64 {
+    unix_ms_of(SystemTime::now())
+}
+
+fn unix_ms_of(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |elapsed| {
+        u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX)
+    })
+}
+
+/// Whether an error means that no database could be reached, rather than
+/// one of them refusing the statements
+pub(crate) fn is_unreachable(err: &CockLockError) -> bool {
+    match err {
+        CockLockError::ClientNotAvailable => true,
+        CockLockError::NoClientsAvailable(failures) => {
+            failures.iter().all(|(_, err)| err.as_db_error().is_none())
+        }
+        CockLockError::PostgresError(err, _, _) => err.as_db_error().is_none(),
+        _ => false,
+    }
+}
+
+impl CockLock {
+    /// Take a lock with the local fallback after the databases failed with
+    /// `cause`, which is returned if there is no fallback
+    pub(crate) fn lock_locally(
+        &mut self,
+        lock_name: &str,
+        timeout_ms: i64,
+        cause: CockLockError,
+    ) -> Result<FencingToken, CockLockError> {
+        let fallback = match &self.local_fallback {
+            Some(fallback) => fallback,
+            None => return Err(cause),
+        };
+        log::warn!(
+            target: "cocklock::fallback",
+            "Taking {lock_name} locally, no database could be reached: {cause}"
+        );
+        let token = fallback.take(
+            &self.table().key(),
+            &self.table().namespaced(lock_name),
+            self.id.as_uuid(),
+            timeout_ms,
+        )?;
+        self.local_locks.insert(lock_name.to_owned());
+        Ok(FencingToken::local(token))
+    }
+
+    /// Release a lock taken with the local fallback, if it was
+    pub(crate) fn unlock_locally(&mut self, lock_name: &str) -> Result<bool, CockLockError> {
+        if !self.local_locks.remove(lock_name) {
+            return Ok(false);
+        }
+        match &self.local_fallback {
+            Some(fallback) => fallback.release(
+                &self.table().key(),
+                &self.table().namespaced(lock_name),
+                self.id.as_uuid(),
+            ),
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{lease_path, LocalFallback, LocalLease, UNREADABLE_LEASE_GRACE};
+    use crate::errors::CockLockError;
+
+    #[test]
+    fn local_locks_exclude_other_holders() {
+        let table_name = Uuid::new_v4().to_string();
+        let directory = std::env::temp_dir().join(&table_name);
+        std::fs::create_dir_all(&directory).unwrap();
+        let fallbacks = [
+            LocalFallback::Process,
+            LocalFallback::Directory(directory.clone()),
+        ];
+        for fallback in fallbacks {
+            let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+            let first = fallback.take(&table_name, "a/b", alice, 10_000).unwrap();
+            assert_eq!(
+                fallback.take(&table_name, "a/b", alice, 10_000).unwrap(),
+                first
+            );
+            assert!(matches!(
+                fallback.take(&table_name, "a/b", bob, 10_000),
+                Err(CockLockError::NotAvailable)
+            ));
+            assert!(!fallback.release(&table_name, "a/b", bob).unwrap());
+            assert!(fallback.release(&table_name, "a/b", alice).unwrap());
+            assert!(fallback.take(&table_name, "a/b", bob, 10_000).unwrap() > first);
+        }
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn unreadable_lock_files_run_out() {
+        let table_name = Uuid::new_v4().to_string();
+        let directory = std::env::temp_dir().join(&table_name);
+        std::fs::create_dir_all(&directory).unwrap();
+        let fallback = LocalFallback::Directory(directory.clone());
+        let path = lease_path(&directory, &table_name, "crashed");
+        let file = std::fs::File::create(&path).unwrap();
+
+        let alice = Uuid::new_v4();
+        assert!(matches!(
+            fallback.take(&table_name, "crashed", alice, 10_000),
+            Err(CockLockError::NotAvailable)
+        ));
+        assert!(!fallback.release(&table_name, "crashed", alice).unwrap());
+
+        let written = std::time::SystemTime::now() - UNREADABLE_LEASE_GRACE * 2;
+        file.set_modified(written).unwrap();
+        assert_eq!(
+            fallback
+                .take(&table_name, "crashed", alice, 10_000)
+                .unwrap(),
+            1
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn leases_survive_a_round_trip() {
+        let lease = LocalLease {
+            holder: Uuid::new_v4(),
+            expires_at_ms: None,
+            token: 7,
+        };
+        assert_eq!(LocalLease::parse(&lease.to_line()), Some(lease));
+        assert_eq!(LocalLease::parse("garbage"), None);
+    }
+}
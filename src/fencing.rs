@@ -0,0 +1,71 @@
+//! Fencing tokens, handed out with every lock
+//!
+//! A holder whose lease ran out without noticing, say after a long GC pause,
+//! still believes it holds the lock. Passing the token of the lease along with
+//! every write lets the storage it writes to reject writes carrying a lower
+//! token than the highest one it has seen.
+
+use std::fmt::{self, Display};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A number that grows with every lease handed out by a lock table
+///
+/// Tokens come from a sequence shared by all locks of the table, so a lease
+/// always has a higher token than the leases of the same lock before it.
+/// That only holds within one database: with `Quorum::Majority` or
+/// `Quorum::All` over independent databases, each of them has a sequence of
+/// its own and the token comes from whichever granted the lease first, so a
+/// later lease may get a lower token. Fence writes with tokens from a single
+/// database, or from replicas of one.
+/// Extending or relocking a lock that is still held keeps its token; taking
+/// it over after it expired or was released draws a new one. Advisory locks
+/// have no sequence, their tokens are all 0 and fence nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FencingToken {
+    value: i64,
+    local: bool,
+}
+
+impl FencingToken {
+    pub fn new(value: i64) -> Self {
+        Self {
+            value,
+            local: false,
+        }
+    }
+
+    /// A token of a lock taken by the local fallback, see
+    /// `CockLockBuilder::with_local_fallback`
+    pub fn local(value: i64) -> Self {
+        Self { value, local: true }
+    }
+
+    pub fn value(self) -> i64 {
+        self.value
+    }
+
+    /// Whether the lock was taken by the local fallback instead of the
+    /// database
+    ///
+    /// Such a lock only excludes instances using the same fallback, and its
+    /// token only grows with the other local leases of the lock, so it must
+    /// not be compared with tokens from the database.
+    pub fn is_local(self) -> bool {
+        self.local
+    }
+}
+
+impl Display for FencingToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+impl From<FencingToken> for i64 {
+    fn from(token: FencingToken) -> Self {
+        token.value
+    }
+}
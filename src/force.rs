@@ -0,0 +1,173 @@
+//! Operator tools for locks whose holder is gone
+//!
+//! A worker that crashed while holding a lock without a timeout keeps it
+//! forever, and one with a long timeout keeps it until the lease runs out.
+//! These methods take such locks away from their holder. The holder is not
+//! told; if it is still alive it finds out the next time it extends or
+//! releases the lock, so only use them on holders known to be gone.
+
+use std::time::{Duration, Instant};
+
+use postgres::types::ToSql;
+use postgres::Client;
+
+use crate::cutover::Cutover;
+use crate::errors::CockLockError;
+use crate::fencing::FencingToken;
+use crate::identity::Identity;
+use crate::lock::{lease_ms, CockLock, CockLockQueries, Operation};
+use crate::quorum::Quorum;
+
+impl CockLock {
+    /// Release a lock whoever holds it
+    ///
+    /// Every client is asked even if some of them fail, the first failure is
+    /// returned once all of them were. Returns the holder the lock was taken
+    /// from, `None` if nobody held it.
+    pub fn force_unlock<T: ToString>(
+        &mut self,
+        lock_name: T,
+    ) -> Result<Option<Identity>, CockLockError> {
+        self.require_table("forced unlocks")?;
+        let started_at = Instant::now();
+        let lock_name = lock_name.to_string();
+        let params: &[&(dyn ToSql + Sync)] = &[&lock_name];
+        let results =
+            self.on_every_client(Operation::ForceUnlock, &mut |client, queries, cutover| {
+                let mut transaction = client.transaction()?;
+                let mut rows = vec![];
+                if let Some(cutover) = cutover {
+                    rows.extend(transaction.query(&cutover.queries.force_unlock, params)?);
+                }
+                rows.extend(transaction.query(&queries.force_unlock, params)?);
+                transaction.commit()?;
+                Ok(rows)
+            });
+
+        let mut holder = None;
+        let mut first_error = None;
+        for (_, result) in results {
+            match result {
+                Ok(rows) => {
+                    holder = holder.or_else(|| rows.first().map(|row| row.get("client_id")));
+                }
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        let result = match first_error {
+            Some(err) => Err(err),
+            None => Ok(holder),
+        };
+        if result.is_ok() {
+            self.held.released(&lock_name);
+        }
+        self.journal.record(
+            Operation::ForceUnlock,
+            &lock_name,
+            None,
+            started_at,
+            &result,
+        );
+        result
+    }
+
+    /// Take a lock over whoever holds it, with a new lease of `timeout_ms`
+    ///
+    /// The lock is released and taken again in one transaction, so nobody
+    /// else can take it in between, and it gets a new fencing token that
+    /// fences off the previous holder. Fails with `NotAvailable` if another
+    /// instance is registered as next in line for the lock, or the quota of
+    /// the instance is exhausted.
+    pub fn steal<T: ToString>(
+        &mut self,
+        lock_name: T,
+        timeout_ms: i32,
+    ) -> Result<FencingToken, CockLockError> {
+        self.steal_ms(lock_name.to_string(), timeout_ms.into())
+    }
+
+    /// Take a lock over whoever holds it like `steal`, with a timeout like
+    /// `lock_for`
+    pub fn steal_for<T: ToString>(
+        &mut self,
+        lock_name: T,
+        timeout: Duration,
+    ) -> Result<FencingToken, CockLockError> {
+        self.steal_ms(lock_name.to_string(), lease_ms(timeout))
+    }
+
+    fn steal_ms(
+        &mut self,
+        lock_name: String,
+        timeout_ms: i64,
+    ) -> Result<FencingToken, CockLockError> {
+        self.require_table("stealing locks")?;
+        let started_at = Instant::now();
+        let renewed_at = self.clock.now();
+        self.check_timeout(&lock_name, timeout_ms)?;
+        let id = self.id;
+        let no_resource: Option<&str> = None;
+        let no_metadata: Option<&[u8]> = None;
+        let max_locks = self
+            .quota
+            .filter(|quota| quota.in_database)
+            .map(|quota| i64::try_from(quota.max_locks).unwrap_or(i64::MAX));
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![
+            &id,
+            &lock_name,
+            &timeout_ms,
+            &no_resource,
+            &no_resource,
+            &no_metadata,
+        ];
+        if let Some(max_locks) = &max_locks {
+            params.push(max_locks);
+        }
+        let params = &params[..];
+        let statements =
+            |client: &mut Client, queries: &CockLockQueries, cutover: Option<&Cutover>| {
+                let mut transaction = client.transaction()?;
+                let mut token = None;
+                if let Some(cutover) = cutover {
+                    // The old table never enforces the quota
+                    transaction.execute(&cutover.queries.force_unlock, &params[1..2])?;
+                    match transaction.query_opt(&cutover.queries.lock, &params[..6])? {
+                        Some(row) => token = Some(row.get::<_, i64>(0)),
+                        None => return Ok(None),
+                    }
+                }
+                transaction.execute(&queries.force_unlock, &params[1..2])?;
+                let row = match transaction.query_opt(&queries.lock, params)? {
+                    Some(row) => row,
+                    None => return Ok(None),
+                };
+                transaction.commit()?;
+                Ok(Some(token.unwrap_or(0).max(row.get(0))))
+            };
+        let (_, result) = match self.quorum {
+            Quorum::FirstAvailable => self.on_available_client(Operation::ForceUnlock, statements),
+            Quorum::Majority | Quorum::All => {
+                self.on_quorum(Operation::ForceUnlock, statements, Option::is_some)
+            }
+        };
+
+        let result = result.and_then(|token| {
+            token
+                .map(FencingToken::new)
+                .ok_or(CockLockError::NotAvailable)
+        });
+        if result.is_ok() {
+            self.held.renewed(&lock_name, timeout_ms, renewed_at);
+        }
+        self.journal.record(
+            Operation::ForceUnlock,
+            &lock_name,
+            None,
+            started_at,
+            &result,
+        );
+        result
+    }
+}
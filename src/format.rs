@@ -0,0 +1,85 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::errors::CockLockError;
+
+/// How typed metadata values are turned into the bytes stored with a lock
+///
+/// JSON is always available with the `serde` feature and is the default since
+/// it stays readable in the table. CBOR and MessagePack are more compact and
+/// need the `cbor` and `msgpack` features.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MetadataFormat {
+    #[default]
+    Json,
+    #[cfg(feature = "cbor")]
+    Cbor,
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+}
+
+impl MetadataFormat {
+    pub fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, CockLockError> {
+        let error = |err: &dyn std::fmt::Display| CockLockError::MetadataError(err.to_string());
+        match self {
+            MetadataFormat::Json => serde_json::to_vec(value).map_err(|err| error(&err)),
+            #[cfg(feature = "cbor")]
+            MetadataFormat::Cbor => {
+                let mut bytes = vec![];
+                ciborium::ser::into_writer(value, &mut bytes).map_err(|err| error(&err))?;
+                Ok(bytes)
+            }
+            #[cfg(feature = "msgpack")]
+            MetadataFormat::MessagePack => rmp_serde::to_vec(value).map_err(|err| error(&err)),
+        }
+    }
+
+    pub fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, CockLockError> {
+        let error = |err: &dyn std::fmt::Display| CockLockError::MetadataError(err.to_string());
+        match self {
+            MetadataFormat::Json => serde_json::from_slice(bytes).map_err(|err| error(&err)),
+            #[cfg(feature = "cbor")]
+            MetadataFormat::Cbor => ciborium::de::from_reader(bytes).map_err(|err| error(&err)),
+            #[cfg(feature = "msgpack")]
+            MetadataFormat::MessagePack => rmp_serde::from_slice(bytes).map_err(|err| error(&err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::MetadataFormat;
+    use crate::errors::CockLockError;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Job {
+        host: String,
+        item: u32,
+    }
+
+    #[test]
+    fn metadata_round_trips_through_every_format() {
+        let job = Job {
+            host: "worker-1".to_owned(),
+            item: 42,
+        };
+        let formats = [
+            MetadataFormat::Json,
+            #[cfg(feature = "cbor")]
+            MetadataFormat::Cbor,
+            #[cfg(feature = "msgpack")]
+            MetadataFormat::MessagePack,
+        ];
+        for format in formats {
+            let bytes = format.serialize(&job).unwrap();
+            assert_eq!(format.deserialize::<Job>(&bytes).unwrap(), job);
+        }
+
+        assert!(matches!(
+            MetadataFormat::Json.deserialize::<Job>(b"not json"),
+            Err(CockLockError::MetadataError(_))
+        ));
+    }
+}
@@ -0,0 +1,195 @@
+use std::time::Duration;
+
+use crate::errors::CockLockError;
+use crate::fencing::FencingToken;
+use crate::hooks::Transition;
+use crate::lock::CockLock;
+
+/// How long the statements releasing a lock on drop may run, see
+/// `CockLock::with_statement_timeout`
+pub static DEFAULT_RELEASE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A held lock that is released when the guard is dropped, see
+/// `CockLock::lock_guard`
+///
+/// Releasing on drop is best effort: its statements may run for
+/// `DEFAULT_RELEASE_TIMEOUT` (see `set_release_timeout`), so that shutting
+/// down can't hang on a database that is stuck. A host that stopped
+/// answering altogether is waited on for as long as the connection allows,
+/// set `connect_timeout`, `tcp_user_timeout` or `keepalives_idle` in the
+/// connection string to bound that as well. A failed release is logged as a warning with the target
+/// `cocklock::guard`, shows up in `CockLock::recent_operations` and reaches
+/// the `TransitionHook` as `Transition::ReleaseFailed`; the lock then runs
+/// out with its timeout. Use `release` to handle the error instead.
+pub struct LockGuard<'a> {
+    cock_lock: &'a mut CockLock,
+    lock_name: String,
+    fencing_token: FencingToken,
+    release_timeout: Duration,
+    released: bool,
+}
+
+impl LockGuard<'_> {
+    pub fn lock_name(&self) -> &str {
+        &self.lock_name
+    }
+
+    /// The fencing token the lock was taken with
+    pub fn fencing_token(&self) -> FencingToken {
+        self.fencing_token
+    }
+
+    /// Change how long the statements releasing on drop may run
+    pub fn set_release_timeout(&mut self, timeout: Duration) {
+        self.release_timeout = timeout;
+    }
+
+    /// Reset the timeout of the lock, see `CockLock::extend_many`
+    pub fn extend(&mut self, timeout_ms: i32) -> Result<(), CockLockError> {
+        match self.cock_lock.extend_many(&[&self.lock_name], timeout_ms)?[..] {
+            [(_, true)] => Ok(()),
+            _ => Err(CockLockError::NotAvailable),
+        }
+    }
+
+    /// Reset the timeout of the lock like `extend`, to a lease of `timeout`
+    /// that is rounded like the one of `CockLock::lock_for`
+    pub fn extend_to(&mut self, timeout: Duration) -> Result<(), CockLockError> {
+        match self.cock_lock.extend_many_to(&[&self.lock_name], timeout)?[..] {
+            [(_, true)] => Ok(()),
+            _ => Err(CockLockError::NotAvailable),
+        }
+    }
+
+    /// Reset the timeout of the lock to the one it was taken with
+    pub fn refresh(&mut self) -> Result<(), CockLockError> {
+        self.cock_lock.refresh(&self.lock_name)
+    }
+
+    /// Whether someone asked for the lock to be released early, see
+    /// `CockLock::request_yield`
+    pub fn yield_requested(&mut self) -> Result<bool, CockLockError> {
+        self.cock_lock.yield_requested(&self.lock_name)
+    }
+
+    /// Release the lock now, reporting whether it worked
+    pub fn release(mut self) -> Result<(), CockLockError> {
+        self.released = true;
+        self.cock_lock.unlock(&self.lock_name)
+    }
+
+    /// Release the lock now with the statement timeout of a release on drop,
+    /// reporting whether it worked instead of logging it
+    pub(crate) fn release_within_timeout(mut self) -> Result<(), CockLockError> {
+        self.released = true;
+        self.cock_lock
+            .unlock_within(&self.lock_name, self.release_timeout)
+    }
+
+    /// The instance holding the lock
+    pub(crate) fn cock_lock(&mut self) -> &mut CockLock {
+        self.cock_lock
+    }
+}
+
+impl Drop for LockGuard<'_> {
+    fn drop(&mut self) {
+        if !self.released {
+            if let Err(err) = self
+                .cock_lock
+                .unlock_within(&self.lock_name, self.release_timeout)
+            {
+                self.cock_lock
+                    .release_failed("cocklock::guard", &self.lock_name, &err);
+            }
+        }
+    }
+}
+
+impl CockLock {
+    /// Try to create a lock like `lock`, returning a guard that releases it
+    /// when dropped
+    ///
+    /// Early returns and panics release the lock instead of leaving it held
+    /// until it expires.
+    pub fn lock_guard<T: ToString>(
+        &mut self,
+        lock_name: T,
+        timeout_ms: i32,
+    ) -> Result<LockGuard<'_>, CockLockError> {
+        let lock_name = lock_name.to_string();
+        let fencing_token = self.lock(&lock_name, timeout_ms)?;
+        Ok(LockGuard {
+            cock_lock: self,
+            lock_name,
+            fencing_token,
+            release_timeout: DEFAULT_RELEASE_TIMEOUT,
+            released: false,
+        })
+    }
+
+    /// Take a lock like `lock`, run `f` while holding it and release the lock
+    /// afterwards, returning what `f` returned
+    ///
+    /// The lock is released like a `LockGuard` going out of scope, also when
+    /// `f` panics, in which case the panic carries on once the lock was
+    /// released. Only taking the lock can fail: `f` already ran by the time
+    /// it is released, so a failed release only shows up in
+    /// `recent_operations` and the lock runs out with its timeout.
+    pub fn with_lock<T, F, R>(
+        &mut self,
+        lock_name: T,
+        timeout_ms: i32,
+        f: F,
+    ) -> Result<R, CockLockError>
+    where
+        T: ToString,
+        F: FnOnce() -> R,
+    {
+        let _guard = self.lock_guard(lock_name, timeout_ms)?;
+        Ok(f())
+    }
+
+    /// Unlock with statements that may run for at most `timeout` on each
+    /// client
+    pub(crate) fn unlock_within(
+        &mut self,
+        lock_name: &str,
+        timeout: Duration,
+    ) -> Result<(), CockLockError> {
+        self.with_statement_timeout(timeout, |cock_lock| cock_lock.unlock(lock_name))
+    }
+
+    /// Run `statements` with the `statement_timeout` of every client set to
+    /// `timeout`
+    ///
+    /// The database ends statements that run longer itself, without a cancel
+    /// request that would need a connection of its own. Clients whose
+    /// connection is closed already are left alone, their statements fail
+    /// right away.
+    pub(crate) fn with_statement_timeout<T, F>(&mut self, timeout: Duration, statements: F) -> T
+    where
+        F: FnOnce(&mut Self) -> T,
+    {
+        let timeout_ms = timeout.as_millis().clamp(1, i32::MAX as u128);
+        for client in self.clients.iter_mut().filter(|client| !client.is_closed()) {
+            let _ = client.batch_execute(&format!("set statement_timeout = {timeout_ms};"));
+        }
+        let result = statements(self);
+        for client in self.clients.iter_mut().filter(|client| !client.is_closed()) {
+            let _ = client.batch_execute("reset statement_timeout;");
+        }
+        result
+    }
+
+    /// Report that releasing a lock on drop failed, it runs out with its
+    /// timeout
+    pub(crate) fn release_failed(&self, target: &str, lock_name: &str, err: &CockLockError) {
+        log::warn!(
+            target: target,
+            "Could not release {lock_name} on drop, it is held until it expires: {err}"
+        );
+        self.hooks
+            .reached(|| Transition::ReleaseFailed(lock_name.to_owned()));
+    }
+}
@@ -0,0 +1,184 @@
+//! Probing whether a client answers
+//!
+//! Lock statements may be allowed to take their time, but telling whether a
+//! client is reachable shouldn't wait that long. A probe sends `select 1`
+//! and cancels it once the probe timeout passed, whatever the other
+//! statements are allowed, see `CockLockBuilder::with_probe_timeout`.
+//! `CockLock::health` reports the probes of every client, to let a service
+//! that can't reach its locks fail its readiness check.
+
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+use postgres::{Client, NoTls};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::connection::Connector;
+use crate::lock::CockLock;
+
+/// How long a probe may take by default before it counts as failed
+pub static DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+static PROBE_QUERY: &str = "select 1";
+
+static TABLE_EXISTS_QUERY: &str = "
+select exists (
+    select 1
+    from information_schema.tables
+    where table_name = $1 and table_schema = coalesce($2, current_schema())
+);
+";
+
+/// Whether a client answered a probe, see `CockLock::health`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ClientHealth {
+    pub label: String,
+    /// How long the probe took to answer, `None` if it failed
+    pub latency: Option<Duration>,
+    /// Whether the lock table exists, only checked by
+    /// `CockLock::health_with_tables`
+    pub table_exists: Option<bool>,
+    /// The error the probe failed with
+    pub error: Option<String>,
+}
+
+impl ClientHealth {
+    /// Whether the client answered, and has a lock table if that was checked
+    pub fn is_healthy(&self) -> bool {
+        self.error.is_none() && self.table_exists != Some(false)
+    }
+}
+
+/// Send `select 1` to `client`, cancelling it after `timeout`
+///
+/// Returns how long the answer took. `connector` is the one that made the
+/// client, if any, to cancel the probe through the same kind of connection.
+pub(crate) fn probe(
+    client: &mut Client,
+    connector: Option<&Connector>,
+    timeout: Duration,
+) -> Result<Duration, postgres::Error> {
+    let started_at = Instant::now();
+    within(client, connector, timeout, |client| {
+        client.simple_query(PROBE_QUERY)
+    })?;
+    Ok(started_at.elapsed())
+}
+
+/// Run `statements` on `client`, cancelling them after `timeout`
+fn within<T, F>(
+    client: &mut Client,
+    connector: Option<&Connector>,
+    timeout: Duration,
+    statements: F,
+) -> Result<T, postgres::Error>
+where
+    F: FnOnce(&mut Client) -> Result<T, postgres::Error>,
+{
+    let token = client.cancel_token();
+    let connector = connector.cloned();
+    let (done, finished) = channel::<()>();
+    spawn(move || {
+        if let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(timeout) {
+            let _ = match connector {
+                Some(connector) => connector.cancel(&token),
+                None => token.cancel_query(NoTls),
+            };
+        }
+    });
+
+    let result = statements(client);
+    let _ = done.send(());
+    result
+}
+
+impl CockLock {
+    /// Probe every client with `select 1`, e.g. for a readiness check
+    ///
+    /// Closed clients whose reconnect back-off passed are reconnected first,
+    /// those still closed count as unhealthy. Each probe is cancelled after
+    /// the probe timeout, see `CockLockBuilder::with_probe_timeout`, so this
+    /// returns quickly even if a client hangs.
+    ///
+    /// Clients given as connection strings or configs are probed over a
+    /// connection of their own, whose network timeouts are the probe timeout
+    /// too, since a host that stopped answering altogether can't be reached
+    /// by a cancel either. The other clients are probed over their
+    /// connection, which a vanished host holds until the network timeouts
+    /// the connection was made with run out.
+    pub fn health(&mut self) -> Vec<ClientHealth> {
+        self.check_health(false)
+    }
+
+    /// Probe every client like `health`, and check that the lock table
+    /// exists on the clients that answer
+    pub fn health_with_tables(&mut self) -> Vec<ClientHealth> {
+        self.check_health(true)
+    }
+
+    fn check_health(&mut self, tables: bool) -> Vec<ClientHealth> {
+        self.reconnect_due();
+        // Advisory locks don't use a table
+        let tables = tables && self.dialect.backend != Backend::Advisory;
+        let timeout = self.probe_timeout;
+        let table = tables.then_some((self.table_name.as_str(), self.schema.as_deref()));
+        (0..self.clients.len())
+            .map(|index| {
+                let mut health = ClientHealth {
+                    label: self.labels[index].clone(),
+                    latency: None,
+                    table_exists: None,
+                    error: None,
+                };
+                let client = &mut self.clients[index];
+                if client.is_closed() {
+                    health.error = Some("The connection is closed".to_owned());
+                    return health;
+                }
+                match &self.connectors[index] {
+                    Some(connector) => {
+                        let connector = connector.for_probes(timeout);
+                        match connector.connect() {
+                            Ok(mut client) => {
+                                check(&mut client, Some(&connector), timeout, table, &mut health)
+                            }
+                            Err(err) => health.error = Some(err.to_string()),
+                        }
+                    }
+                    None => check(client, None, timeout, table, &mut health),
+                }
+                health
+            })
+            .collect()
+    }
+}
+
+/// Probe `client` into `health`, and check that `table` exists if given
+fn check(
+    client: &mut Client,
+    connector: Option<&Connector>,
+    timeout: Duration,
+    table: Option<(&str, Option<&str>)>,
+    health: &mut ClientHealth,
+) {
+    match probe(client, connector, timeout) {
+        Ok(latency) => health.latency = Some(latency),
+        Err(err) => {
+            health.error = Some(err.to_string());
+            return;
+        }
+    }
+    if let Some((table_name, schema)) = table {
+        let exists = within(client, connector, timeout, |client| {
+            client.query_one(TABLE_EXISTS_QUERY, &[&table_name, &schema])
+        });
+        match exists {
+            Ok(row) => health.table_exists = Some(row.get(0)),
+            Err(err) => health.error = Some(err.to_string()),
+        }
+    }
+}
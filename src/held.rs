@@ -0,0 +1,171 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::lock::CockLock;
+
+/// A lock this instance holds, as far as the instance knows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeldLock {
+    pub lock_name: String,
+    /// The timeout the lock was last locked or extended with, 0 for locks
+    /// without an expiry
+    pub timeout_ms: i64,
+    /// When the lease runs out at the latest, `None` for locks without an
+    /// expiry
+    ///
+    /// The lease is counted from before the statement that took or renewed
+    /// it, so it ends a little later on the database.
+    pub expires_at: Option<Instant>,
+}
+
+impl HeldLock {
+    /// Whether the lease ran out by `now`, after which the lock may be held
+    /// by someone else
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// The locks held by an instance, kept up to date by its own operations
+///
+/// The workers of a `SharedCockLock` hold their locks together, so the list
+/// can be shared between instances.
+#[derive(Debug, Default)]
+pub(crate) struct HeldLocks {
+    locks: Arc<Mutex<BTreeMap<String, HeldLock>>>,
+}
+
+impl HeldLocks {
+    /// The same list, for another worker of a shared instance
+    pub fn share(&self) -> Self {
+        Self {
+            locks: Arc::clone(&self.locks),
+        }
+    }
+
+    fn locked(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, HeldLock>> {
+        self.locks.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Record that a lock was taken or renewed by a statement started at
+    /// `started_at`
+    pub fn renewed(&self, lock_name: &str, timeout_ms: i64, started_at: Instant) {
+        let expires_at = (timeout_ms != 0)
+            .then(|| started_at + Duration::from_millis(timeout_ms.unsigned_abs()));
+        let lock = HeldLock {
+            lock_name: lock_name.to_owned(),
+            timeout_ms,
+            expires_at,
+        };
+        self.locked().insert(lock.lock_name.clone(), lock);
+    }
+
+    /// Record that a lock was renewed with the timeout it was taken with
+    pub fn refreshed(&self, lock_name: &str, started_at: Instant) {
+        let timeout_ms = self.locked().get(lock_name).map(|lock| lock.timeout_ms);
+        if let Some(timeout_ms) = timeout_ms {
+            self.renewed(lock_name, timeout_ms, started_at);
+        }
+    }
+
+    /// Record that a lock was released or lost
+    pub fn released(&self, lock_name: &str) {
+        self.locked().remove(lock_name);
+    }
+
+    pub fn get(&self, lock_name: &str) -> Option<HeldLock> {
+        self.locked().get(lock_name).cloned()
+    }
+
+    pub fn locks(&self) -> Vec<HeldLock> {
+        self.locked().values().cloned().collect()
+    }
+}
+
+/// Names of locks an instance keeps track of, like the locks taken by the
+/// local fallback or the ones it is a standby for
+///
+/// Shared between the workers of a `SharedCockLock` like `HeldLocks`.
+#[derive(Debug, Default)]
+pub(crate) struct LockNames {
+    names: Arc<Mutex<HashSet<String>>>,
+}
+
+impl LockNames {
+    /// The same names, for another worker of a shared instance
+    pub fn share(&self) -> Self {
+        Self {
+            names: Arc::clone(&self.names),
+        }
+    }
+
+    fn locked(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.names.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Add a name, returning whether it was new
+    pub fn insert(&self, lock_name: String) -> bool {
+        self.locked().insert(lock_name)
+    }
+
+    /// Remove a name, returning whether it was there
+    pub fn remove(&self, lock_name: &str) -> bool {
+        self.locked().remove(lock_name)
+    }
+
+    pub fn contains(&self, lock_name: &str) -> bool {
+        self.locked().contains(lock_name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.locked().iter().cloned().collect()
+    }
+}
+
+impl CockLock {
+    /// The locks this instance holds, ordered by name, without asking the
+    /// database
+    ///
+    /// The list is kept up to date by the locking, extending, refreshing and
+    /// unlocking of this instance, including the renewals of a
+    /// `RenewalScheduler`. A lock that ran out, or that was released by
+    /// another instance, stays in the list until this instance next touches
+    /// it, so check `HeldLock::is_expired` before relying on a lease. Locks
+    /// left from a previous run with the same ID are only listed once this
+    /// instance locks or extends them.
+    pub fn held_locks(&self) -> Vec<HeldLock> {
+        self.held.locks()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::HeldLocks;
+
+    #[test]
+    fn held_locks_follow_renewals_and_releases() {
+        let start = Instant::now();
+        let held = HeldLocks::default();
+        held.renewed("report", 1_000, start);
+        held.renewed("leader", 0, start);
+
+        let later = start + Duration::from_millis(500);
+        held.refreshed("report", later);
+        held.refreshed("unknown", later);
+
+        let locks = held.locks();
+        assert_eq!(locks.len(), 2);
+        assert_eq!(locks[0].lock_name, "leader");
+        assert!(!locks[0].is_expired(start + Duration::from_secs(3_600)));
+        assert_eq!(
+            locks[1].expires_at,
+            Some(later + Duration::from_millis(1_000))
+        );
+
+        held.released("report");
+        assert_eq!(held.locks().len(), 1);
+    }
+}
@@ -0,0 +1,203 @@
+//! Yield points for deterministic concurrency tests
+//!
+//! Renewals, noticing a lost lease and reconnecting happen on background
+//! threads or in the middle of an operation, where an integration test can't
+//! choose what happens in between. Each of those transitions calls the
+//! `TransitionHook` of the instance first (`CockLockBuilder::with_transition_hook`),
+//! so a test can hold threads there and release them in an order of its
+//! choosing. `StepScheduler` does exactly that; driving it from a seeded
+//! `SeededRng` explores interleavings reproducibly, in the manner of loom or
+//! shuttle.
+
+use std::fmt::Debug;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// A state transition of the renewal or connection logic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transition {
+    /// A renewal of the lock is about to be sent, by a keepalive, a leader
+    /// election or a `RenewalScheduler`
+    Renewing(String),
+    /// A renewal of the lock succeeded
+    Renewed(String),
+    /// The holder noticed it lost the lock: a renewal found it taken, or the
+    /// lease ran out while renewals failed
+    LeaseLost(String),
+    /// The client with this label lost its connection and is about to fail
+    /// over to another host or reconnect
+    Reconnecting(String),
+    /// The client with this label reconnected
+    Reconnected(String),
+    /// The client with this label failed and was skipped for another one,
+    /// see `Strictness::Any`
+    ClientSkipped(String),
+    /// Only as many clients answered as the quorum needs, see
+    /// `CockLock::quorum_at_risk`
+    QuorumAtRisk { healthy: usize, required: usize },
+    /// More clients than the quorum needs answer again
+    QuorumRestored,
+    /// Releasing the lock on drop failed, it is held until it expires, see
+    /// `LockGuard`
+    ReleaseFailed(String),
+}
+
+/// Called at every `Transition` on the thread making it: before sending a
+/// renewal or reconnecting, after a renewal or losing a lease. Blocking in
+/// `reached` holds that thread there.
+pub trait TransitionHook: Debug + Send + Sync {
+    fn reached(&self, transition: &Transition);
+}
+
+/// The hook of an instance, if it has one
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Hooks(Option<Arc<dyn TransitionHook>>);
+
+impl Hooks {
+    pub fn new(hook: Option<Arc<dyn TransitionHook>>) -> Self {
+        Self(hook)
+    }
+
+    /// Report a transition, only building it if there is a hook
+    pub fn reached<F: FnOnce() -> Transition>(&self, transition: F) {
+        if let Some(hook) = &self.0 {
+            hook.reached(&transition());
+        }
+    }
+}
+
+/// A hook that holds every thread reaching a transition until the test
+/// releases it
+///
+/// Only one thread proceeds at a time: `parked` waits for the threads a test
+/// expects and `release` lets one of them go on, so the order of the
+/// releases is the order of the transitions.
+#[derive(Debug, Default)]
+pub struct StepScheduler {
+    state: Mutex<StepState>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct StepState {
+    /// The threads waiting at a transition, by ticket, in arrival order
+    parked: Vec<(u64, Transition)>,
+    next_ticket: u64,
+    released: Vec<u64>,
+    history: Vec<Transition>,
+}
+
+impl StepScheduler {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Wait up to `timeout` until `count` threads are parked, returning their
+    /// transitions in the order they arrived
+    ///
+    /// Returns fewer if the timeout passed first.
+    pub fn parked(&self, count: usize, timeout: Duration) -> Vec<Transition> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        while state.parked.len() < count {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            state = self
+                .changed
+                .wait_timeout(state, left)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        state
+            .parked
+            .iter()
+            .map(|(_, transition)| transition.clone())
+            .collect()
+    }
+
+    /// Let the thread at `index` of `parked` go on, returning its transition
+    ///
+    /// Panics if fewer threads are parked.
+    pub fn release(&self, index: usize) -> Transition {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let (ticket, transition) = state.parked.remove(index);
+        state.released.push(ticket);
+        state.history.push(transition.clone());
+        self.changed.notify_all();
+        transition
+    }
+
+    /// The transitions released so far, in order
+    pub fn history(&self) -> Vec<Transition> {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.history.clone()
+    }
+}
+
+impl TransitionHook for StepScheduler {
+    fn reached(&self, transition: &Transition) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.parked.push((ticket, transition.clone()));
+        self.changed.notify_all();
+        while !state.released.contains(&ticket) {
+            state = self
+                .changed
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        state.released.retain(|released| *released != ticket);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::spawn;
+    use std::time::Duration;
+
+    use super::{StepScheduler, Transition, TransitionHook};
+
+    #[test]
+    fn threads_go_on_in_the_order_they_are_released() {
+        let scheduler = StepScheduler::new();
+        let threads: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|lock_name| {
+                let scheduler = scheduler.clone();
+                spawn(move || {
+                    scheduler.reached(&Transition::Renewing(lock_name.to_owned()));
+                    scheduler.reached(&Transition::Renewed(lock_name.to_owned()));
+                })
+            })
+            .collect();
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(scheduler.parked(2, timeout).len(), 2);
+        let first = scheduler.release(1);
+        let first_name = match &first {
+            Transition::Renewing(lock_name) => lock_name.clone(),
+            other => panic!("unexpected {other:?}"),
+        };
+        // The released thread parks again before the other one moves
+        let parked = scheduler.parked(2, timeout);
+        let index = parked
+            .iter()
+            .position(|transition| *transition == Transition::Renewed(first_name.clone()))
+            .unwrap();
+        scheduler.release(index);
+        scheduler.release(0);
+        assert_eq!(scheduler.parked(1, timeout).len(), 1);
+        scheduler.release(0);
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let history = scheduler.history();
+        assert_eq!(history.len(), 4);
+        assert_eq!(history[0], first);
+        assert_eq!(history[1], Transition::Renewed(first_name));
+    }
+}
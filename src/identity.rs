@@ -0,0 +1,136 @@
+//! Who holds a lock
+//!
+//! Every lock row records the identity of its holder, and instances with the
+//! same identity hold each other's locks. Identities are UUIDs in the
+//! database. Readable names, like the name of a pod, map to a UUID of their
+//! own, always the same one for the same name, so an instance restarted under
+//! its name keeps its locks.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use bytes::BytesMut;
+use postgres::types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::errors::CockLockError;
+
+/// The longest name `Identity::parse` accepts, in bytes
+pub const MAX_IDENTITY_NAME_LENGTH: usize = 255;
+
+/// The namespace names are mapped to UUIDs in
+const NAME_NAMESPACE: Uuid = Uuid::from_u128(0x929bc4d1_89c2_42f3_9ef6_51fac6ffbab0);
+
+/// The identity of a lock holder, see `CockLockBuilder::with_client_id`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct Identity(Uuid);
+
+impl Identity {
+    /// A new identity that nobody else has
+    pub fn random() -> Self {
+        Self(Uuid::new_v4())
+    }
+
+    /// The identity of a UUID written out, or else of a name
+    ///
+    /// Fails with `CockLockError::InvalidIdentity` for names that are blank,
+    /// longer than `MAX_IDENTITY_NAME_LENGTH` or contain control characters.
+    pub fn parse(name: &str) -> Result<Self, CockLockError> {
+        if name.trim().is_empty()
+            || name.len() > MAX_IDENTITY_NAME_LENGTH
+            || name.chars().any(char::is_control)
+        {
+            return Err(CockLockError::InvalidIdentity(name.to_owned()));
+        }
+        Ok(match Uuid::parse_str(name) {
+            Ok(uuid) => Self(uuid),
+            Err(_) => Self(Uuid::new_v5(&NAME_NAMESPACE, name.as_bytes())),
+        })
+    }
+
+    pub fn as_uuid(self) -> Uuid {
+        self.0
+    }
+}
+
+impl From<Uuid> for Identity {
+    fn from(uuid: Uuid) -> Self {
+        Self(uuid)
+    }
+}
+
+impl From<Identity> for Uuid {
+    fn from(identity: Identity) -> Self {
+        identity.0
+    }
+}
+
+impl FromStr for Identity {
+    type Err = CockLockError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::parse(name)
+    }
+}
+
+impl Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl ToSql for Identity {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        self.0.to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <Uuid as ToSql>::accepts(ty)
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for Identity {
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Uuid::from_sql(ty, raw).map(Self)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <Uuid as FromSql>::accepts(ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{Identity, MAX_IDENTITY_NAME_LENGTH};
+    use crate::errors::CockLockError;
+
+    #[test]
+    fn names_map_to_the_same_identity_every_time() {
+        let uuid = Uuid::new_v4();
+        assert_eq!(Identity::parse(&uuid.to_string()).unwrap(), uuid.into());
+
+        let pod = Identity::parse("worker-7f9c").unwrap();
+        assert_eq!(Identity::parse("worker-7f9c").unwrap(), pod);
+        assert_ne!(Identity::parse("worker-7f9d").unwrap(), pod);
+
+        let too_long = "x".repeat(MAX_IDENTITY_NAME_LENGTH + 1);
+        for invalid in ["", "   ", "worker\n", too_long.as_str()] {
+            assert!(matches!(
+                Identity::parse(invalid),
+                Err(CockLockError::InvalidIdentity(_))
+            ));
+        }
+    }
+}
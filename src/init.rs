@@ -0,0 +1,130 @@
+//! Values computed once under a lock and shared through the lock table
+//!
+//! Every instance asking for a value that isn't cached yet competes for a lock
+//! of the same name. The winner computes the value and stores it in the
+//! `<table>_values` table, the others wait for the lock and then find the
+//! value there instead of computing it themselves.
+
+use std::time::Duration;
+
+use postgres::types::ToSql;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::errors::CockLockError;
+use crate::guard::DEFAULT_RELEASE_TIMEOUT;
+use crate::lock::{lease_ms, AcquisitionMode, CockLock, Operation};
+
+impl CockLock {
+    /// Get the value cached under `name`, or compute it with `init` if there
+    /// is none or it is older than `ttl_ms`
+    ///
+    /// Only one instance at a time runs `init`, holding the lock `name` for at
+    /// most `ttl_ms` while it does; instances asking meanwhile wait for it to
+    /// finish, backing off like `lock_wait`. If the computing instance dies
+    /// the next one in line computes the value once the lock runs out. Values
+    /// are serialized with the format of
+    /// `CockLockBuilder::with_metadata_format`.
+    pub fn get_or_init<T, V, F>(
+        &mut self,
+        name: T,
+        ttl_ms: i32,
+        init: F,
+    ) -> Result<V, CockLockError>
+    where
+        T: ToString,
+        V: Serialize + DeserializeOwned,
+        F: FnOnce() -> V,
+    {
+        self.get_or_init_ms(name.to_string(), ttl_ms.into(), init)
+    }
+
+    /// Get the value cached under `name` like `get_or_init`, with a `ttl`
+    /// that is rounded like the lease of `lock_for`
+    pub fn get_or_init_for<T, V, F>(
+        &mut self,
+        name: T,
+        ttl: Duration,
+        init: F,
+    ) -> Result<V, CockLockError>
+    where
+        T: ToString,
+        V: Serialize + DeserializeOwned,
+        F: FnOnce() -> V,
+    {
+        self.get_or_init_ms(name.to_string(), lease_ms(ttl), init)
+    }
+
+    fn get_or_init_ms<V, F>(
+        &mut self,
+        name: String,
+        ttl_ms: i64,
+        init: F,
+    ) -> Result<V, CockLockError>
+    where
+        V: Serialize + DeserializeOwned,
+        F: FnOnce() -> V,
+    {
+        self.require_table("cached values")?;
+        if self.acquisition_mode == AcquisitionMode::AppendOnly {
+            return Err(CockLockError::Unsupported(
+                "cached values in append-only mode".to_owned(),
+            ));
+        }
+        if ttl_ms == 0 {
+            return Err(CockLockError::InfiniteLease(name));
+        }
+
+        let mut attempt = 0;
+        loop {
+            if let Some(bytes) = self.cached_value(&name)? {
+                return self.metadata_format.deserialize(&bytes);
+            }
+            match self.lock_ms(name.clone(), ttl_ms) {
+                Ok(_) => break,
+                Err(CockLockError::NotAvailable) => {
+                    let delay = self.retry_policy.delay(attempt, &mut self.rng);
+                    self.wait_for_release(&name, delay);
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        // The previous holder may have stored the value just before releasing
+        let result = match self.cached_value(&name) {
+            Ok(Some(bytes)) => self.metadata_format.deserialize(&bytes),
+            Ok(None) => {
+                let value = init();
+                self.store_value(&name, &value, ttl_ms).map(|_| value)
+            }
+            Err(err) => Err(err),
+        };
+        let _ = self.unlock_within(&name, DEFAULT_RELEASE_TIMEOUT);
+        result
+    }
+
+    fn cached_value(&mut self, name: &str) -> Result<Option<Vec<u8>>, CockLockError> {
+        let params: &[&(dyn ToSql + Sync)] = &[&name];
+        let (_, result) = self.on_available_client(Operation::Value, |client, queries, _| {
+            client.query_opt(&queries.cached_value, params)
+        });
+
+        Ok(result?.map(|row| row.get("value")))
+    }
+
+    fn store_value<V: Serialize>(
+        &mut self,
+        name: &str,
+        value: &V,
+        ttl_ms: i64,
+    ) -> Result<(), CockLockError> {
+        let bytes = self.metadata_format.serialize(value)?;
+        let params: &[&(dyn ToSql + Sync)] = &[&name, &bytes, &ttl_ms];
+        let (_, result) = self.on_available_client(Operation::Value, |client, queries, _| {
+            client.execute(&queries.store_value, params)
+        });
+
+        result.map(|_| ())
+    }
+}
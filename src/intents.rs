@@ -0,0 +1,196 @@
+//! Taking several locks as one, without leaking half of them on a crash
+//!
+//! An instance that crashes halfway through taking a set of locks holds some
+//! of them until they run out, and a restarted instance with the same ID
+//! can't tell them from locks it took on purpose. `CockLock::lock_all`
+//! therefore records an intent naming the whole set before taking any of
+//! them and deletes it once the set is complete or rolled back; an intent
+//! that is still there on the next start belongs to an interrupted call, and
+//! `CockLock::recover_intents` rolls it back.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use postgres::types::ToSql;
+use uuid::Uuid;
+
+use crate::backend::Backend;
+use crate::errors::CockLockError;
+use crate::fencing::FencingToken;
+use crate::lock::{CockLock, Operation};
+
+impl CockLock {
+    /// Take every lock of `lock_names` with a timeout of `timeout_ms`, or
+    /// none of them
+    ///
+    /// The locks are taken one after the other in the given order, and the
+    /// fencing tokens are returned in that order. If one of them can't be
+    /// taken, those taken before it are released again and its error is
+    /// returned. An intent listing the locks is recorded on every client that
+    /// can be reached before the first lock is taken, so a restart of the
+    /// instance can roll back a call that never finished with
+    /// `recover_intents`; it fails if no client records it. Locks the
+    /// instance held already before the call are left out of the intent and
+    /// are never rolled back.
+    pub fn lock_all<T: ToString>(
+        &mut self,
+        lock_names: &[T],
+        timeout_ms: i32,
+    ) -> Result<Vec<FencingToken>, CockLockError> {
+        self.require_table("intent records")?;
+        let lock_names: Vec<String> = lock_names.iter().map(ToString::to_string).collect();
+        for lock_name in &lock_names {
+            self.check_timeout(lock_name, timeout_ms.into())?;
+        }
+        let now = self.clock.now();
+        let held_before: HashSet<&String> = lock_names
+            .iter()
+            .filter(|lock_name| {
+                self.held
+                    .get(lock_name)
+                    .is_some_and(|lock| !lock.is_expired(now))
+            })
+            .collect();
+        let intended: Vec<&String> = lock_names
+            .iter()
+            .filter(|lock_name| !held_before.contains(lock_name))
+            .collect();
+
+        let intent_id = Uuid::new_v4();
+        let id = self.id;
+        let params: &[&(dyn ToSql + Sync)] = &[&intent_id, &id, &intended];
+        let results = self.on_every_client(Operation::Intent, &mut |client, queries, _| {
+            client.execute(&queries.record_intent, params)
+        });
+        let mut first_error = None;
+        let mut recorded = false;
+        for (_, result) in results {
+            match result {
+                Ok(_) => recorded = true,
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        if !recorded {
+            if let Some(err) = first_error {
+                return Err(err);
+            }
+        }
+
+        let mut tokens = Vec::with_capacity(lock_names.len());
+        for lock_name in &lock_names {
+            match self.lock(lock_name, timeout_ms) {
+                Ok(token) => tokens.push(token),
+                Err(err) => {
+                    let rolled_back = lock_names[..tokens.len()]
+                        .iter()
+                        .filter(|taken| !held_before.contains(taken))
+                        .all(|taken| {
+                            matches!(
+                                self.unlock(taken),
+                                Ok(()) | Err(CockLockError::NotAvailable)
+                            )
+                        });
+                    // A failed rollback leaves the intent to `recover_intents`
+                    if rolled_back {
+                        self.clear_intent(intent_id);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        self.clear_intent(intent_id);
+        Ok(tokens)
+    }
+
+    /// Roll back the calls of `lock_all` that this instance didn't finish,
+    /// e.g. because it crashed in the middle of one
+    ///
+    /// Only useful when the instance starts with a stable ID set through
+    /// `CockLockBuilder::with_client_id`; `reconcile_on_start` calls it
+    /// before dealing with the remaining locks. Every lock an unfinished call
+    /// meant to take is released, whether it was taken or not, unless the
+    /// instance has held it since before the intent was recorded. Every client
+    /// is asked even if some of them fail, the first failure is returned once
+    /// all of them were. Returns the names of the released locks in
+    /// alphabetical order.
+    pub fn recover_intents(&mut self) -> Result<Vec<String>, CockLockError> {
+        if self.dialect.backend == Backend::Advisory {
+            return Ok(vec![]);
+        }
+        let id = self.id;
+        let results = self.on_every_client(Operation::Intent, &mut |client, queries, _| {
+            client.query(&queries.own_intents, &[&id])
+        });
+
+        // Every client recorded the same intents, unless some were down
+        let mut intents = BTreeMap::new();
+        let mut first_error = None;
+        for (_, result) in results {
+            match result {
+                Ok(rows) => {
+                    for row in rows {
+                        let intent_id: Uuid = row.get("intent_id");
+                        let lock_names: Vec<String> = row.get("lock_names");
+                        let age_ms: i64 = row.get("age_ms");
+                        intents.entry(intent_id).or_insert((lock_names, age_ms));
+                    }
+                }
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+
+        let mut released = BTreeSet::new();
+        for (intent_id, (lock_names, age_ms)) in intents {
+            let mut rolled_back = true;
+            for lock_name in lock_names {
+                // A lock held since before the intent wasn't taken by the call
+                let predates_intent = match self.lock_info(&lock_name) {
+                    Ok(info) => info.is_some_and(|info| {
+                        info.holder == id && info.held_ms.is_some_and(|held_ms| held_ms > age_ms)
+                    }),
+                    Err(err) => {
+                        rolled_back = false;
+                        first_error.get_or_insert(err);
+                        continue;
+                    }
+                };
+                if predates_intent {
+                    continue;
+                }
+                match self.unlock(&lock_name) {
+                    Ok(()) => {
+                        released.insert(lock_name);
+                    }
+                    // The lock was never taken or ran out in the meantime
+                    Err(CockLockError::NotAvailable) => {}
+                    Err(err) => {
+                        rolled_back = false;
+                        first_error.get_or_insert(err);
+                    }
+                }
+            }
+            if rolled_back {
+                self.clear_intent(intent_id);
+            }
+        }
+
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(released.into_iter().collect()),
+        }
+    }
+
+    /// Delete an intent from every client that can be reached
+    ///
+    /// Clients that miss the deletion keep the intent until the next
+    /// `recover_intents`, which only releases locks the instance holds.
+    fn clear_intent(&mut self, intent_id: Uuid) {
+        let params: &[&(dyn ToSql + Sync)] = &[&intent_id];
+        self.on_every_client(Operation::Intent, &mut |client, queries, _| {
+            client.execute(&queries.clear_intent, params)
+        });
+    }
+}
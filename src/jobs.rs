@@ -0,0 +1,342 @@
+//! Running scheduled jobs on one replica at a time
+//!
+//! Every replica of a service usually runs the same schedule, so a job due at
+//! midnight runs once per replica. `JobLocker` makes each run take a lock
+//! named after the job first and skips the run on the replicas that don't get
+//! it. It works with any scheduler that runs closures; with the
+//! `job_scheduler` feature `JobLocker::job` builds the jobs of
+//! `job_scheduler_ng` directly.
+//!
+//! The lock is not released after a run but kept for the whole lease, since
+//! a replica whose clock is a little behind would otherwise take it for the
+//! same run once the first one finished. The lease should be longer than the
+//! clock skew between replicas and the job itself, and shorter than the time
+//! between two runs.
+//!
+//! A schedule that lives only in the replicas forgets the runs nobody was up
+//! for, and after a full outage the job runs whenever the first replica
+//! happens to wake. `JobLocker::run_due` instead keeps the last and next run
+//! of a job in the database, on the clock of the database, and decides what
+//! to do about missed runs with a `CatchUp` policy. Since the recorded next
+//! run tells the other replicas that a run already happened, it releases the
+//! lock once the runs are recorded.
+
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use crate::errors::CockLockError;
+use crate::lock::{lease_ms, CockLock, Operation};
+
+/// The most runs `JobLocker::run_due` catches up on in one call
+const MAX_RUNS_PER_CALL: u128 = 100;
+
+/// What `JobLocker::run_due` does about the runs a job missed while no
+/// replica ran it, e.g. during an outage of the whole fleet
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CatchUp {
+    /// Drop the missed runs and wait for the next scheduled one
+    Skip,
+    /// Run once for all of them
+    #[default]
+    RunOnce,
+    /// Run once for each of them, oldest first
+    RunAllMissed,
+}
+
+/// The runs of a job kept in the database, see `JobLocker::run_due`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JobRuns {
+    /// When the job last ran, `None` if its runs were only ever skipped
+    pub last_run_at: Option<SystemTime>,
+    pub next_run_at: SystemTime,
+}
+
+/// Runs jobs only on the replica that takes their lock, see the module docs
+#[derive(Clone)]
+pub struct JobLocker {
+    cock_lock: Arc<Mutex<CockLock>>,
+    lease_ms: i64,
+}
+
+impl JobLocker {
+    /// Lock jobs with `cock_lock` for leases of `lease_ms`
+    pub fn new(cock_lock: CockLock, lease_ms: i32) -> Self {
+        Self::with_lease_ms(cock_lock, lease_ms.into())
+    }
+
+    /// Lock jobs with `cock_lock` for leases of `lease`, rounded like the
+    /// ones of `CockLock::lock_for`
+    pub fn for_lease(cock_lock: CockLock, lease: Duration) -> Self {
+        Self::with_lease_ms(cock_lock, lease_ms(lease))
+    }
+
+    fn with_lease_ms(cock_lock: CockLock, lease_ms: i64) -> Self {
+        Self {
+            cock_lock: Arc::new(Mutex::new(cock_lock)),
+            lease_ms,
+        }
+    }
+
+    /// Run `job` if this replica takes the lock `job_name`, returns whether
+    /// it ran
+    pub fn run_exclusive<F: FnOnce()>(
+        &self,
+        job_name: &str,
+        job: F,
+    ) -> Result<bool, CockLockError> {
+        let locked = self
+            .cock_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .lock_ms(job_name.to_owned(), self.lease_ms);
+        match locked {
+            Ok(_) => {
+                job();
+                Ok(true)
+            }
+            Err(CockLockError::NotAvailable) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Run `job` for every run of `job_name` due by now, if this replica
+    /// takes its lock, returns how many runs happened
+    ///
+    /// The job is due every `every` from its first run on, and gets the
+    /// time each run was scheduled for. `run_due` only runs what is due, so
+    /// it may be called more often than that, e.g. every minute from a
+    /// scheduler or a loop; runs missed by more than one interval are
+    /// handled by `catch_up`, though at most 100 of them run per call and
+    /// the rest on the following ones. Each run is recorded once the job
+    /// returns and the lease renewed before the next one, so a replica that
+    /// dies halfway leaves the remaining runs to the next one; the lease
+    /// should outlast a single run. Fails with `CockLockError::ZeroInterval`
+    /// if `every` is zero.
+    pub fn run_due<F: FnMut(SystemTime)>(
+        &self,
+        job_name: &str,
+        every: Duration,
+        catch_up: CatchUp,
+        mut job: F,
+    ) -> Result<usize, CockLockError> {
+        if every.is_zero() {
+            return Err(CockLockError::ZeroInterval(job_name.to_owned()));
+        }
+        let state = {
+            let mut cock_lock = self
+                .cock_lock
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            match cock_lock.lock_ms(job_name.to_owned(), self.lease_ms) {
+                Ok(_) => {}
+                Err(CockLockError::NotAvailable) => return Ok(0),
+                Err(err) => return Err(err),
+            }
+            cock_lock.job_runs(job_name)
+        };
+
+        let result = state.and_then(|(runs, now)| {
+            let (due, next_run_at) = match runs {
+                Some(runs) => due_runs(runs.next_run_at, now, every, catch_up),
+                None => (vec![now], now + every),
+            };
+            if due.is_empty() {
+                let runs = JobRuns {
+                    last_run_at: runs.and_then(|runs| runs.last_run_at),
+                    next_run_at,
+                };
+                return self
+                    .cock_lock
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .record_job_runs(job_name, &runs)
+                    .map(|()| 0);
+            }
+
+            for (ran, &scheduled_at) in due.iter().enumerate() {
+                job(scheduled_at);
+                let runs = JobRuns {
+                    last_run_at: Some(now),
+                    next_run_at: due.get(ran + 1).copied().unwrap_or(next_run_at),
+                };
+                let mut cock_lock = self
+                    .cock_lock
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                cock_lock.record_job_runs(job_name, &runs)?;
+                if ran + 1 == due.len() {
+                    break;
+                }
+                match cock_lock.refresh(job_name) {
+                    Ok(()) => {}
+                    // Another replica may take over the remaining runs
+                    Err(CockLockError::NotAvailable) => return Ok(ran + 1),
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok(due.len())
+        });
+
+        let mut cock_lock = self
+            .cock_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        // The lease runs out anyway if this fails
+        let _ = cock_lock.unlock(job_name);
+        result
+    }
+
+    /// The runs of `job_name` kept by `run_due`, `None` if it never ran
+    pub fn runs(&self, job_name: &str) -> Result<Option<JobRuns>, CockLockError> {
+        self.cock_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .job_runs(job_name)
+            .map(|(runs, _)| runs)
+    }
+
+    /// Wrap `job` for a scheduler, so that each run only happens on the
+    /// replica that takes the lock `job_name`
+    ///
+    /// Runs that fail to reach the database are skipped and logged.
+    pub fn wrap<F>(&self, job_name: &str, mut job: F) -> impl FnMut() + Send + 'static
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let locker = self.clone();
+        let job_name = job_name.to_owned();
+        move || {
+            if let Err(err) = locker.run_exclusive(&job_name, &mut job) {
+                log::error!(target: "cocklock::jobs", "Skipped a run of {job_name}: {err}");
+            }
+        }
+    }
+
+    /// A job of `job_scheduler_ng` that runs `job` on `schedule` on one
+    /// replica at a time
+    #[cfg(feature = "job_scheduler")]
+    pub fn job<F>(
+        &self,
+        schedule: job_scheduler_ng::Schedule,
+        job_name: &str,
+        job: F,
+    ) -> job_scheduler_ng::Job<'static>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        job_scheduler_ng::Job::new(schedule, self.wrap(job_name, job))
+    }
+}
+
+/// The runs due at `now` of a job running every `every` whose next run was
+/// due at `next_run_at`, and when the run after them is due
+///
+/// At most `MAX_RUNS_PER_CALL` missed runs are returned, the run after them
+/// is then the first one left out.
+fn due_runs(
+    next_run_at: SystemTime,
+    now: SystemTime,
+    every: Duration,
+    catch_up: CatchUp,
+) -> (Vec<SystemTime>, SystemTime) {
+    let overdue = match now.duration_since(next_run_at) {
+        Ok(overdue) => overdue,
+        Err(_) => return (vec![], next_run_at),
+    };
+    let every_ns = every.as_nanos().max(1);
+    let scheduled = |run: u128| {
+        let since_ns = every_ns.saturating_mul(run);
+        next_run_at + Duration::from_nanos(u64::try_from(since_ns).unwrap_or(u64::MAX))
+    };
+    let missed = overdue.as_nanos() / every_ns + 1;
+    let due = match (missed, catch_up) {
+        (1, _) => vec![next_run_at],
+        (_, CatchUp::Skip) => vec![],
+        (_, CatchUp::RunOnce) => vec![scheduled(missed - 1)],
+        (_, CatchUp::RunAllMissed) => {
+            let runs = missed.min(MAX_RUNS_PER_CALL);
+            return ((0..runs).map(scheduled).collect(), scheduled(runs));
+        }
+    };
+    (due, scheduled(missed))
+}
+
+impl CockLock {
+    /// The runs of `job_name` kept in the database, and the time of the
+    /// database
+    fn job_runs(&mut self, job_name: &str) -> Result<(Option<JobRuns>, SystemTime), CockLockError> {
+        self.require_table("job runs")?;
+        let (_, result) = self.on_available_client(Operation::Job, |client, queries, _| {
+            client.query_one(&queries.job_runs, &[&job_name])
+        });
+        let row = result?;
+        let runs = row
+            .get::<_, Option<SystemTime>>("next_run_at")
+            .map(|next_run_at| JobRuns {
+                last_run_at: row.get("last_run_at"),
+                next_run_at,
+            });
+        Ok((runs, row.get("now")))
+    }
+
+    fn record_job_runs(&mut self, job_name: &str, runs: &JobRuns) -> Result<(), CockLockError> {
+        let (_, result) = self.on_available_client(Operation::Job, |client, queries, _| {
+            client.execute(
+                &queries.record_job_runs,
+                &[&job_name, &runs.last_run_at, &runs.next_run_at],
+            )
+        });
+        result.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{due_runs, CatchUp, MAX_RUNS_PER_CALL};
+
+    #[test]
+    fn missed_runs_follow_the_catch_up_policy() {
+        let hour = Duration::from_secs(3600);
+        let next_run_at = SystemTime::UNIX_EPOCH + hour * 100;
+
+        // Not due yet
+        let now = next_run_at - Duration::from_secs(1);
+        assert_eq!(
+            due_runs(next_run_at, now, hour, CatchUp::Skip),
+            (vec![], next_run_at)
+        );
+
+        // Due, without anything missed
+        let now = next_run_at + Duration::from_secs(60);
+        for catch_up in [CatchUp::Skip, CatchUp::RunOnce, CatchUp::RunAllMissed] {
+            assert_eq!(
+                due_runs(next_run_at, now, hour, catch_up),
+                (vec![next_run_at], next_run_at + hour)
+            );
+        }
+
+        // Three runs missed, a fourth one due
+        let now = next_run_at + hour * 3 + Duration::from_secs(60);
+        let after = next_run_at + hour * 4;
+        assert_eq!(
+            due_runs(next_run_at, now, hour, CatchUp::Skip),
+            (vec![], after)
+        );
+        assert_eq!(
+            due_runs(next_run_at, now, hour, CatchUp::RunOnce),
+            (vec![next_run_at + hour * 3], after)
+        );
+        assert_eq!(
+            due_runs(next_run_at, now, hour, CatchUp::RunAllMissed),
+            ((0..4).map(|run| next_run_at + hour * run).collect(), after)
+        );
+
+        // Too many runs missed to catch up on at once
+        let now = next_run_at + hour * 1_000;
+        let (due, after) = due_runs(next_run_at, now, hour, CatchUp::RunAllMissed);
+        assert_eq!(due.len() as u128, MAX_RUNS_PER_CALL);
+        assert_eq!(after, next_run_at + hour * 100);
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::deterministic::SeededRng;
+use crate::errors::CockLockError;
+use crate::lock::Operation;
+
+/// The number of operations kept unless configured otherwise
+pub static DEFAULT_JOURNAL_CAPACITY: usize = 100;
+
+/// How an operation ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    /// The lock was held by someone else, or not held by the instance
+    NotAvailable,
+    Failed,
+}
+
+/// A single lock operation performed by the instance
+#[derive(Debug, Clone)]
+pub struct OperationRecord {
+    pub operation: Operation,
+    pub lock_name: String,
+    pub outcome: Outcome,
+    /// The index of the client that answered, `None` if none could be reached
+    pub client: Option<usize>,
+    /// The label of the client that answered
+    pub client_label: Option<String>,
+    pub started_at: SystemTime,
+    pub duration: Duration,
+    /// The error message of failed operations
+    pub error: Option<String>,
+}
+
+/// A bounded in-memory ring buffer of the most recent operations
+pub(crate) struct Journal {
+    capacity: usize,
+    records: VecDeque<OperationRecord>,
+    /// The fraction of successful operations that are kept
+    success_sampling: f64,
+    rng: SeededRng,
+}
+
+impl Journal {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: VecDeque::with_capacity(capacity),
+            success_sampling: 1.0,
+            rng: SeededRng::new(0),
+        }
+    }
+
+    /// Only keep a random `fraction` of the successful operations, drawn
+    /// from a generator seeded with `seed`
+    pub fn with_success_sampling(self, fraction: f64, seed: u64) -> Self {
+        Self {
+            success_sampling: fraction.clamp(0.0, 1.0),
+            rng: SeededRng::new(seed),
+            ..self
+        }
+    }
+
+    /// An empty journal with the same settings
+    pub fn emptied(&self) -> Self {
+        Self {
+            success_sampling: self.success_sampling,
+            rng: self.rng.clone(),
+            ..Self::new(self.capacity)
+        }
+    }
+
+    /// Add an operation that started at `started_at` and ended just now,
+    /// dropping the oldest one if the journal is full
+    pub fn record<T>(
+        &mut self,
+        operation: Operation,
+        lock_name: &str,
+        client: Option<(usize, &str)>,
+        started_at: Instant,
+        result: &Result<T, CockLockError>,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if result.is_ok() && !self.sampled() {
+            return;
+        }
+
+        let duration = started_at.elapsed();
+        let (outcome, error) = match result {
+            Ok(_) => (Outcome::Success, None),
+            Err(CockLockError::NotAvailable) => (Outcome::NotAvailable, None),
+            Err(err) => (Outcome::Failed, Some(err.to_string())),
+        };
+
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(OperationRecord {
+            operation,
+            lock_name: lock_name.to_owned(),
+            outcome,
+            client: client.map(|(index, _)| index),
+            client_label: client.map(|(_, label)| label.to_owned()),
+            started_at: SystemTime::now() - duration,
+            duration,
+            error,
+        });
+    }
+
+    /// Whether the next successful operation is kept, also asked before the
+    /// observers are told about one
+    pub fn sampled(&mut self) -> bool {
+        if self.success_sampling >= 1.0 {
+            return true;
+        }
+        // The upper 53 bits make a uniform float in [0, 1)
+        let draw = (self.rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        draw < self.success_sampling
+    }
+
+    pub fn records(&self) -> Vec<OperationRecord> {
+        self.records.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::{Journal, Outcome};
+    use crate::errors::CockLockError;
+    use crate::lock::Operation;
+
+    #[test]
+    fn journal_keeps_the_most_recent_operations() {
+        let mut journal = Journal::new(2);
+        journal.record(
+            Operation::Lock,
+            "a",
+            Some((0, "primary")),
+            Instant::now(),
+            &Ok(()),
+        );
+        journal.record::<()>(
+            Operation::Lock,
+            "b",
+            Some((0, "primary")),
+            Instant::now(),
+            &Err(CockLockError::NotAvailable),
+        );
+        journal.record::<()>(
+            Operation::Unlock,
+            "c",
+            None,
+            Instant::now(),
+            &Err(CockLockError::NoClientsAvailable(vec![])),
+        );
+
+        let records = journal.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].lock_name, "b");
+        assert_eq!(records[0].outcome, Outcome::NotAvailable);
+        assert_eq!(records[0].client_label.as_deref(), Some("primary"));
+        assert_eq!(records[1].outcome, Outcome::Failed);
+        assert!(records[1].error.is_some());
+
+        let mut disabled = Journal::new(0);
+        disabled.record(
+            Operation::Lock,
+            "a",
+            Some((0, "primary")),
+            Instant::now(),
+            &Ok(()),
+        );
+        assert!(disabled.records().is_empty());
+    }
+
+    #[test]
+    fn sampling_keeps_every_failure() {
+        let mut journal = Journal::new(1_000).with_success_sampling(0.1, 7);
+        for _ in 0..500 {
+            journal.record(Operation::Lock, "a", None, Instant::now(), &Ok(()));
+            journal.record::<()>(
+                Operation::Lock,
+                "a",
+                None,
+                Instant::now(),
+                &Err(CockLockError::NoClientsAvailable(vec![])),
+            );
+        }
+
+        let records = journal.records();
+        let successes = records
+            .iter()
+            .filter(|record| record.outcome == Outcome::Success)
+            .count();
+        assert_eq!(records.len() - successes, 500);
+        assert!((20..=80).contains(&successes), "{successes} successes kept");
+
+        let mut muted = journal.emptied().with_success_sampling(0.0, 7);
+        muted.record(Operation::Lock, "a", None, Instant::now(), &Ok(()));
+        assert!(muted.records().is_empty());
+    }
+}
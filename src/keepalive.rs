@@ -0,0 +1,148 @@
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread::spawn;
+use std::time::Duration;
+
+use crate::errors::CockLockError;
+use crate::fencing::FencingToken;
+use crate::guard::DEFAULT_RELEASE_TIMEOUT;
+use crate::hooks::Transition;
+use crate::lock::CockLock;
+
+type Reply = Sender<Result<(), CockLockError>>;
+
+/// The shortest lease of `CockLock::lock_with_keepalive`, so that renewing it
+/// every third of the lease waits at least a millisecond between renewals
+pub static MIN_KEEPALIVE_LEASE_MS: i32 = 3;
+
+/// A lock that is renewed in the background until it is released, see
+/// `CockLock::lock_with_keepalive`
+///
+/// Renewals run on a thread with connections of its own, so the instance
+/// that took the lock stays free for other work. When the lock is lost, the
+/// thread reports `CockLockError::NotAvailable` and stops; failed renewals
+/// are reported and retried at the next interval. Dropping the keepalive
+/// releases the lock from the background thread without waiting for it.
+pub struct KeepAlive {
+    lock_name: String,
+    fencing_token: FencingToken,
+    /// Asks the thread to release the lock, optionally reporting the result
+    release: Option<Sender<Option<Reply>>>,
+    failures: Receiver<CockLockError>,
+}
+
+impl KeepAlive {
+    pub fn lock_name(&self) -> &str {
+        &self.lock_name
+    }
+
+    /// The fencing token of the lease, which the renewals keep
+    pub fn fencing_token(&self) -> FencingToken {
+        self.fencing_token
+    }
+
+    /// The oldest renewal failure that wasn't taken yet, if any
+    pub fn try_failure(&self) -> Option<CockLockError> {
+        self.failures.try_recv().ok()
+    }
+
+    /// Wait up to `timeout` for a renewal to fail
+    ///
+    /// Returns `None` if every renewal within `timeout` succeeded, or if the
+    /// renewals stopped after reporting their last failure.
+    pub fn wait_for_failure(&self, timeout: Duration) -> Option<CockLockError> {
+        self.failures.recv_timeout(timeout).ok()
+    }
+
+    /// Stop renewing and release the lock, reporting whether it worked
+    ///
+    /// Returns `CockLockError::NotAvailable` if the lock was lost before.
+    pub fn release(mut self) -> Result<(), CockLockError> {
+        let (reply, result) = channel();
+        self.release
+            .take()
+            .and_then(|release| release.send(Some(reply)).ok())
+            .ok_or(CockLockError::NotAvailable)?;
+        result.recv().unwrap_or(Err(CockLockError::NotAvailable))
+    }
+}
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        if let Some(release) = self.release.take() {
+            let _ = release.send(None);
+        }
+    }
+}
+
+impl CockLock {
+    /// Try to create a lock like `lock`, and keep renewing it to `lease_ms`
+    /// every third of the lease until the returned `KeepAlive` is released or
+    /// dropped
+    ///
+    /// Long jobs can take a short lease this way, instead of guessing a
+    /// timeout long enough for their slowest run. The renewals need
+    /// connections of their own, so every client must have been given as a
+    /// connection string. Neither the renewals nor the release show up in
+    /// `recent_operations` or `held_locks` of this instance. Leases shorter
+    /// than `MIN_KEEPALIVE_LEASE_MS` fail with `CockLockError::TimeoutTooShort`.
+    pub fn lock_with_keepalive<T: ToString>(
+        &mut self,
+        lock_name: T,
+        lease_ms: i32,
+    ) -> Result<KeepAlive, CockLockError> {
+        let lock_name = lock_name.to_string();
+        if lease_ms == 0 {
+            return Err(CockLockError::InfiniteLease(lock_name));
+        }
+        if lease_ms < MIN_KEEPALIVE_LEASE_MS {
+            return Err(CockLockError::TimeoutTooShort(
+                lock_name,
+                MIN_KEEPALIVE_LEASE_MS,
+            ));
+        }
+        self.require_table("keep-alives")?;
+        let mut renewer = self.sibling()?;
+        let fencing_token = self.lock(&lock_name, lease_ms)?;
+
+        let interval = Duration::from_millis(lease_ms.unsigned_abs().into()) / 3;
+        let (release, requests) = channel::<Option<Reply>>();
+        let (failed, failures) = channel();
+        let name = lock_name.clone();
+        spawn(move || loop {
+            match requests.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {
+                    renewer.hooks.reached(|| Transition::Renewing(name.clone()));
+                    match renewer.extend_many(&[&name], lease_ms) {
+                        Ok(extended) if extended[0].1 => {
+                            renewer.hooks.reached(|| Transition::Renewed(name.clone()));
+                        }
+                        Ok(_) => {
+                            renewer
+                                .hooks
+                                .reached(|| Transition::LeaseLost(name.clone()));
+                            let _ = failed.send(CockLockError::NotAvailable);
+                            return;
+                        }
+                        Err(err) => {
+                            let _ = failed.send(err);
+                        }
+                    }
+                }
+                request => {
+                    let result = renewer.unlock_within(&name, DEFAULT_RELEASE_TIMEOUT);
+                    if let Ok(Some(reply)) = request {
+                        let _ = reply.send(result);
+                    }
+                    return;
+                }
+            }
+        });
+
+        Ok(KeepAlive {
+            lock_name,
+            fencing_token,
+            release: Some(release),
+            failures,
+        })
+    }
+}
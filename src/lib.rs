@@ -1,9 +1,79 @@
+mod connection;
+#[cfg(test)]
+mod containers;
 mod queries;
+mod table;
+mod tls;
 
 pub mod errors;
 
+pub mod abort;
+pub mod audit;
+pub mod backend;
+pub mod barrier;
 pub mod builder;
+#[cfg(feature = "changefeed")]
+pub mod changefeed;
+pub mod chunked;
+pub mod composite;
+pub mod consistency;
+pub mod cutover;
+pub mod delegation;
+pub mod describe;
+pub mod deterministic;
+pub mod election;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+pub mod export;
+pub mod failure;
+pub mod fairness;
+pub mod fallback;
+pub mod fencing;
+pub mod force;
+#[cfg(feature = "serde")]
+pub mod format;
+pub mod guard;
+pub mod health;
+pub mod held;
+pub mod hooks;
+pub mod identity;
+#[cfg(feature = "serde")]
+pub mod init;
+pub mod intents;
+pub mod jobs;
+pub mod journal;
+pub mod keepalive;
+pub mod listen;
+pub mod listing;
 pub mod lock;
+pub mod maintenance;
+pub mod metadata;
+pub mod multiplex;
+pub mod notify;
+pub mod observer;
+pub mod optimistic;
+pub mod policy;
+pub mod pool;
+pub mod preemption;
+pub mod quorum;
+pub mod quota;
+pub mod raw;
+pub mod reconcile;
+pub mod reconnect;
+pub mod registry;
+pub mod renewal;
+pub mod retry;
+pub mod rotation;
+pub mod scenarios;
+pub mod schema;
+pub mod snapshot;
+pub mod standby;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod transfer;
+pub mod usage;
+#[cfg(feature = "webhooks")]
+pub mod webhooks;
 
 pub use crate::builder::CockLockBuilder;
 pub use crate::lock::CockLock;
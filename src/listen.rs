@@ -0,0 +1,89 @@
+//! Lock releases pushed by PostgreSQL through `LISTEN`/`NOTIFY`
+//!
+//! With `CockLockBuilder::with_unlock_notifications`, every release sends a
+//! notification on the `cocklock:<table>` channel with the name of the lock
+//! as its payload, prefixed with the namespace if there is one. One channel
+//! per table keeps the number of `LISTEN`s down, the notifier skips the
+//! releases of other locks.
+
+use std::time::{Duration, Instant};
+
+use postgres::fallible_iterator::FallibleIterator;
+use postgres::Client;
+
+use crate::backend::Backend;
+use crate::errors::CockLockError;
+use crate::lock::{CockLock, Operation};
+use crate::notify::Notifier;
+use crate::table::{quote_identifier, Table};
+
+/// Wakes waiters as soon as their lock is released
+///
+/// The notifier listens on a connection of its own. Locks that expire
+/// aren't announced, so waiters still need a timeout.
+pub struct ListenNotifier {
+    client: Client,
+    label: String,
+    table: Table,
+}
+
+impl Notifier for ListenNotifier {
+    fn wait(&mut self, lock_name: &str, timeout: Duration) -> Result<(), CockLockError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let notification = self.client.notifications().timeout_iter(remaining).next();
+            match notification {
+                Ok(Some(notification))
+                    if notification.payload() == self.table.namespaced(lock_name) =>
+                {
+                    return Ok(())
+                }
+                Ok(Some(_)) => {}
+                Ok(None) => return Ok(()),
+                Err(err) => {
+                    return Err(CockLockError::postgres(err, Operation::Watch, &self.label))
+                }
+            }
+        }
+    }
+}
+
+impl CockLock {
+    /// Listen for the releases of locks on the first client that was given as
+    /// a connection string
+    ///
+    /// Only available on PostgreSQL, and only releases by instances with
+    /// unlock notifications turned on are heard.
+    pub fn listen_notifier(&self) -> Result<ListenNotifier, CockLockError> {
+        if self.dialect.backend != Backend::Postgres {
+            return Err(CockLockError::Unsupported(
+                "LISTEN/NOTIFY on CockroachDB".to_owned(),
+            ));
+        }
+
+        let (connector, label) = self
+            .connectors
+            .iter()
+            .zip(&self.labels)
+            .find_map(|(connector, label)| Some((connector.as_ref()?, label.clone())))
+            .ok_or_else(|| {
+                CockLockError::Unsupported("listening on clients added as objects".to_owned())
+            })?;
+        let mut client = connector
+            .connect()
+            .map_err(|err| CockLockError::postgres(err, Operation::Watch, &label))?;
+        client
+            .batch_execute(&format!(
+                "listen {};",
+                quote_identifier(&format!("cocklock:{}", self.table().key()))
+            ))
+            .map_err(|err| CockLockError::postgres(err, Operation::Watch, &label))?;
+
+        Ok(ListenNotifier {
+            client,
+            label,
+            table: self.table(),
+        })
+    }
+}
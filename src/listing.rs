@@ -0,0 +1,301 @@
+use std::time::Duration;
+
+use postgres::types::ToSql;
+use postgres::Row;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::errors::CockLockError;
+use crate::identity::Identity;
+use crate::lock::{CockLock, Operation};
+
+/// An active lock as seen by one of the clients
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LockInfo {
+    pub lock_name: String,
+    /// The ID of the instance holding the lock
+    pub holder: Identity,
+    /// The lease left, `None` for locks without an expiry
+    pub remaining_ms: Option<i64>,
+    /// The time since the holder last locked, extended or refreshed the lock,
+    /// `None` for locks taken by versions that didn't record it
+    ///
+    /// A long lease whose holder has been silent for most of it may belong to
+    /// a holder that is stuck or gone.
+    pub silent_ms: Option<i64>,
+    /// The time since the holder took the lock, kept while it relocks or
+    /// extends it, `None` for locks taken by versions that didn't record it
+    pub held_ms: Option<i64>,
+    /// How many holders the lock has had, counting up whenever it changes
+    /// hands but not when it is extended or relocked by its holder
+    ///
+    /// Cheaper to compare than the holder for noticing a takeover, and it
+    /// also changes when the same instance takes the lock again after
+    /// losing it. `None` for locks taken by versions that didn't count them
+    /// on CockroachDB.
+    pub epoch: Option<i64>,
+    /// What the holder attached to the lock with `CockLock::lock_with_meta`
+    pub metadata: Option<Vec<u8>>,
+}
+
+impl LockInfo {
+    /// Whether the lock is held until it is explicitly unlocked
+    ///
+    /// Such locks outlive crashed holders, so they are worth keeping an eye
+    /// on.
+    pub fn never_expires(&self) -> bool {
+        self.remaining_ms.is_none()
+    }
+
+    /// Read a lock from a row with the columns of the listing queries
+    pub(crate) fn from_row(row: &Row) -> Self {
+        Self {
+            lock_name: row.get("lock_name"),
+            holder: row.get("client_id"),
+            remaining_ms: row.get("remaining_ms"),
+            silent_ms: row.get("silent_ms"),
+            held_ms: row.get("held_ms"),
+            epoch: row.get("epoch"),
+            metadata: row.get("metadata"),
+        }
+    }
+}
+
+/// The order `CockLock::list_locks` returns locks in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockOrder {
+    #[default]
+    Name,
+    /// Soonest to expire first, locks without an expiry last
+    Expiry,
+}
+
+/// Where a page of `CockLock::list_locks` ended, to continue after it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListCursor {
+    lock_name: String,
+    /// The expiry of the last lock as Postgres prints it, so that it can be
+    /// compared without losing precision
+    expires_at: Option<String>,
+}
+
+/// What `CockLock::list_locks` lists and how
+///
+/// By default the active locks of every holder are listed by name, 100 at a
+/// time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListLocks {
+    order: LockOrder,
+    expired: bool,
+    only_mine: bool,
+    limit: i64,
+    after: Option<ListCursor>,
+}
+
+impl Default for ListLocks {
+    fn default() -> Self {
+        Self {
+            order: LockOrder::default(),
+            expired: false,
+            only_mine: false,
+            limit: 100,
+            after: None,
+        }
+    }
+}
+
+impl ListLocks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ordered_by(mut self, order: LockOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// List the locks that expired but weren't deleted yet instead of the
+    /// active ones
+    ///
+    /// On PostgreSQL expired locks are reaped whenever a lock is taken, so
+    /// there are rarely any. On CockroachDB they stay until
+    /// `CockLock::reap_expired` runs or their name is locked again.
+    pub fn only_expired(mut self) -> Self {
+        self.expired = true;
+        self
+    }
+
+    /// Only list the locks held by this instance
+    pub fn only_mine(mut self) -> Self {
+        self.only_mine = true;
+        self
+    }
+
+    /// The maximum number of locks per page
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Continue after the page that returned `cursor`
+    ///
+    /// The cursor only makes sense with the order it was returned for.
+    pub fn after(mut self, cursor: ListCursor) -> Self {
+        self.after = Some(cursor);
+        self
+    }
+}
+
+/// One page of locks, with the cursor to the next one if the page is full
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LockPage {
+    pub locks: Vec<LockInfo>,
+    pub next: Option<ListCursor>,
+}
+
+impl CockLock {
+    /// Whether anyone holds the lock `lock_name` right now
+    ///
+    /// Read from the first reachable client, like `lock_info`.
+    pub fn is_locked<T: ToString>(&mut self, lock_name: T) -> Result<bool, CockLockError> {
+        Ok(self.lock_info(lock_name)?.is_some())
+    }
+
+    /// Who holds the lock `lock_name` and for how long, `None` if nobody
+    /// does
+    ///
+    /// The lock is read from the first reachable client. With several
+    /// independent clients that is only the view of that client; a lock
+    /// held on a minority of them may still show up here.
+    pub fn lock_info<T: ToString>(
+        &mut self,
+        lock_name: T,
+    ) -> Result<Option<LockInfo>, CockLockError> {
+        self.require_table("inspecting locks")?;
+        let lock_name = lock_name.to_string();
+        let params: &[&(dyn ToSql + Sync)] = &[&lock_name];
+        let (_, result) = self.on_available_client(Operation::Inspect, |client, queries, _| {
+            client.query_opt(&queries.lock_info, params)
+        });
+
+        Ok(result?.as_ref().map(|row| self.lock_info_from_row(row)))
+    }
+
+    /// How much of its lease the instance has left on the lock `lock_name`,
+    /// `None` for a lease without an expiry
+    ///
+    /// Measured against `now()` of the database, which decides when the
+    /// lease ends, rather than the clock of the instance. Fails with
+    /// `NotHeld` if the instance doesn't hold the lock, including when its
+    /// lease already ran out.
+    pub fn remaining_ttl<T: ToString>(
+        &mut self,
+        lock_name: T,
+    ) -> Result<Option<Duration>, CockLockError> {
+        let lock_name = lock_name.to_string();
+        match self.lock_info(&lock_name)? {
+            Some(info) if info.holder == self.id => Ok(info
+                .remaining_ms
+                .map(|remaining_ms| Duration::from_millis(remaining_ms.max(0).unsigned_abs()))),
+            _ => Err(CockLockError::NotHeld(lock_name)),
+        }
+    }
+
+    /// List the locks of the table one page at a time
+    ///
+    /// Pages are continued from the last lock of the previous one rather than
+    /// from an offset, so every page costs about the same however large the
+    /// table is, and locks that come and go between pages don't shift the
+    /// rest. The locks are read from the first reachable client.
+    pub fn list_locks(&mut self, options: &ListLocks) -> Result<LockPage, CockLockError> {
+        self.require_table("listing locks")?;
+        let holder = options.only_mine.then_some(self.id);
+        let after_name = options.after.as_ref().map(|cursor| &cursor.lock_name);
+        let after_expiry = options
+            .after
+            .as_ref()
+            .and_then(|cursor| cursor.expires_at.as_ref());
+        let (_, result) =
+            self.on_available_client(Operation::List, |client, queries, _| match options.order {
+                LockOrder::Name => client.query(
+                    &queries.list_by_name,
+                    &[&options.expired, &holder, &after_name, &options.limit],
+                ),
+                LockOrder::Expiry => client.query(
+                    &queries.list_by_expiry,
+                    &[
+                        &options.expired,
+                        &holder,
+                        &after_name,
+                        &options.limit,
+                        &after_expiry,
+                    ],
+                ),
+            });
+
+        let rows = result?;
+        let next = match rows.last() {
+            Some(row) if rows.len() as i64 >= options.limit => Some(ListCursor {
+                lock_name: row.get("lock_name"),
+                expires_at: row.get("expires_at_key"),
+            }),
+            _ => None,
+        };
+        let locks = rows
+            .iter()
+            .map(|row| self.lock_info_from_row(row))
+            .collect();
+
+        Ok(LockPage { locks, next })
+    }
+
+    /// List the active locks whose name starts with `prefix`, ordered by name
+    ///
+    /// The locks are read from the first reachable client. The lookup uses an
+    /// index on the lock names, so it stays cheap for small families of locks
+    /// in a large table.
+    pub fn list_locks_prefix<T: ToString>(
+        &mut self,
+        prefix: T,
+    ) -> Result<Vec<LockInfo>, CockLockError> {
+        self.require_table("listing locks")?;
+        let pattern = like_prefix(&prefix.to_string());
+        let params: &[&(dyn ToSql + Sync)] = &[&pattern];
+        let (_, result) = self.on_available_client(Operation::List, |client, queries, _| {
+            client.query(&queries.list_prefix, params)
+        });
+
+        Ok(result?
+            .iter()
+            .map(|row| self.lock_info_from_row(row))
+            .collect())
+    }
+}
+
+/// A `LIKE` pattern matching every name that starts with `prefix`
+///
+/// Wildcards in the prefix are escaped so that they match literally.
+pub(crate) fn like_prefix(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for char in prefix.chars() {
+        if matches!(char, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(char);
+    }
+    pattern.push('%');
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::like_prefix;
+
+    #[test]
+    fn like_prefix_escapes_wildcards() {
+        assert_eq!(like_prefix("tenant-1/"), "tenant-1/%");
+        assert_eq!(like_prefix("50%_off\\"), "50\\%\\_off\\\\%");
+        assert_eq!(like_prefix(""), "%");
+    }
+}
@@ -0,0 +1,80 @@
+//! A separate role for the schema changes
+//!
+//! Creating, migrating and dropping the lock tables takes rights that the
+//! role of the application shouldn't need. With a `MaintenanceRole`, those
+//! statements run on short-lived connections of their own as that role,
+//! which then grants the user of each client what the lock statements need
+//! on the tables: reading and writing rows, and drawing fencing tokens.
+
+use postgres::Client;
+
+use crate::connection::Connector;
+use crate::errors::CockLockError;
+use crate::lock::{CockLockQueries, Operation};
+
+/// Where the user of a client is filled into the grant statements
+static GRANTEE_PLACEHOLDER: &str = "GRANTEE";
+
+/// The role the lock tables are created, migrated and dropped as, see
+/// `CockLockBuilder::with_maintenance_role`
+#[derive(Clone)]
+pub struct MaintenanceRole {
+    user: String,
+    password: Option<String>,
+}
+
+impl MaintenanceRole {
+    /// Connect as `user`, with the password of each client unless
+    /// `with_password` gives one
+    pub fn new<T: ToString>(user: T) -> Self {
+        Self {
+            user: user.to_string(),
+            password: None,
+        }
+    }
+
+    pub fn with_password<T: ToString>(mut self, password: T) -> Self {
+        self.password = Some(password.to_string());
+        self
+    }
+
+    /// Open a connection as the role to the database of a client, which must
+    /// have been given as a connection string or config
+    pub(crate) fn connect(
+        &self,
+        connector: Option<&Connector>,
+        label: &str,
+    ) -> Result<Client, CockLockError> {
+        let connector = connector.ok_or_else(|| {
+            CockLockError::Unsupported("maintenance roles for clients added as objects".to_owned())
+        })?;
+        connector
+            .as_user(&self.user, self.password.as_deref())
+            .connect()
+            .map_err(|err| CockLockError::postgres(err, Operation::Connect, label))
+    }
+}
+
+/// The statements granting the user `client` is connected as the lock
+/// tables of `queries`
+pub(crate) fn grants(
+    client: &mut Client,
+    queries: &[&CockLockQueries],
+    fairness_stats: bool,
+    audit: bool,
+) -> Result<String, postgres::Error> {
+    let grantee: String = client
+        .query_one("select quote_ident(current_user::text);", &[])?
+        .get(0);
+    let mut grants = String::new();
+    for queries in queries {
+        grants.push_str(&queries.grant);
+        if fairness_stats {
+            grants.push_str(&queries.grant_stats);
+        }
+        if audit {
+            grants.push_str(&queries.grant_audit);
+        }
+    }
+    Ok(grants.replace(GRANTEE_PLACEHOLDER, &grantee))
+}
@@ -0,0 +1,97 @@
+//! Payloads attached to locks
+//!
+//! During an incident the holder ID of a lock says little on its own. A
+//! holder can attach a payload when it takes a lock, like the pod it runs on
+//! and the work item it is processing, which is stored with the lock and
+//! shows up in `LockInfo::metadata`. Relocking without a payload keeps the
+//! one attached before, a new holder starts without one.
+//!
+//! With the `encryption` feature the metadata can be sealed before it is
+//! stored, see `encryption`.
+
+use postgres::Row;
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::errors::CockLockError;
+use crate::fencing::FencingToken;
+#[cfg(feature = "serde")]
+use crate::format::MetadataFormat;
+use crate::listing::LockInfo;
+use crate::lock::CockLock;
+
+impl CockLock {
+    /// Try to create a lock like `lock`, attaching `metadata` to it
+    ///
+    /// Relocking a lock the instance holds replaces its metadata.
+    pub fn lock_with_meta<T: ToString>(
+        &mut self,
+        lock_name: T,
+        timeout_ms: i32,
+        metadata: &[u8],
+    ) -> Result<FencingToken, CockLockError> {
+        self.require_table("lock metadata")?;
+        let lock_name = lock_name.to_string();
+        let timeout_ms = timeout_ms.into();
+        self.check_timeout(&lock_name, timeout_ms)?;
+        #[cfg(feature = "encryption")]
+        if let Some(metadata_key) = &self.metadata_key {
+            let sealed = metadata_key.seal(&self.table().namespaced(&lock_name), metadata)?;
+            return self.acquire(lock_name, None, timeout_ms, Some(&sealed));
+        }
+        self.acquire(lock_name, None, timeout_ms, Some(metadata))
+    }
+
+    /// Try to create a lock like `lock_with_meta`, attaching `metadata`
+    /// serialized with the format of `CockLockBuilder::with_metadata_format`
+    #[cfg(feature = "serde")]
+    pub fn lock_with_value<T: ToString, M: Serialize>(
+        &mut self,
+        lock_name: T,
+        timeout_ms: i32,
+        metadata: &M,
+    ) -> Result<FencingToken, CockLockError> {
+        let bytes = self.metadata_format.serialize(metadata)?;
+        self.lock_with_meta(lock_name, timeout_ms, &bytes)
+    }
+
+    /// Read a lock from a row with the columns of the listing queries,
+    /// opening its metadata if it is sealed
+    pub(crate) fn lock_info_from_row(&self, row: &Row) -> LockInfo {
+        #[cfg_attr(not(feature = "encryption"), allow(unused_mut))]
+        let mut lock = LockInfo::from_row(row);
+        #[cfg(feature = "encryption")]
+        if let (Some(metadata_key), Some(sealed)) = (&self.metadata_key, &lock.metadata) {
+            let lock_key = self.table().namespaced(&lock.lock_name);
+            lock.metadata = match metadata_key.open(&lock_key, sealed) {
+                Ok(metadata) => Some(metadata),
+                Err(err) => {
+                    log::warn!(
+                        target: "cocklock::metadata",
+                        "Ignoring the metadata of {}: {err}",
+                        lock.lock_name
+                    );
+                    None
+                }
+            };
+        }
+        lock
+    }
+}
+
+impl LockInfo {
+    /// The metadata of the lock deserialized from `format`, `None` if it has
+    /// none
+    #[cfg(feature = "serde")]
+    pub fn metadata_as<M: DeserializeOwned>(
+        &self,
+        format: MetadataFormat,
+    ) -> Result<Option<M>, CockLockError> {
+        self.metadata
+            .as_deref()
+            .map(|bytes| format.deserialize(bytes))
+            .transpose()
+    }
+}
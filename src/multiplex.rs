@@ -0,0 +1,256 @@
+//! Lock operations from many threads over a few connections
+//!
+//! A `CockLock` runs one operation at a time, so threads sharing one have to
+//! take turns, and giving each thread its own opens a connection per thread
+//! and database. `SharedCockLock` sits in between: operations from any number
+//! of threads are queued and run by a fixed set of workers, each with its own
+//! connections. Its operations take `&self` and it is `Send` and `Sync`, so
+//! it can be put in an `Arc` and used from every thread without a `Mutex`
+//! around it.
+
+use std::cell::Cell;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{spawn, JoinHandle};
+use std::time::Duration;
+
+use crate::errors::CockLockError;
+use crate::fencing::FencingToken;
+use crate::identity::Identity;
+use crate::lock::{CockLock, Expiry};
+
+type Job = Box<dyn FnOnce(&mut CockLock) + Send>;
+
+type Queue = Arc<Mutex<Receiver<Job>>>;
+
+thread_local! {
+    /// The queue of the pool the current thread works for, if any
+    static WORKING_FOR: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// A lock manager that can be shared between threads, see
+/// `CockLockBuilder::build_shared` and `CockLock::into_shared`
+///
+/// Every operation is handed to the next idle worker, the calling thread
+/// waits for its result. The workers share what they know about the locks of
+/// the ID, like `held_locks`, the locks taken by the local fallback and the
+/// standby claims, so a lock taken by one worker can be released by another.
+/// The journal and the reacquire delays are kept per worker; use
+/// `with_instance` to reach them.
+pub struct SharedCockLock {
+    id: Identity,
+    jobs: Option<Sender<Job>>,
+    queue: Queue,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl SharedCockLock {
+    pub(crate) fn new(mut instances: Vec<CockLock>) -> Self {
+        let id = instances[0].id;
+        let (first, others) = instances.split_at_mut(1);
+        for instance in others {
+            instance.held = first[0].held.share();
+            instance.standbys = first[0].standbys.share();
+            instance.turns = first[0].turns.share();
+            instance.local_locks = first[0].local_locks.share();
+        }
+        let (jobs, queue) = channel::<Job>();
+        let queue: Queue = Arc::new(Mutex::new(queue));
+        let workers = instances
+            .into_iter()
+            .map(|instance| {
+                let queue = queue.clone();
+                spawn(move || work(instance, &queue))
+            })
+            .collect();
+
+        Self {
+            id,
+            jobs: Some(jobs),
+            queue,
+            workers,
+        }
+    }
+
+    /// The ID shared by all connections
+    pub fn id(&self) -> Identity {
+        self.id
+    }
+
+    /// The number of workers, each with its own connections
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Run a closure on the instance of the next idle worker
+    ///
+    /// Fails with `CockLockError::ClientNotAvailable` if the closure panicked,
+    /// the worker carries on with the next operation. Fails with
+    /// `CockLockError::Reentrant` when called from a closure run by one of the
+    /// workers, which would wait for itself once every worker does the same.
+    pub fn with_instance<R, F>(&self, operation: F) -> Result<R, CockLockError>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut CockLock) -> R + Send + 'static,
+    {
+        if WORKING_FOR.with(Cell::get) == Some(pool_of(&self.queue)) {
+            return Err(CockLockError::Reentrant);
+        }
+        let (reply, result) = channel();
+        let job: Job = Box::new(move |instance| {
+            let _ = reply.send(operation(instance));
+        });
+        self.jobs
+            .as_ref()
+            .and_then(|jobs| jobs.send(job).ok())
+            .ok_or(CockLockError::ClientNotAvailable)?;
+        result.recv().map_err(|_| CockLockError::ClientNotAvailable)
+    }
+
+    /// See `CockLock::lock`
+    pub fn lock<T: ToString>(
+        &self,
+        lock_name: T,
+        timeout_ms: i32,
+    ) -> Result<FencingToken, CockLockError> {
+        let lock_name = lock_name.to_string();
+        self.with_instance(move |instance| instance.lock(lock_name, timeout_ms))?
+    }
+
+    /// See `CockLock::try_acquire`
+    pub fn try_acquire<T: ToString>(
+        &self,
+        lock_name: T,
+        timeout_ms: i32,
+    ) -> Result<FencingToken, CockLockError> {
+        let lock_name = lock_name.to_string();
+        self.with_instance(move |instance| instance.try_acquire(lock_name, timeout_ms))?
+    }
+
+    /// See `CockLock::lock_wait`
+    ///
+    /// The worker is busy while it waits, so waiting on more locks at once
+    /// than there are workers holds up every other operation.
+    pub fn lock_wait<T: ToString>(
+        &self,
+        lock_name: T,
+        timeout_ms: i32,
+        wait: Duration,
+    ) -> Result<FencingToken, CockLockError> {
+        let lock_name = lock_name.to_string();
+        self.with_instance(move |instance| instance.lock_wait(lock_name, timeout_ms, wait))?
+    }
+
+    /// See `CockLock::extend`
+    pub fn extend<T: ToString>(
+        &self,
+        lock_name: T,
+        additional_ms: i32,
+    ) -> Result<Expiry, CockLockError> {
+        let lock_name = lock_name.to_string();
+        self.with_instance(move |instance| instance.extend(lock_name, additional_ms))?
+    }
+
+    /// See `CockLock::extend_many`
+    pub fn extend_many<T: ToString>(
+        &self,
+        lock_names: &[T],
+        timeout_ms: i32,
+    ) -> Result<Vec<(String, bool)>, CockLockError> {
+        let lock_names: Vec<String> = lock_names.iter().map(ToString::to_string).collect();
+        self.with_instance(move |instance| instance.extend_many(&lock_names, timeout_ms))?
+    }
+
+    /// See `CockLock::refresh`
+    pub fn refresh<T: ToString>(&self, lock_name: T) -> Result<(), CockLockError> {
+        let lock_name = lock_name.to_string();
+        self.with_instance(move |instance| instance.refresh(lock_name))?
+    }
+
+    /// See `CockLock::unlock`
+    pub fn unlock<T: ToString>(&self, lock_name: T) -> Result<(), CockLockError> {
+        let lock_name = lock_name.to_string();
+        self.with_instance(move |instance| instance.unlock(lock_name))?
+    }
+
+    /// See `CockLock::unlock_all`, which releases the locks taken by every
+    /// worker since they share the ID
+    pub fn unlock_all(&self) -> Result<Vec<String>, CockLockError> {
+        self.with_instance(CockLock::unlock_all)?
+    }
+
+    /// See `CockLock::is_locked`
+    pub fn is_locked<T: ToString>(&self, lock_name: T) -> Result<bool, CockLockError> {
+        let lock_name = lock_name.to_string();
+        self.with_instance(move |instance| instance.is_locked(lock_name))?
+    }
+}
+
+impl CockLock {
+    /// Turn the instance into a `SharedCockLock` of `connections` workers,
+    /// whose operations take `&self`
+    ///
+    /// The instance becomes the first worker, the others connect on their own
+    /// with the same ID, so every client must have been given as a
+    /// connection string or config. Fails with `CockLockError::Unsupported`
+    /// with advisory locks, which only the connection that took them can
+    /// release.
+    pub fn into_shared(self, connections: usize) -> Result<SharedCockLock, CockLockError> {
+        self.require_table("shared instances")?;
+        let mut instances = vec![];
+        for _ in 1..connections.max(1) {
+            instances.push(self.sibling()?);
+        }
+        instances.insert(0, self);
+        Ok(SharedCockLock::new(instances))
+    }
+}
+
+impl Drop for SharedCockLock {
+    /// Let the workers finish the queued operations and close their
+    /// connections
+    fn drop(&mut self) {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Identify a pool by the address of its queue
+fn pool_of(queue: &Queue) -> usize {
+    Arc::as_ptr(queue) as usize
+}
+
+/// Run jobs until the queue is closed
+///
+/// A job that panics drops its reply channel, which fails the waiting call,
+/// and the worker moves on to the next one.
+fn work(mut instance: CockLock, queue: &Queue) {
+    WORKING_FOR.with(|pool| pool.set(Some(pool_of(queue))));
+    loop {
+        // The queue is only locked while waiting, not while the job runs
+        let job = match queue.lock() {
+            Ok(queue) => queue.recv(),
+            Err(_) => return,
+        };
+        match job {
+            Ok(job) => {
+                let _ = catch_unwind(AssertUnwindSafe(|| job(&mut instance)));
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SharedCockLock;
+
+    #[test]
+    fn shared_instances_can_be_used_from_any_thread() {
+        fn shareable<T: Send + Sync>() {}
+        shareable::<SharedCockLock>();
+    }
+}
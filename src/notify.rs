@@ -0,0 +1,109 @@
+//! Waiting for locks to change hands
+//!
+//! How a waiter learns that a lock may have been released depends on the
+//! deployment: polling works everywhere, while engines with push
+//! notifications can wake waiters as soon as it happens. `Notifier` hides the
+//! difference, and applications with their own event channel can implement
+//! it as well.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::deterministic::{Clock, SystemClock};
+use crate::errors::CockLockError;
+use crate::lock::CockLock;
+
+/// How often `PollingNotifier` checks a lock unless configured otherwise
+pub static DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Delivers the news that a lock may have changed
+pub trait Notifier: Send {
+    /// Block until `lock_name` may have been released, or `timeout` passed
+    ///
+    /// Waking up without a change is fine, the waiter checks the lock again
+    /// either way.
+    fn wait(&mut self, lock_name: &str, timeout: Duration) -> Result<(), CockLockError>;
+}
+
+/// Checks the lock again after a fixed interval, for any engine
+#[derive(Debug, Clone)]
+pub struct PollingNotifier {
+    interval: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for PollingNotifier {
+    fn default() -> Self {
+        Self::new(DEFAULT_POLL_INTERVAL)
+    }
+}
+
+impl PollingNotifier {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Replace the clock that is slept on, e.g. with a `VirtualClock` in tests
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+}
+
+impl Notifier for PollingNotifier {
+    fn wait(&mut self, _lock_name: &str, timeout: Duration) -> Result<(), CockLockError> {
+        self.clock.sleep(self.interval.min(timeout));
+        Ok(())
+    }
+}
+
+impl CockLock {
+    /// Wait until nobody holds a lock, without taking it
+    ///
+    /// Returns whether the lock was free before `timeout` ran out. The lock
+    /// may of course be taken again by anyone right after.
+    pub fn wait_until_free<T: ToString>(
+        &mut self,
+        lock_name: T,
+        timeout: Duration,
+        notifier: &mut dyn Notifier,
+    ) -> Result<bool, CockLockError> {
+        let lock_name = lock_name.to_string();
+        let deadline = self.clock.now() + timeout;
+        loop {
+            match self.read_if_unlocked(&lock_name, || ()) {
+                Ok(()) => return Ok(true),
+                Err(CockLockError::NotAvailable) => {}
+                Err(err) => return Err(err),
+            }
+
+            let now = self.clock.now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            notifier.wait(&lock_name, deadline - now)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Notifier, PollingNotifier};
+    use crate::deterministic::VirtualClock;
+
+    #[test]
+    fn polling_never_sleeps_past_the_timeout() {
+        let clock = VirtualClock::new();
+        let mut notifier = PollingNotifier::new(Duration::from_secs(1)).with_clock(clock.clone());
+
+        notifier.wait("task", Duration::from_secs(5)).unwrap();
+        assert_eq!(clock.elapsed(), Duration::from_secs(1));
+        notifier.wait("task", Duration::from_millis(300)).unwrap();
+        assert_eq!(clock.elapsed(), Duration::from_millis(1_300));
+    }
+}
@@ -0,0 +1,131 @@
+//! Callbacks for metrics on lock operations
+//!
+//! A `LockObserver` is told about every acquisition, contended attempt,
+//! release and failed renewal of an instance along with how long the
+//! operation took, e.g. to feed counters and histograms of a metrics
+//! library. Observers are called on the thread making the operation, after
+//! it finished, so they should return quickly. With
+//! `CockLockBuilder::with_success_sampling` they only hear about a sample of
+//! the acquisitions and releases.
+//!
+//! With the `tracing` feature the same operations also run in `tracing`
+//! spans named `cocklock.lock`, `cocklock.extend`, `cocklock.refresh` and
+//! `cocklock.unlock`, with the lock name and the outcome as fields.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::errors::CockLockError;
+
+/// Told about the lock operations of an instance, see
+/// `CockLockBuilder::with_observer`
+///
+/// Every method does nothing unless implemented.
+pub trait LockObserver: Debug + Send + Sync {
+    /// The instance took or relocked `lock_name`
+    fn on_acquired(&self, lock_name: &str, elapsed: Duration) {
+        let _ = (lock_name, elapsed);
+    }
+
+    /// `lock_name` was held by someone else
+    fn on_contended(&self, lock_name: &str, elapsed: Duration) {
+        let _ = (lock_name, elapsed);
+    }
+
+    /// The instance released `lock_name`
+    fn on_released(&self, lock_name: &str, elapsed: Duration) {
+        let _ = (lock_name, elapsed);
+    }
+
+    /// Extending or refreshing `lock_name` failed, with `NotAvailable` if the
+    /// instance didn't hold it anymore
+    fn on_renewal_failed(&self, lock_name: &str, error: &CockLockError, elapsed: Duration) {
+        let _ = (lock_name, error, elapsed);
+    }
+}
+
+/// The observers of an instance
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Observers(Arc<Vec<Arc<dyn LockObserver>>>);
+
+impl Observers {
+    pub fn new(observers: Vec<Arc<dyn LockObserver>>) -> Self {
+        Self(Arc::new(observers))
+    }
+
+    pub fn acquired(&self, lock_name: &str, elapsed: Duration) {
+        for observer in self.0.iter() {
+            observer.on_acquired(lock_name, elapsed);
+        }
+    }
+
+    pub fn contended(&self, lock_name: &str, elapsed: Duration) {
+        for observer in self.0.iter() {
+            observer.on_contended(lock_name, elapsed);
+        }
+    }
+
+    pub fn released(&self, lock_name: &str, elapsed: Duration) {
+        for observer in self.0.iter() {
+            observer.on_released(lock_name, elapsed);
+        }
+    }
+
+    pub fn renewal_failed(&self, lock_name: &str, error: &CockLockError, elapsed: Duration) {
+        for observer in self.0.iter() {
+            observer.on_renewal_failed(lock_name, error, elapsed);
+        }
+    }
+}
+
+/// The outcome of an operation as a span field
+#[cfg(feature = "tracing")]
+pub(crate) fn outcome<T>(result: &Result<T, CockLockError>) -> &'static str {
+    match result {
+        Ok(_) => "success",
+        Err(CockLockError::NotAvailable) => "not_available",
+        Err(_) => "failed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::{LockObserver, Observers};
+    use crate::errors::CockLockError;
+
+    #[derive(Debug, Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl LockObserver for Recorder {
+        fn on_acquired(&self, lock_name: &str, _: Duration) {
+            self.0.lock().unwrap().push(format!("acquired {lock_name}"));
+        }
+
+        fn on_renewal_failed(&self, lock_name: &str, error: &CockLockError, _: Duration) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("renewal of {lock_name} failed: {error}"));
+        }
+    }
+
+    #[test]
+    fn observers_only_hear_what_they_implement() {
+        let recorder = Arc::new(Recorder::default());
+        let observers = Observers::new(vec![recorder.clone(), recorder.clone()]);
+
+        observers.acquired("a", Duration::ZERO);
+        observers.contended("a", Duration::ZERO);
+        observers.released("a", Duration::ZERO);
+        observers.renewal_failed("a", &CockLockError::NotAvailable, Duration::ZERO);
+
+        let events = recorder.0.lock().unwrap();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[..2], ["acquired a", "acquired a"]);
+        assert!(events[2].starts_with("renewal of a failed"));
+    }
+}
@@ -0,0 +1,58 @@
+use postgres::types::ToSql;
+
+use crate::errors::CockLockError;
+use crate::lock::{CockLock, Operation};
+
+/// Whether a lock was held and how often it has been acquired so far
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LockState {
+    client: Option<usize>,
+    locked: bool,
+    epoch: i64,
+}
+
+impl CockLock {
+    /// Run a read-only closure while the lock is free, without taking it
+    ///
+    /// The state of the lock is read before and after the closure runs. If the
+    /// lock was held at the start, or was acquired by anyone while the closure
+    /// ran (even if it was released again since), the result of the closure is
+    /// discarded and `CockLockError::NotAvailable` is returned. The closure
+    /// must therefore not have side effects, it may run on data that is being
+    /// modified and should simply be retried in that case.
+    pub fn read_if_unlocked<T, F, R>(&mut self, lock_name: T, read: F) -> Result<R, CockLockError>
+    where
+        T: ToString,
+        F: FnOnce() -> R,
+    {
+        let lock_name = lock_name.to_string();
+        let before = self.lock_state(&lock_name)?;
+        if before.locked {
+            return Err(CockLockError::NotAvailable);
+        }
+
+        let value = read();
+
+        // A state read from another client can't be compared
+        if self.lock_state(&lock_name)? != before {
+            return Err(CockLockError::NotAvailable);
+        }
+        Ok(value)
+    }
+
+    fn lock_state(&mut self, lock_name: &str) -> Result<LockState, CockLockError> {
+        self.require_table("optimistic reads")?;
+        let params: &[&(dyn ToSql + Sync)] = &[&lock_name];
+        let (client, result) = self
+            .on_available_client(Operation::Inspect, |client, queries, _| {
+                client.query_one(&queries.lock_state, params)
+            });
+
+        let row = result?;
+        Ok(LockState {
+            client,
+            locked: row.get("locked"),
+            epoch: row.get("epoch"),
+        })
+    }
+}
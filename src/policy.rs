@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::errors::CockLockError;
+use crate::fencing::FencingToken;
+use crate::lock::{lease_ms, CockLock};
+
+/// Rules for the locks of a name or prefix, set once on the builder instead
+/// of at every call site
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LockPolicy {
+    /// The timeout used by `CockLock::lock_with_policy`
+    pub default_timeout_ms: Option<i64>,
+    /// The longest timeout the locks may be taken or extended with, which
+    /// also rules out locks that never expire
+    pub max_timeout_ms: Option<i64>,
+    /// How often holders are meant to renew the locks, for schedulers that
+    /// look it up with `CockLock::policy_for`
+    pub renewal_interval: Option<Duration>,
+    /// How long this instance waits before taking a lock again after
+    /// releasing it, giving other instances a chance to get it
+    pub reacquire_delay: Option<Duration>,
+}
+
+impl LockPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_default_timeout(mut self, timeout_ms: i32) -> Self {
+        self.default_timeout_ms = Some(timeout_ms.into());
+        self
+    }
+
+    /// Set the default timeout to `timeout`, rounded like the lease of
+    /// `CockLock::lock_for`
+    pub fn with_default_timeout_for(mut self, timeout: Duration) -> Self {
+        self.default_timeout_ms = Some(lease_ms(timeout));
+        self
+    }
+
+    pub fn with_max_timeout(mut self, timeout_ms: i32) -> Self {
+        self.max_timeout_ms = Some(timeout_ms.into());
+        self
+    }
+
+    /// Set the longest timeout to `timeout`, rounded like the lease of
+    /// `CockLock::lock_for`
+    pub fn with_max_timeout_for(mut self, timeout: Duration) -> Self {
+        self.max_timeout_ms = Some(lease_ms(timeout));
+        self
+    }
+
+    pub fn with_renewal_interval(mut self, interval: Duration) -> Self {
+        self.renewal_interval = Some(interval);
+        self
+    }
+
+    pub fn with_reacquire_delay(mut self, delay: Duration) -> Self {
+        self.reacquire_delay = Some(delay);
+        self
+    }
+}
+
+/// The policies of an instance by lock name and by prefix
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LockPolicies {
+    names: HashMap<String, LockPolicy>,
+    prefixes: Vec<(String, LockPolicy)>,
+}
+
+impl LockPolicies {
+    pub fn insert_name(&mut self, lock_name: String, policy: LockPolicy) {
+        self.names.insert(lock_name, policy);
+    }
+
+    pub fn insert_prefix(&mut self, prefix: String, policy: LockPolicy) {
+        self.prefixes.retain(|(existing, _)| *existing != prefix);
+        self.prefixes.push((prefix, policy));
+    }
+
+    /// The policy of a lock name, or else the one of its longest matching
+    /// prefix
+    pub fn get(&self, lock_name: &str) -> Option<&LockPolicy> {
+        self.names.get(lock_name).or_else(|| {
+            self.prefixes
+                .iter()
+                .filter(|(prefix, _)| lock_name.starts_with(prefix.as_str()))
+                .max_by_key(|(prefix, _)| prefix.len())
+                .map(|(_, policy)| policy)
+        })
+    }
+
+    /// Every policy with its name or prefix, and whether it is a prefix
+    pub fn iter(&self) -> impl Iterator<Item = (&str, bool, &LockPolicy)> {
+        let names = self
+            .names
+            .iter()
+            .map(|(name, policy)| (name.as_str(), false, policy));
+        let prefixes = self
+            .prefixes
+            .iter()
+            .map(|(prefix, policy)| (prefix.as_str(), true, policy));
+        names.chain(prefixes)
+    }
+}
+
+impl CockLock {
+    /// The policy that applies to a lock, if any
+    pub fn policy_for(&self, lock_name: &str) -> Option<&LockPolicy> {
+        self.policies.get(lock_name)
+    }
+
+    /// Try to create a lock with the default timeout of its policy
+    ///
+    /// Fails with `CockLockError::NoDefaultTimeout` if no policy with a
+    /// default timeout applies to the lock.
+    pub fn lock_with_policy<T: ToString>(
+        &mut self,
+        lock_name: T,
+    ) -> Result<FencingToken, CockLockError> {
+        let lock_name = lock_name.to_string();
+        let timeout_ms = self
+            .policies
+            .get(&lock_name)
+            .and_then(|policy| policy.default_timeout_ms)
+            .ok_or_else(|| CockLockError::NoDefaultTimeout(lock_name.clone()))?;
+        self.lock_ms(lock_name, timeout_ms)
+    }
+
+    /// Reject a timeout above the maximum of the policy of the lock
+    pub(crate) fn check_policy(
+        &self,
+        lock_name: &str,
+        timeout_ms: i64,
+    ) -> Result<(), CockLockError> {
+        let max_timeout_ms = self
+            .policies
+            .get(lock_name)
+            .and_then(|policy| policy.max_timeout_ms);
+        match max_timeout_ms {
+            Some(max_timeout_ms) if timeout_ms == 0 || timeout_ms > max_timeout_ms => Err(
+                CockLockError::LeaseTooLong(lock_name.to_owned(), max_timeout_ms),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{LockPolicies, LockPolicy};
+
+    #[test]
+    fn names_win_over_the_longest_prefix() {
+        let mut policies = LockPolicies::default();
+        let short = LockPolicy::new().with_max_timeout(1_000);
+        let long = LockPolicy::new().with_max_timeout(2_000);
+        let exact = LockPolicy::new().with_renewal_interval(Duration::from_secs(1));
+        policies.insert_prefix("jobs/".to_owned(), short.clone());
+        policies.insert_prefix("jobs/reports/".to_owned(), long.clone());
+        policies.insert_name("jobs/reports/daily".to_owned(), exact.clone());
+
+        assert_eq!(policies.get("jobs/cleanup"), Some(&short));
+        assert_eq!(policies.get("jobs/reports/weekly"), Some(&long));
+        assert_eq!(policies.get("jobs/reports/daily"), Some(&exact));
+        assert_eq!(policies.get("leader"), None);
+    }
+}
@@ -0,0 +1,70 @@
+//! Clients checked out of a connection pool
+//!
+//! With the `r2d2` feature, `CockLockBuilder::with_pool` takes an `r2d2`
+//! pool of postgres connections, e.g. made with `r2d2_postgres`, instead of
+//! connection strings. Every instance checks out one connection of the pool
+//! when it is built and gives it back when it is dropped, so instances that
+//! come and go reuse connections rather than opening their own, and the size
+//! of the pool caps how many there are. The pool tests connections before
+//! handing them out, and a pooled client that loses its connection while an
+//! instance holds it is replaced by another checkout, the way clients given
+//! as connection strings are reconnected.
+//!
+//! Async pools such as `deadpool` hand out `tokio_postgres` clients, which
+//! the blocking API of CockLock can't use.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use postgres::Client;
+
+use crate::errors::CockLockError;
+
+/// The connection of a client, owned by the instance or checked out of a
+/// pool until the instance is dropped
+pub enum ClientHandle {
+    Owned(Box<Client>),
+    Pooled(Box<dyn DerefMut<Target = Client> + Send>),
+}
+
+impl From<Client> for ClientHandle {
+    fn from(client: Client) -> Self {
+        ClientHandle::Owned(Box::new(client))
+    }
+}
+
+impl Deref for ClientHandle {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        match self {
+            ClientHandle::Owned(client) => client,
+            ClientHandle::Pooled(connection) => connection,
+        }
+    }
+}
+
+impl DerefMut for ClientHandle {
+    fn deref_mut(&mut self) -> &mut Client {
+        match self {
+            ClientHandle::Owned(client) => client,
+            ClientHandle::Pooled(connection) => connection,
+        }
+    }
+}
+
+/// Checks out a connection of a pool for a new instance
+pub(crate) type Checkout = Arc<dyn Fn() -> Result<ClientHandle, CockLockError> + Send + Sync>;
+
+#[cfg(feature = "r2d2")]
+pub(crate) fn checkout<M>(pool: r2d2::Pool<M>) -> Checkout
+where
+    M: r2d2::ManageConnection<Connection = Client>,
+{
+    Arc::new(move || {
+        let connection = pool
+            .get()
+            .map_err(|err| CockLockError::PoolError(err.to_string()))?;
+        Ok(ClientHandle::Pooled(Box::new(connection)))
+    })
+}
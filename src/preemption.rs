@@ -0,0 +1,53 @@
+use postgres::types::ToSql;
+
+use crate::errors::CockLockError;
+use crate::lock::{AcquisitionMode, CockLock, Operation};
+
+impl CockLock {
+    /// Ask the current holder of a lock to release it early
+    ///
+    /// Meant for an urgent waiter on preemptable work: the request is only a
+    /// flag on the lock, the holder decides whether and when to act on it by
+    /// checking `yield_requested`. Returns whether a holder other than this
+    /// instance was asked. The flag is cleared when the lock changes hands.
+    pub fn request_yield<T: ToString>(&mut self, lock_name: T) -> Result<bool, CockLockError> {
+        self.check_yield_support()?;
+        let lock_name = lock_name.to_string();
+        let id = self.id;
+        let params: &[&(dyn ToSql + Sync)] = &[&id, &lock_name];
+        let (_, result) = self
+            .on_available_client(Operation::RequestYield, |client, queries, _| {
+                client.execute(&queries.request_yield, params)
+            });
+
+        Ok(result? > 0)
+    }
+
+    /// Whether someone asked this instance to release a lock it holds early,
+    /// see `request_yield`
+    ///
+    /// Cooperative holders check this between units of work and unlock once
+    /// they reach a point where stopping is cheap.
+    pub fn yield_requested<T: ToString>(&mut self, lock_name: T) -> Result<bool, CockLockError> {
+        self.check_yield_support()?;
+        let lock_name = lock_name.to_string();
+        let id = self.id;
+        let params: &[&(dyn ToSql + Sync)] = &[&id, &lock_name];
+        let (_, result) = self.on_available_client(Operation::Inspect, |client, queries, _| {
+            client.query_opt(&queries.yield_requested, params)
+        });
+
+        Ok(result?.is_some())
+    }
+
+    /// Requests are written in place, which append-only tables forbid
+    fn check_yield_support(&self) -> Result<(), CockLockError> {
+        self.require_table("yield requests")?;
+        if self.acquisition_mode == AcquisitionMode::AppendOnly {
+            return Err(CockLockError::Unsupported(
+                "yield requests in append-only mode".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+}
@@ -1,11 +1,49 @@
+/// Tables created by older versions store `ttl_ms` as an int, which caps
+/// leases at about 24.8 days, and are widened in place
 pub static PG_TABLE_QUERY: &str = "
 create table if not exists TABLE_NAME (
     client_id uuid not null,
     lock_name text not null unique,
-    expires_at timestamp
+    expires_at timestamp,
+    ttl_ms bigint
 );
 
-create or replace function _lock_reap()
+alter table TABLE_NAME add column if not exists ttl_ms bigint;
+alter table TABLE_NAME add column if not exists resource_type text;
+alter table TABLE_NAME add column if not exists resource_id text;
+alter table TABLE_NAME add column if not exists last_seen_at timestamp;
+alter table TABLE_NAME add column if not exists yield_requested_at timestamp;
+alter table TABLE_NAME add column if not exists fencing_token bigint;
+alter table TABLE_NAME add column if not exists acquired_at timestamp;
+alter table TABLE_NAME add column if not exists namespace text not null default '';
+alter table TABLE_NAME add column if not exists metadata bytea;
+
+do $$
+begin
+    if (
+        select atttypid from pg_attribute
+        where attrelid = 'TABLE_NAME'::regclass and attname = 'ttl_ms'
+    ) = 'integer'::regtype then
+        alter table TABLE_NAME alter column ttl_ms type bigint;
+    end if;
+end;
+$$;
+
+create sequence if not exists TABLE_NAME_fencing_seq;
+
+create unique index if not exists TABLE_NAME_namespace_idx
+    on TABLE_NAME (namespace, lock_name);
+
+create unique index if not exists TABLE_NAME_namespace_resource_idx
+    on TABLE_NAME (namespace, resource_type, resource_id);
+
+create index if not exists TABLE_NAME_expiry_idx
+    on TABLE_NAME (expires_at, lock_name);
+
+create index if not exists TABLE_NAME_prefix_idx
+    on TABLE_NAME (lock_name text_pattern_ops);
+
+create or replace function _lock_reap_TABLE_NAME()
 returns trigger as $$
     begin
         delete from TABLE_NAME
@@ -19,28 +57,1423 @@ $$ language plpgsql;
 create or replace trigger _lock_reap_trigger
     before insert or update
     on TABLE_NAME
-    execute function _lock_reap();
+    execute function _lock_reap_TABLE_NAME();
+
+create table if not exists TABLE_NAME_epochs (
+    lock_name text primary key,
+    epoch bigint not null
+);
+
+create or replace function _lock_epoch_TABLE_NAME()
+returns trigger as $$
+    begin
+        insert into TABLE_NAME_epochs (lock_name, epoch)
+        values (case when new.namespace = '' then new.lock_name else new.namespace || chr(31) || new.lock_name end, 1)
+        on conflict (lock_name) do update
+            set epoch = TABLE_NAME_epochs.epoch + 1;
+        return null;
+    end;
+$$ language plpgsql;
+
+create or replace trigger _lock_epoch_trigger
+    after insert
+    on TABLE_NAME
+    for each row
+    execute function _lock_epoch_TABLE_NAME();
+
+create table if not exists TABLE_NAME_standbys (
+    lock_name text primary key,
+    client_id uuid not null,
+    expires_at timestamp not null
+);
+
+create table if not exists TABLE_NAME_values (
+    name text primary key,
+    value bytea not null,
+    expires_at timestamp not null
+);
+
+create table if not exists TABLE_NAME_instances (
+    client_id uuid primary key,
+    incarnation uuid not null,
+    seen_at timestamp not null
+);
+
+create table if not exists TABLE_NAME_intents (
+    intent_id uuid primary key,
+    client_id uuid not null,
+    lock_names text[] not null,
+    created_at timestamp not null
+);
+
+create table if not exists TABLE_NAME_jobs (
+    job_name text primary key,
+    last_run_at timestamp,
+    next_run_at timestamp not null
+);
+";
+
+/// CockroachDB has no trigger functions, so expired locks are taken over by
+/// the lock statement instead of being reaped before it
+pub static CRDB_TABLE_QUERY: &str = "
+create table if not exists TABLE_NAME (
+    client_id uuid not null,
+    lock_name text not null unique,
+    expires_at timestamp,
+    ttl_ms bigint
+);
+
+alter table TABLE_NAME add column if not exists ttl_ms bigint;
+alter table TABLE_NAME add column if not exists resource_type text;
+alter table TABLE_NAME add column if not exists resource_id text;
+alter table TABLE_NAME add column if not exists last_seen_at timestamp;
+alter table TABLE_NAME add column if not exists yield_requested_at timestamp;
+alter table TABLE_NAME add column if not exists fencing_token bigint;
+alter table TABLE_NAME add column if not exists acquired_at timestamp;
+alter table TABLE_NAME add column if not exists namespace text not null default '';
+alter table TABLE_NAME add column if not exists metadata bytea;
+
+create sequence if not exists TABLE_NAME_fencing_seq;
+
+create unique index if not exists TABLE_NAME_namespace_idx
+    on TABLE_NAME (namespace, lock_name);
+
+create unique index if not exists TABLE_NAME_namespace_resource_idx
+    on TABLE_NAME (namespace, resource_type, resource_id);
+
+create index if not exists TABLE_NAME_expiry_idx
+    on TABLE_NAME (expires_at, lock_name);
+
+create table if not exists TABLE_NAME_epochs (
+    lock_name text primary key,
+    epoch bigint not null
+);
+
+create table if not exists TABLE_NAME_standbys (
+    lock_name text primary key,
+    client_id uuid not null,
+    expires_at timestamp not null
+);
+
+create table if not exists TABLE_NAME_values (
+    name text primary key,
+    value bytea not null,
+    expires_at timestamp not null
+);
+
+create table if not exists TABLE_NAME_instances (
+    client_id uuid primary key,
+    incarnation uuid not null,
+    seen_at timestamp not null
+);
+
+create table if not exists TABLE_NAME_intents (
+    intent_id uuid primary key,
+    client_id uuid not null,
+    lock_names text[] not null,
+    created_at timestamp not null
+);
+
+create table if not exists TABLE_NAME_jobs (
+    job_name text primary key,
+    last_run_at timestamp,
+    next_run_at timestamp not null
+);
+";
+
+/// Lock names and resources stay unique on their own until an instance with
+/// a namespace drops the constraint and the index of tables created by older
+/// versions, see `CockLockBuilder::with_namespace`
+pub static PG_NAMESPACES_QUERY: &str = "
+do $$
+declare
+    unique_name text;
+    resource_index text;
+begin
+    select conname into unique_name
+    from pg_constraint
+    where
+        conrelid = 'TABLE_NAME'::regclass
+        and contype = 'u'
+        and conkey = array[(
+            select attnum from pg_attribute
+            where attrelid = 'TABLE_NAME'::regclass and attname = 'lock_name'
+        )];
+    if unique_name is not null then
+        execute format('alter table %s drop constraint %I', 'TABLE_NAME'::regclass, unique_name);
+    end if;
+
+    select indexrelid::regclass::text into resource_index
+    from pg_index
+    where
+        indrelid = 'TABLE_NAME'::regclass
+        and indisunique
+        and indnatts = 2
+        and indkey[0] = (
+            select attnum from pg_attribute
+            where attrelid = 'TABLE_NAME'::regclass and attname = 'resource_type'
+        )
+        and indkey[1] = (
+            select attnum from pg_attribute
+            where attrelid = 'TABLE_NAME'::regclass and attname = 'resource_id'
+        );
+    if resource_index is not null then
+        execute format('drop index %s', resource_index);
+    end if;
+end;
+$$;
+";
+
+pub static CRDB_NAMESPACES_QUERY: &str = "
+drop index if exists TABLE_NAME@TABLE_NAME_lock_name_key cascade;
+drop index if exists TABLE_NAME@TABLE_NAME_resource_idx;
+";
+
+/// Only created when fairness statistics are enabled, after which every
+/// instance using the table contributes to them
+pub static PG_STATS_TABLE_QUERY: &str = "
+create table if not exists TABLE_NAME_stats (
+    lock_name text not null,
+    client_id uuid not null,
+    acquisitions bigint not null,
+    last_acquired_at timestamp not null,
+    primary key (lock_name, client_id)
+);
+
+create or replace function _lock_stats_TABLE_NAME()
+returns trigger as $$
+    begin
+        insert into TABLE_NAME_stats (lock_name, client_id, acquisitions, last_acquired_at)
+        values (case when new.namespace = '' then new.lock_name else new.namespace || chr(31) || new.lock_name end, new.client_id, 1, now())
+        on conflict (lock_name, client_id) do update
+            set
+                acquisitions = TABLE_NAME_stats.acquisitions + 1,
+                last_acquired_at = excluded.last_acquired_at;
+        return null;
+    end;
+$$ language plpgsql;
+
+create or replace trigger _lock_stats_trigger
+    after insert
+    on TABLE_NAME
+    for each row
+    execute function _lock_stats_TABLE_NAME();
+";
+
+/// Only created when the audit log is enabled, after which every change to
+/// the lock table is recorded, whichever instance makes it. Updates that
+/// don't touch `last_seen_at`, like yield requests, aren't lock operations
+/// and are left out.
+pub static PG_AUDIT_TABLE_QUERY: &str = "
+create table if not exists TABLE_NAME_audit (
+    event_id bigserial primary key,
+    namespace text not null,
+    lock_name text not null,
+    client_id uuid not null,
+    event text not null,
+    fencing_token bigint,
+    expires_at timestamptz,
+    recorded_at timestamptz not null default now()
+);
+
+create index if not exists TABLE_NAME_audit_idx
+    on TABLE_NAME_audit (namespace, lock_name, recorded_at);
+
+create or replace function _lock_audit_TABLE_NAME()
+returns trigger as $$
+    begin
+        if tg_op = 'DELETE' then
+            insert into TABLE_NAME_audit (
+                namespace, lock_name, client_id, event, fencing_token, expires_at
+            )
+            values (
+                old.namespace,
+                old.lock_name,
+                old.client_id,
+                case
+                    when old.expires_at is not null and now() > old.expires_at then 'expired'
+                    else 'released'
+                end,
+                old.fencing_token,
+                old.expires_at
+            );
+        elsif tg_op = 'INSERT' or old.client_id <> new.client_id
+            or old.last_seen_at is distinct from new.last_seen_at then
+            insert into TABLE_NAME_audit (
+                namespace, lock_name, client_id, event, fencing_token, expires_at
+            )
+            values (
+                new.namespace,
+                new.lock_name,
+                new.client_id,
+                case
+                    when tg_op = 'UPDATE' and old.client_id = new.client_id then 'extended'
+                    else 'acquired'
+                end,
+                new.fencing_token,
+                new.expires_at
+            );
+        end if;
+        return null;
+    end;
+$$ language plpgsql;
+
+create or replace trigger _lock_audit_trigger
+    after insert or update or delete
+    on TABLE_NAME
+    for each row
+    execute function _lock_audit_TABLE_NAME();
+";
+
+pub static PG_READ_ONLY_QUERY: &str = "
+select pg_is_in_recovery() or current_setting('transaction_read_only') = 'on';
 ";
 
 pub static PG_LOCK_QUERY: &str = "
-insert into TABLE_NAME (client_id, lock_name, expires_at)
-select $1, $2, now() + ($3::int || ' milliseconds')::interval
-on conflict (lock_name) do update
-    set expires_at = now() + ($3::int || ' milliseconds')::interval
+insert into TABLE_NAME (
+    client_id, lock_name, expires_at, ttl_ms, resource_type, resource_id, last_seen_at,
+    fencing_token, acquired_at, namespace, metadata
+)
+select
+    $1,
+    $2,
+    case when $3::bigint = 0 then null else now() + ($3::bigint || ' milliseconds')::interval end,
+    $3::bigint,
+    $4,
+    $5,
+    now(),
+    nextval('TABLE_NAME_fencing_seq'),
+    now(),
+    NAMESPACE,
+    $6::bytea
+where
+    not exists (
+        select from TABLE_NAME_standbys
+        where
+            lock_name = NAMESPACE_KEY || $2
+            and client_id <> $1
+            and expires_at >= now()
+    )
+    or exists (
+        select from TABLE_NAME
+        where
+            namespace = NAMESPACE
+            and lock_name = $2
+            and client_id = $1
+            and (expires_at is null or expires_at >= now())
+    )
+on conflict (namespace, lock_name) do update
+    set
+        expires_at = excluded.expires_at,
+        ttl_ms = excluded.ttl_ms,
+        resource_type = excluded.resource_type,
+        resource_id = excluded.resource_id,
+        last_seen_at = excluded.last_seen_at,
+        fencing_token = coalesce(TABLE_NAME.fencing_token, excluded.fencing_token),
+        acquired_at = coalesce(TABLE_NAME.acquired_at, excluded.acquired_at),
+        metadata = coalesce(excluded.metadata, TABLE_NAME.metadata)
     where
         TABLE_NAME.client_id = excluded.client_id
-        and TABLE_NAME.lock_name = excluded.lock_name;
+        and TABLE_NAME.lock_name = excluded.lock_name
+returning fencing_token;
+";
+
+pub static CRDB_LOCK_QUERY: &str = "
+insert into TABLE_NAME (
+    client_id, lock_name, expires_at, ttl_ms, resource_type, resource_id, last_seen_at,
+    fencing_token, acquired_at, namespace, metadata
+)
+select
+    $1,
+    $2,
+    case when $3::bigint = 0 then null else now() + ($3::bigint || ' milliseconds')::interval end,
+    $3::bigint,
+    $4,
+    $5,
+    now(),
+    nextval('TABLE_NAME_fencing_seq'),
+    now(),
+    NAMESPACE,
+    $6::bytea
+where
+    not exists (
+        select from TABLE_NAME_standbys
+        where
+            lock_name = NAMESPACE_KEY || $2
+            and client_id <> $1
+            and expires_at >= now()
+    )
+    or exists (
+        select from TABLE_NAME
+        where
+            namespace = NAMESPACE
+            and lock_name = $2
+            and client_id = $1
+            and (expires_at is null or expires_at >= now())
+    )
+on conflict (namespace, lock_name) do update
+    set
+        client_id = excluded.client_id,
+        expires_at = excluded.expires_at,
+        ttl_ms = excluded.ttl_ms,
+        resource_type = excluded.resource_type,
+        resource_id = excluded.resource_id,
+        last_seen_at = excluded.last_seen_at,
+        yield_requested_at = case
+            when TABLE_NAME.client_id = excluded.client_id then TABLE_NAME.yield_requested_at
+        end,
+        fencing_token = case
+            when
+                TABLE_NAME.client_id = excluded.client_id
+                and (TABLE_NAME.expires_at is null or TABLE_NAME.expires_at >= now())
+            then coalesce(TABLE_NAME.fencing_token, excluded.fencing_token)
+            else excluded.fencing_token
+        end,
+        acquired_at = case
+            when
+                TABLE_NAME.client_id = excluded.client_id
+                and (TABLE_NAME.expires_at is null or TABLE_NAME.expires_at >= now())
+            then coalesce(TABLE_NAME.acquired_at, excluded.acquired_at)
+            else excluded.acquired_at
+        end,
+        metadata = case
+            when
+                TABLE_NAME.client_id = excluded.client_id
+                and (TABLE_NAME.expires_at is null or TABLE_NAME.expires_at >= now())
+            then coalesce(excluded.metadata, TABLE_NAME.metadata)
+            else excluded.metadata
+        end
+    where
+        TABLE_NAME.client_id = excluded.client_id
+        or TABLE_NAME.expires_at < now()
+returning fencing_token;
+";
+
+/// Counts a new epoch whenever the lock statement `LOCK_QUERY` hands a lock
+/// to a new holder, for tables without the epoch trigger
+///
+/// The lock statement is left unindented so that `PG_QUOTA_CONDITION` still
+/// finds its condition.
+pub static EPOCH_COUNTING_LOCK_QUERY: &str = "
+with previous as (
+    select client_id, expires_at
+    from TABLE_NAME
+    where namespace = NAMESPACE and lock_name = $2
+),
+taken as (LOCK_QUERY
+),
+counted as (
+    insert into TABLE_NAME_epochs (lock_name, epoch)
+    select NAMESPACE_KEY || $2, 1
+    from taken
+    where not exists (
+        select from previous
+        where
+            client_id = $1
+            and (expires_at is null or expires_at >= now())
+    )
+    on conflict (lock_name) do update
+        set epoch = TABLE_NAME_epochs.epoch + 1
+)
+select fencing_token from taken;
+";
+
+/// Counts a new epoch for a lock handed to a new holder by `TRANSFER_QUERY`
+pub static EPOCH_COUNTING_TRANSFER_QUERY: &str = "
+with transferred as (TRANSFER_QUERY
+),
+counted as (
+    insert into TABLE_NAME_epochs (lock_name, epoch)
+    select NAMESPACE_KEY || $2, 1
+    from transferred
+    on conflict (lock_name) do update
+        set epoch = TABLE_NAME_epochs.epoch + 1
+)
+select fencing_token from transferred;
+";
+
+/// Hands a lock held by `$1` to `$3` with a new fencing token, keeping its
+/// lease
+pub static PG_TRANSFER_QUERY: &str = "
+update TABLE_NAME
+set
+    client_id = $3,
+    fencing_token = nextval('TABLE_NAME_fencing_seq'),
+    acquired_at = now(),
+    last_seen_at = now(),
+    yield_requested_at = null
+where
+    client_id = $1
+    and namespace = NAMESPACE
+    and lock_name = $2
+    and (expires_at is null or expires_at >= now())
+returning fencing_token
+";
+
+/// Prepended to the condition of the lock queries when the quota of the
+/// instance is enforced by the database, see `LockQuota::in_database`
+pub static PG_QUOTA_CONDITION: &str = "
+where
+    (
+        select count(*)
+        from TABLE_NAME
+        where
+            client_id = $1
+            and namespace = NAMESPACE
+            and lock_name <> $2
+            and (expires_at is null or expires_at >= now())
+    ) < $7
+    and (
+";
+
+pub static PG_REAP_QUERY: &str = "
+delete from TABLE_NAME
+where
+    expires_at is not null
+    and expires_at < now();
+";
+
+pub static PG_HELD_QUERY: &str = "
+select coalesce(fencing_token, 0) as fencing_token
+from TABLE_NAME
+where
+    client_id = $1
+    and namespace = NAMESPACE
+    and lock_name = $2
+    and (expires_at is null or expires_at >= now());
+";
+
+pub static PG_RENEW_QUERY: &str = "
+update TABLE_NAME
+set
+    expires_at = case when $3::bigint = 0 then null else now() + ($3::bigint || ' milliseconds')::interval end,
+    ttl_ms = $3::bigint,
+    last_seen_at = now()
+where
+    client_id = $1
+    and namespace = NAMESPACE
+    and lock_name = any($2)
+returning lock_name;
+";
+
+pub static PG_REFRESH_QUERY: &str = "
+update TABLE_NAME
+set
+    expires_at = case when ttl_ms = 0 then null else now() + (ttl_ms || ' milliseconds')::interval end,
+    last_seen_at = now()
+where
+    client_id = $1
+    and namespace = NAMESPACE
+    and lock_name = $2
+    and ttl_ms is not null;
 ";
 
 pub static PG_UNLOCK_QUERY: &str = "
 delete from TABLE_NAME
 where
     client_id = $1
+    and namespace = NAMESPACE
     and lock_name = $2;
 ";
 
+pub static PG_UNLOCK_PREFIX_QUERY: &str = "
+delete from TABLE_NAME
+where
+    client_id = $1
+    and namespace = NAMESPACE
+    and lock_name like $2
+returning lock_name;
+";
+
+/// Releases a lock whoever holds it, returning the holder
+pub static PG_FORCE_UNLOCK_QUERY: &str = "
+delete from TABLE_NAME
+where
+    namespace = NAMESPACE
+    and lock_name = $1
+    and (expires_at is null or expires_at >= now())
+returning client_id;
+";
+
+/// Releasing a lock with notifications also tells the listeners of the
+/// `cocklock:TABLE_KEY` channel which lock was released
+pub static PG_NOTIFY_UNLOCK_QUERY: &str = "
+with released as (
+    delete from TABLE_NAME
+    where
+        client_id = $1
+        and namespace = NAMESPACE
+        and lock_name = $2
+    returning lock_name
+)
+select pg_notify('cocklock:TABLE_KEY', NAMESPACE_KEY || lock_name)
+from released;
+";
+
+pub static PG_NOTIFY_UNLOCK_PREFIX_QUERY: &str = "
+with released as (
+    delete from TABLE_NAME
+    where
+        client_id = $1
+        and namespace = NAMESPACE
+        and lock_name like $2
+    returning lock_name
+)
+select lock_name, pg_notify('cocklock:TABLE_KEY', NAMESPACE_KEY || lock_name)
+from released;
+";
+
+pub static PG_NOTIFY_FORCE_UNLOCK_QUERY: &str = "
+with released as (
+    delete from TABLE_NAME
+    where
+        namespace = NAMESPACE
+        and lock_name = $1
+        and (expires_at is null or expires_at >= now())
+    returning client_id, lock_name
+)
+select client_id, pg_notify('cocklock:TABLE_KEY', NAMESPACE_KEY || lock_name)
+from released;
+";
+
+pub static PG_ACTIVE_LOCKS_QUERY: &str = "
+select client_id, lock_name
+from TABLE_NAME
+where
+    namespace = NAMESPACE
+    and (expires_at is null or expires_at >= now());
+";
+
+pub static PG_EXPORT_QUERY: &str = "
+select
+    client_id,
+    lock_name,
+    (extract(epoch from (expires_at - now()::timestamp)) * 1000)::bigint as remaining_ms,
+    ttl_ms
+from TABLE_NAME
+where
+    namespace = NAMESPACE
+    and (expires_at is null or expires_at >= now());
+";
+
+pub static PG_LIST_PREFIX_QUERY: &str = "
+select
+    client_id,
+    lock_name,
+    (extract(epoch from (expires_at - now()::timestamp)) * 1000)::bigint as remaining_ms,
+    (extract(epoch from (now()::timestamp - last_seen_at)) * 1000)::bigint as silent_ms,
+    (extract(epoch from (now()::timestamp - acquired_at)) * 1000)::bigint as held_ms,
+    (
+        select epoch from TABLE_NAME_epochs
+        where TABLE_NAME_epochs.lock_name = NAMESPACE_KEY || TABLE_NAME.lock_name
+    ) as epoch,
+    metadata
+from TABLE_NAME
+where
+    namespace = NAMESPACE
+    and lock_name like $1
+    and (expires_at is null or expires_at >= now())
+order by lock_name;
+";
+
+pub static PG_LIST_BY_NAME_QUERY: &str = "
+select
+    client_id,
+    lock_name,
+    expires_at::text as expires_at_key,
+    (extract(epoch from (expires_at - now()::timestamp)) * 1000)::bigint as remaining_ms,
+    (extract(epoch from (now()::timestamp - last_seen_at)) * 1000)::bigint as silent_ms,
+    (extract(epoch from (now()::timestamp - acquired_at)) * 1000)::bigint as held_ms,
+    (
+        select epoch from TABLE_NAME_epochs
+        where TABLE_NAME_epochs.lock_name = NAMESPACE_KEY || TABLE_NAME.lock_name
+    ) as epoch,
+    metadata
+from TABLE_NAME
+where
+    namespace = NAMESPACE
+    and case
+        when $1::bool then expires_at < now()
+        else expires_at is null or expires_at >= now()
+    end
+    and ($2::uuid is null or client_id = $2)
+    and ($3::text is null or lock_name > $3)
+order by lock_name
+limit $4;
+";
+
+/// Locks without an expiry sort last, after every lock that expires
+pub static PG_LIST_BY_EXPIRY_QUERY: &str = "
+select
+    client_id,
+    lock_name,
+    expires_at::text as expires_at_key,
+    (extract(epoch from (expires_at - now()::timestamp)) * 1000)::bigint as remaining_ms,
+    (extract(epoch from (now()::timestamp - last_seen_at)) * 1000)::bigint as silent_ms,
+    (extract(epoch from (now()::timestamp - acquired_at)) * 1000)::bigint as held_ms,
+    (
+        select epoch from TABLE_NAME_epochs
+        where TABLE_NAME_epochs.lock_name = NAMESPACE_KEY || TABLE_NAME.lock_name
+    ) as epoch,
+    metadata
+from TABLE_NAME
+where
+    namespace = NAMESPACE
+    and case
+        when $1::bool then expires_at < now()
+        else expires_at is null or expires_at >= now()
+    end
+    and ($2::uuid is null or client_id = $2)
+    and (
+        $3::text is null
+        or (expires_at, lock_name) > ($5::text::timestamp, $3)
+        or (expires_at is null and ($5::text::timestamp is not null or lock_name > $3))
+    )
+order by expires_at, lock_name
+limit $4;
+";
+
+pub static PG_LIST_RESOURCE_TYPE_QUERY: &str = "
+select
+    client_id,
+    lock_name,
+    resource_type,
+    resource_id,
+    (extract(epoch from (expires_at - now()::timestamp)) * 1000)::bigint as remaining_ms,
+    (extract(epoch from (now()::timestamp - last_seen_at)) * 1000)::bigint as silent_ms,
+    (extract(epoch from (now()::timestamp - acquired_at)) * 1000)::bigint as held_ms,
+    (
+        select epoch from TABLE_NAME_epochs
+        where TABLE_NAME_epochs.lock_name = NAMESPACE_KEY || TABLE_NAME.lock_name
+    ) as epoch,
+    metadata
+from TABLE_NAME
+where
+    namespace = NAMESPACE
+    and resource_type = $1
+    and (expires_at is null or expires_at >= now())
+order by resource_id;
+";
+
+pub static PG_REQUEST_YIELD_QUERY: &str = "
+update TABLE_NAME
+set yield_requested_at = coalesce(yield_requested_at, now())
+where
+    namespace = NAMESPACE
+    and lock_name = $2
+    and client_id <> $1
+    and (expires_at is null or expires_at >= now());
+";
+
+pub static PG_YIELD_REQUESTED_QUERY: &str = "
+select from TABLE_NAME
+where
+    client_id = $1
+    and namespace = NAMESPACE
+    and lock_name = $2
+    and yield_requested_at is not null;
+";
+
+pub static PG_REGISTER_STANDBY_QUERY: &str = "
+insert into TABLE_NAME_standbys (lock_name, client_id, expires_at)
+values (NAMESPACE_KEY || $2, $1, now() + ($3::bigint || ' milliseconds')::interval)
+on conflict (lock_name) do update
+    set
+        client_id = excluded.client_id,
+        expires_at = excluded.expires_at
+    where
+        TABLE_NAME_standbys.client_id = excluded.client_id
+        or TABLE_NAME_standbys.expires_at < now();
+";
+
+pub static PG_CANCEL_STANDBY_QUERY: &str = "
+delete from TABLE_NAME_standbys
+where
+    lock_name = NAMESPACE_KEY || $2
+    and client_id = $1;
+";
+
+pub static PG_LOCK_STATE_QUERY: &str = "
+select
+    exists (
+        select from TABLE_NAME
+        where
+            namespace = NAMESPACE
+            and lock_name = $1
+            and (expires_at is null or expires_at >= now())
+    ) as locked,
+    coalesce(
+        (select epoch from TABLE_NAME_epochs where lock_name = NAMESPACE_KEY || $1),
+        0
+    ) as epoch;
+";
+
+pub static PG_LOCK_INFO_QUERY: &str = "
+select
+    client_id,
+    lock_name,
+    (extract(epoch from (expires_at - now()::timestamp)) * 1000)::bigint as remaining_ms,
+    (extract(epoch from (now()::timestamp - last_seen_at)) * 1000)::bigint as silent_ms,
+    (extract(epoch from (now()::timestamp - acquired_at)) * 1000)::bigint as held_ms,
+    (
+        select epoch from TABLE_NAME_epochs
+        where TABLE_NAME_epochs.lock_name = NAMESPACE_KEY || TABLE_NAME.lock_name
+    ) as epoch,
+    metadata
+from TABLE_NAME
+where
+    namespace = NAMESPACE
+    and lock_name = $1
+    and (expires_at is null or expires_at >= now());
+";
+
+pub static PG_OWNED_LOCKS_QUERY: &str = "
+select
+    client_id,
+    lock_name,
+    (extract(epoch from (expires_at - now()::timestamp)) * 1000)::bigint as remaining_ms,
+    (extract(epoch from (now()::timestamp - last_seen_at)) * 1000)::bigint as silent_ms,
+    (extract(epoch from (now()::timestamp - acquired_at)) * 1000)::bigint as held_ms,
+    (
+        select epoch from TABLE_NAME_epochs
+        where TABLE_NAME_epochs.lock_name = NAMESPACE_KEY || TABLE_NAME.lock_name
+    ) as epoch,
+    metadata
+from TABLE_NAME
+where
+    client_id = $1
+    and namespace = NAMESPACE
+    and (expires_at is null or expires_at >= now())
+order by lock_name;
+";
+
+#[cfg(feature = "serde")]
+pub static PG_CACHED_VALUE_QUERY: &str = "
+select value from TABLE_NAME_values
+where
+    name = NAMESPACE_KEY || $1
+    and expires_at >= now();
+";
+
+#[cfg(feature = "serde")]
+pub static PG_STORE_VALUE_QUERY: &str = "
+insert into TABLE_NAME_values (name, value, expires_at)
+values (NAMESPACE_KEY || $1, $2, now() + ($3::bigint || ' milliseconds')::interval)
+on conflict (name) do update
+    set
+        value = excluded.value,
+        expires_at = excluded.expires_at;
+";
+
+/// Register the ID `$1` for the incarnation `$2`, unless another incarnation
+/// registered it less than `$3` milliseconds ago; returns no row then
+pub static PG_HEARTBEAT_QUERY: &str = "
+insert into TABLE_NAME_instances (client_id, incarnation, seen_at)
+values ($1, $2, now())
+on conflict (client_id) do update
+    set
+        incarnation = excluded.incarnation,
+        seen_at = excluded.seen_at
+    where
+        TABLE_NAME_instances.incarnation = excluded.incarnation
+        or TABLE_NAME_instances.seen_at < now() - ($3::bigint || ' milliseconds')::interval
+returning incarnation;
+";
+
+/// Record that the instance `$2` is about to take the locks `$3`, see
+/// `CockLock::lock_all`
+pub static PG_RECORD_INTENT_QUERY: &str = "
+insert into TABLE_NAME_intents (intent_id, client_id, lock_names, created_at)
+values ($1, $2, $3, now());
+";
+
+pub static PG_CLEAR_INTENT_QUERY: &str = "
+delete from TABLE_NAME_intents
+where intent_id = $1;
+";
+
+pub static PG_OWN_INTENTS_QUERY: &str = "
+select
+    intent_id,
+    lock_names,
+    (extract(epoch from (now()::timestamp - created_at)) * 1000)::bigint as age_ms
+from TABLE_NAME_intents
+where client_id = $1
+order by created_at;
+";
+
+/// When the job `$1` last ran and is due next, along with the time of the
+/// database, see `JobLocker::run_due`
+pub static PG_JOB_RUNS_QUERY: &str = "
+select jobs.last_run_at, jobs.next_run_at, now()::timestamp as now
+from (select 1) as anchor
+left join TABLE_NAME_jobs as jobs on jobs.job_name = NAMESPACE_KEY || $1;
+";
+
+pub static PG_RECORD_JOB_RUNS_QUERY: &str = "
+insert into TABLE_NAME_jobs (job_name, last_run_at, next_run_at)
+values (NAMESPACE_KEY || $1, $2, $3)
+on conflict (job_name) do update
+    set
+        last_run_at = excluded.last_run_at,
+        next_run_at = excluded.next_run_at;
+";
+
+pub static PG_STANDBYS_QUERY: &str = "
+select
+    substr(lock_name, length(NAMESPACE_KEY) + 1) as lock_name,
+    client_id,
+    (extract(epoch from (expires_at - now()::timestamp)) * 1000)::bigint as remaining_ms
+from TABLE_NAME_standbys
+where
+    expires_at >= now()
+    and left(lock_name, length(NAMESPACE_KEY)) = NAMESPACE_KEY
+    and strpos(substr(lock_name, length(NAMESPACE_KEY) + 1), chr(31)) = 0
+order by lock_name;
+";
+
+pub static PG_INSTANCES_QUERY: &str = "
+select
+    client_id,
+    (extract(epoch from (now()::timestamp - seen_at)) * 1000)::bigint as silent_ms
+from TABLE_NAME_instances
+order by client_id;
+";
+
+pub static PG_STATS_QUERY: &str = "
+select
+    client_id,
+    acquisitions,
+    (extract(epoch from (now()::timestamp - last_acquired_at)) * 1000)::bigint as since_last_ms
+from TABLE_NAME_stats
+where lock_name = NAMESPACE_KEY || $1
+order by acquisitions desc, client_id;
+";
+
+pub static PG_RESET_STATS_QUERY: &str = "
+delete from TABLE_NAME_stats
+where lock_name = NAMESPACE_KEY || $1;
+";
+
+pub static PG_AUDIT_EVENTS_QUERY: &str = "
+select lock_name, client_id, event, fencing_token, expires_at, recorded_at
+from TABLE_NAME_audit
+where
+    namespace = NAMESPACE
+    and ($1::text is null or lock_name = $1)
+    and ($2::timestamptz is null or recorded_at >= $2)
+    and ($3::timestamptz is null or recorded_at <= $3)
+order by event_id desc
+limit $4;
+";
+
+pub static PG_IMPORT_QUERY: &str = "
+insert into TABLE_NAME (client_id, lock_name, expires_at, ttl_ms, fencing_token, namespace)
+select
+    $1,
+    $2,
+    now() + ($3::bigint || ' milliseconds')::interval,
+    $4::bigint,
+    nextval('TABLE_NAME_fencing_seq'),
+    NAMESPACE
+on conflict (namespace, lock_name) do nothing;
+";
+
+/// Leases keep their fencing token, and the sequence and epochs of the new
+/// table are moved past the ones of the old table, see `PG_CUTOVER_SYNC_QUERY`
+pub static PG_CUTOVER_COPY_QUERY: &str = "
+insert into TABLE_NAME (
+    client_id, lock_name, expires_at, ttl_ms, resource_type, resource_id, last_seen_at,
+    fencing_token, acquired_at, namespace, metadata
+)
+select
+    client_id, lock_name, expires_at, ttl_ms, resource_type, resource_id, last_seen_at,
+    fencing_token, acquired_at, namespace, metadata
+from SOURCE_TABLE
+where
+    expires_at is null
+    or expires_at >= now()
+on conflict (namespace, lock_name) do nothing;
+";
+
+/// Fencing tokens and epochs only grow if a new table never hands out one
+/// below the ones of the table it replaces
+pub static PG_CUTOVER_SYNC_QUERY: &str = "
+select setval(
+    'TABLE_NAME_fencing_seq',
+    greatest(
+        (select last_value from TABLE_NAME_fencing_seq),
+        (select last_value from SOURCE_TABLE_fencing_seq)
+    )
+);
+
+insert into TABLE_NAME_epochs (lock_name, epoch)
+select lock_name, epoch from SOURCE_TABLE_epochs
+on conflict (lock_name) do update
+    set epoch = greatest(TABLE_NAME_epochs.epoch, excluded.epoch);
+";
+
+/// What a `SchemaToken` remembers of a table, see `CockLock::verify`
+pub static PG_SCHEMA_FINGERPRINT_QUERY: &str = "
+select
+    coalesce(
+        to_regclass(quote_ident(coalesce($2, current_schema())) || '.' || quote_ident($1))::oid::bigint,
+        0
+    ) as table_oid,
+    coalesce((
+        select string_agg(column_name || ' ' || data_type, ', ' order by column_name)
+        from information_schema.columns
+        where table_name = $1 and table_schema = coalesce($2, current_schema())
+    ), '') as columns;
+";
+
+/// Keeps anybody from changing the lock table until the transaction ends,
+/// if there is one
+pub static PG_LOCK_SCHEMA_QUERY: &str = "
+do $$
+begin
+    if to_regclass('TABLE_NAME') is not null then
+        lock table TABLE_NAME in access exclusive mode;
+    end if;
+end;
+$$;
+";
+
+pub static PG_COLUMNS_QUERY: &str = "
+select column_name || ' ' || data_type || ' ' || is_nullable
+from information_schema.columns
+where table_name = $1 and table_schema = coalesce($2, current_schema())
+order by column_name;
+";
+
 pub static PG_CLEAN_UP_QUERY: &str = "
 drop trigger if exists _lock_reap_trigger on TABLE_NAME;
-drop function if exists _lock_reap();
+drop trigger if exists _lock_epoch_trigger on TABLE_NAME;
+drop trigger if exists _lock_stats_trigger on TABLE_NAME;
+drop trigger if exists _lock_audit_trigger on TABLE_NAME;
+drop function if exists _lock_reap_TABLE_NAME();
+drop function if exists _lock_epoch_TABLE_NAME();
+drop function if exists _lock_stats_TABLE_NAME();
+drop function if exists _lock_audit_TABLE_NAME();
+drop table if exists TABLE_NAME;
+drop table if exists TABLE_NAME_epochs;
+drop table if exists TABLE_NAME_stats;
+drop table if exists TABLE_NAME_audit;
+drop table if exists TABLE_NAME_standbys;
+drop table if exists TABLE_NAME_values;
+drop table if exists TABLE_NAME_instances;
+drop table if exists TABLE_NAME_intents;
+drop table if exists TABLE_NAME_jobs;
+drop sequence if exists TABLE_NAME_fencing_seq;
+";
+
+pub static CRDB_CLEAN_UP_QUERY: &str = "
 drop table if exists TABLE_NAME;
+drop table if exists TABLE_NAME_epochs;
+drop table if exists TABLE_NAME_stats;
+drop table if exists TABLE_NAME_standbys;
+drop table if exists TABLE_NAME_values;
+drop table if exists TABLE_NAME_instances;
+drop table if exists TABLE_NAME_intents;
+drop table if exists TABLE_NAME_jobs;
+drop sequence if exists TABLE_NAME_fencing_seq;
+";
+
+/// Lets `GRANTEE` use the lock tables when they are created by a maintenance
+/// role, see `CockLockBuilder::with_maintenance_role`
+pub static GRANT_QUERY: &str = "
+grant select, insert, update, delete
+    on TABLE_NAME, TABLE_NAME_epochs, TABLE_NAME_standbys, TABLE_NAME_values,
+        TABLE_NAME_instances, TABLE_NAME_intents, TABLE_NAME_jobs
+    to GRANTEE;
+grant usage, select, update on sequence TABLE_NAME_fencing_seq to GRANTEE;
+";
+
+pub static STATS_GRANT_QUERY: &str = "
+grant select, insert, update, delete on TABLE_NAME_stats to GRANTEE;
+";
+
+pub static AUDIT_GRANT_QUERY: &str = "
+grant select, insert on TABLE_NAME_audit to GRANTEE;
+grant usage on sequence TABLE_NAME_audit_event_id_seq to GRANTEE;
+";
+
+/// Older versions shared a single `_lock_reap()` function between every lock
+/// table in the database. Once no trigger references it anymore it is dropped.
+pub static PG_LEGACY_REAP_CLEAN_UP_QUERY: &str = "
+do $$
+begin
+    if to_regproc('_lock_reap') is not null and not exists (
+        select from pg_trigger where tgfoid = to_regproc('_lock_reap')
+    ) then
+        drop function _lock_reap();
+    end if;
+end;
+$$;
+";
+
+/// In append-only mode every lease is a new row of `TABLE_NAME_history`, and
+/// `TABLE_NAME` is a view of the latest row of each lock that wasn't released.
+/// Each row takes the next generation of its lock, so of two statements
+/// writing the same lock at once only one succeeds. The view is dropped while
+/// `ttl_ms` is widened for tables of older versions, and created again.
+pub static APPEND_ONLY_TABLE_QUERY: &str = "
+create table if not exists TABLE_NAME_history (
+    lock_name text not null,
+    generation bigint not null,
+    client_id uuid not null,
+    expires_at timestamp,
+    ttl_ms bigint,
+    resource_type text,
+    resource_id text,
+    last_seen_at timestamp,
+    released boolean not null,
+    primary key (lock_name, generation)
+);
+
+alter table TABLE_NAME_history add column if not exists fencing_token bigint;
+alter table TABLE_NAME_history add column if not exists acquired_at timestamp;
+alter table TABLE_NAME_history add column if not exists metadata bytea;
+
+do $$
+begin
+    if (
+        select atttypid from pg_attribute
+        where attrelid = 'TABLE_NAME_history'::regclass and attname = 'ttl_ms'
+    ) = 'integer'::regtype then
+        drop view if exists TABLE_NAME;
+        alter table TABLE_NAME_history alter column ttl_ms type bigint;
+    end if;
+end;
+$$;
+
+create sequence if not exists TABLE_NAME_fencing_seq;
+
+create or replace view TABLE_NAME as
+select
+    client_id, lock_name, expires_at, ttl_ms, resource_type, resource_id, last_seen_at,
+    generation, fencing_token, acquired_at, ''::text as namespace, metadata
+from (
+    select distinct on (lock_name) *
+    from TABLE_NAME_history
+    order by lock_name, generation desc
+) as latest
+where not released;
+
+create table if not exists TABLE_NAME_epochs (
+    lock_name text primary key,
+    epoch bigint not null
+);
+
+create table if not exists TABLE_NAME_instances (
+    client_id uuid primary key,
+    incarnation uuid not null,
+    seen_at timestamp not null
+);
+
+create table if not exists TABLE_NAME_intents (
+    intent_id uuid primary key,
+    client_id uuid not null,
+    lock_names text[] not null,
+    created_at timestamp not null
+);
+
+create table if not exists TABLE_NAME_jobs (
+    job_name text primary key,
+    last_run_at timestamp,
+    next_run_at timestamp not null
+);
+";
+
+pub static APPEND_ONLY_LOCK_QUERY: &str = "
+insert into TABLE_NAME_history (
+    lock_name, generation, client_id, expires_at, ttl_ms, resource_type, resource_id,
+    last_seen_at, released, fencing_token, acquired_at, metadata
+)
+select
+    $2,
+    coalesce(latest.generation, 0) + 1,
+    $1,
+    case when $3::bigint = 0 then null else now() + ($3::bigint || ' milliseconds')::interval end,
+    $3::bigint,
+    $4,
+    $5,
+    now(),
+    false,
+    case
+        when
+            latest.client_id = $1
+            and not latest.released
+            and (latest.expires_at is null or latest.expires_at >= now())
+        then coalesce(latest.fencing_token, nextval('TABLE_NAME_fencing_seq'))
+        else nextval('TABLE_NAME_fencing_seq')
+    end,
+    case
+        when
+            latest.client_id = $1
+            and not latest.released
+            and (latest.expires_at is null or latest.expires_at >= now())
+        then coalesce(latest.acquired_at, now())
+        else now()
+    end,
+    case
+        when
+            latest.client_id = $1
+            and not latest.released
+            and (latest.expires_at is null or latest.expires_at >= now())
+        then coalesce($6::bytea, latest.metadata)
+        else $6::bytea
+    end
+from (select 1) as one
+left join lateral (
+    select generation, client_id, expires_at, released, fencing_token, acquired_at, metadata
+    from TABLE_NAME_history
+    where lock_name = $2
+    order by generation desc
+    limit 1
+) as latest on true
+where
+    latest.generation is null
+    or latest.released
+    or latest.client_id = $1
+    or latest.expires_at < now()
+on conflict (lock_name, generation) do nothing
+returning fencing_token;
+";
+
+pub static APPEND_ONLY_RENEW_QUERY: &str = "
+insert into TABLE_NAME_history (
+    lock_name, generation, client_id, expires_at, ttl_ms, resource_type, resource_id,
+    last_seen_at, released, fencing_token, acquired_at, metadata
+)
+select
+    lock_name,
+    generation + 1,
+    client_id,
+    case when $3::bigint = 0 then null else now() + ($3::bigint || ' milliseconds')::interval end,
+    $3::bigint,
+    resource_type,
+    resource_id,
+    now(),
+    false,
+    fencing_token,
+    acquired_at,
+    metadata
+from TABLE_NAME
+where
+    client_id = $1
+    and lock_name = any($2)
+on conflict (lock_name, generation) do nothing
+returning lock_name;
+";
+
+pub static APPEND_ONLY_REFRESH_QUERY: &str = "
+insert into TABLE_NAME_history (
+    lock_name, generation, client_id, expires_at, ttl_ms, resource_type, resource_id,
+    last_seen_at, released, fencing_token, acquired_at, metadata
+)
+select
+    lock_name,
+    generation + 1,
+    client_id,
+    case when ttl_ms = 0 then null else now() + (ttl_ms || ' milliseconds')::interval end,
+    ttl_ms,
+    resource_type,
+    resource_id,
+    now(),
+    false,
+    fencing_token,
+    acquired_at,
+    metadata
+from TABLE_NAME
+where
+    client_id = $1
+    and lock_name = $2
+    and ttl_ms is not null
+on conflict (lock_name, generation) do nothing;
+";
+
+pub static APPEND_ONLY_UNLOCK_QUERY: &str = "
+insert into TABLE_NAME_history (
+    lock_name, generation, client_id, expires_at, ttl_ms, resource_type, resource_id,
+    last_seen_at, released, fencing_token, acquired_at, metadata
+)
+select
+    lock_name, generation + 1, client_id, now(), ttl_ms, resource_type, resource_id, now(), true,
+    fencing_token, acquired_at, metadata
+from TABLE_NAME
+where
+    client_id = $1
+    and lock_name = $2
+on conflict (lock_name, generation) do nothing;
+";
+
+pub static APPEND_ONLY_UNLOCK_PREFIX_QUERY: &str = "
+insert into TABLE_NAME_history (
+    lock_name, generation, client_id, expires_at, ttl_ms, resource_type, resource_id,
+    last_seen_at, released, fencing_token, acquired_at, metadata
+)
+select
+    lock_name, generation + 1, client_id, now(), ttl_ms, resource_type, resource_id, now(), true,
+    fencing_token, acquired_at, metadata
+from TABLE_NAME
+where
+    client_id = $1
+    and lock_name like $2
+on conflict (lock_name, generation) do nothing
+returning lock_name;
+";
+
+pub static APPEND_ONLY_FORCE_UNLOCK_QUERY: &str = "
+insert into TABLE_NAME_history (
+    lock_name, generation, client_id, expires_at, ttl_ms, resource_type, resource_id,
+    last_seen_at, released, fencing_token, acquired_at, metadata
+)
+select
+    lock_name, generation + 1, client_id, now(), ttl_ms, resource_type, resource_id, now(), true,
+    fencing_token, acquired_at, metadata
+from TABLE_NAME
+where
+    lock_name = $1
+    and (expires_at is null or expires_at >= now())
+on conflict (lock_name, generation) do nothing
+returning client_id;
+";
+
+pub static APPEND_ONLY_TRANSFER_QUERY: &str = "
+insert into TABLE_NAME_history (
+    lock_name, generation, client_id, expires_at, ttl_ms, resource_type, resource_id,
+    last_seen_at, released, fencing_token, acquired_at, metadata
+)
+select
+    lock_name,
+    generation + 1,
+    $3,
+    expires_at,
+    ttl_ms,
+    resource_type,
+    resource_id,
+    now(),
+    false,
+    nextval('TABLE_NAME_fencing_seq'),
+    now(),
+    metadata
+from TABLE_NAME
+where
+    client_id = $1
+    and lock_name = $2
+    and (expires_at is null or expires_at >= now())
+on conflict (lock_name, generation) do nothing
+returning fencing_token
+";
+
+pub static APPEND_ONLY_IMPORT_QUERY: &str = "
+insert into TABLE_NAME_history (
+    lock_name, generation, client_id, expires_at, ttl_ms, last_seen_at, released, fencing_token
+)
+select
+    $2,
+    coalesce((select max(generation) from TABLE_NAME_history where lock_name = $2), 0) + 1,
+    $1,
+    now() + ($3::bigint || ' milliseconds')::interval,
+    $4::bigint,
+    now(),
+    false,
+    nextval('TABLE_NAME_fencing_seq')
+where not exists (
+    select from TABLE_NAME
+    where
+        lock_name = $2
+        and (expires_at is null or expires_at >= now())
+)
+on conflict (lock_name, generation) do nothing;
+";
+
+/// Expired leases are part of the history, nothing is deleted
+pub static APPEND_ONLY_REAP_QUERY: &str = "
+select where false;
+";
+
+/// Advisory locks live in the connection that took them rather than in a
+/// table, see `Backend::Advisory`. A lock the connection holds already is
+/// left as it is, since taking it again would need as many unlocks.
+pub static ADVISORY_LOCK_QUERY: &str = "
+with params as (
+    select
+        $1::uuid as client_id,
+        $3::bigint as ttl_ms,
+        $4::text as resource_type,
+        $5::text as resource_id,
+        $6::bytea as metadata,
+        hashtextextended('TABLE_KEY/' || NAMESPACE_KEY || $2::text, 0) as key
+)
+select 0::bigint as fencing_token
+from params
+where case
+    when exists (
+        select from pg_locks
+        where
+            locktype = 'advisory'
+            and pid = pg_backend_pid()
+            and objsubid = 1
+            and classid = ((params.key >> 32) & 4294967295)::oid
+            and objid = (params.key & 4294967295)::oid
+    ) then true
+    else pg_try_advisory_lock(params.key)
+end;
+";
+
+pub static ADVISORY_HELD_QUERY: &str = "
+with params as (
+    select $1::uuid as client_id, hashtextextended('TABLE_KEY/' || NAMESPACE_KEY || $2::text, 0) as key
+)
+select 0::bigint as fencing_token
+from params
+where exists (
+    select from pg_locks
+    where
+        locktype = 'advisory'
+        and pid = pg_backend_pid()
+        and objsubid = 1
+        and classid = ((params.key >> 32) & 4294967295)::oid
+        and objid = (params.key & 4294967295)::oid
+);
+";
+
+pub static ADVISORY_UNLOCK_QUERY: &str = "
+with params as (
+    select $1::uuid as client_id, hashtextextended('TABLE_KEY/' || NAMESPACE_KEY || $2::text, 0) as key
+)
+select
+from params
+where case
+    when exists (
+        select from pg_locks
+        where
+            locktype = 'advisory'
+            and pid = pg_backend_pid()
+            and objsubid = 1
+            and classid = ((params.key >> 32) & 4294967295)::oid
+            and objid = (params.key & 4294967295)::oid
+    ) then pg_advisory_unlock(params.key)
+    else false
+end;
+";
+
+pub static APPEND_ONLY_GRANT_QUERY: &str = "
+grant select, insert, update, delete
+    on TABLE_NAME_history, TABLE_NAME, TABLE_NAME_epochs, TABLE_NAME_instances,
+        TABLE_NAME_intents, TABLE_NAME_jobs
+    to GRANTEE;
+grant usage, select, update on sequence TABLE_NAME_fencing_seq to GRANTEE;
+";
+
+pub static APPEND_ONLY_CLEAN_UP_QUERY: &str = "
+drop view if exists TABLE_NAME;
+drop table if exists TABLE_NAME_history;
+drop table if exists TABLE_NAME_epochs;
+drop table if exists TABLE_NAME_instances;
+drop table if exists TABLE_NAME_intents;
+drop table if exists TABLE_NAME_jobs;
+drop sequence if exists TABLE_NAME_fencing_seq;
 ";
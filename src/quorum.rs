@@ -0,0 +1,410 @@
+//! Locks that must be granted by a majority of independent databases
+//!
+//! By default the clients are tried in order and the first one that answers
+//! decides, which treats them as replicas of one lock store. When the clients
+//! are independent databases, a lock granted by one of them says nothing about
+//! the others, so `Quorum::Majority` runs every operation on all clients and
+//! only counts it as done when more than half of them agree, like Redlock.
+//! `Quorum::All` goes further and fails whenever any client can't be reached
+//! or refuses.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use postgres::types::ToSql;
+use postgres::Client;
+
+use crate::cutover::Cutover;
+use crate::errors::CockLockError;
+use crate::failure::FailureAction;
+use crate::hooks::Transition;
+use crate::lock::{warn_if_slow, with_failover, CockLock, CockLockQueries, Grant, Operation};
+
+/// How many clients have to agree on a lock operation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Quorum {
+    /// The first client that can be reached decides, the others are only
+    /// used when it can't be
+    #[default]
+    FirstAvailable,
+    /// More than half of the clients must grant a lock, otherwise the
+    /// clients that did are rolled back
+    ///
+    /// Locks are also extended, refreshed and released on every client, and
+    /// only count as extended or refreshed where a majority did so. Fencing
+    /// tokens only grow per database, see `FencingToken`.
+    Majority,
+    /// Every client must grant a lock, like `Majority` but without tolerating
+    /// any client that fails
+    All,
+}
+
+impl Quorum {
+    /// The number of clients out of `clients` that must agree
+    pub fn required(self, clients: usize) -> usize {
+        match self {
+            Quorum::FirstAvailable => 1,
+            Quorum::Majority => clients / 2 + 1,
+            Quorum::All => clients,
+        }
+    }
+}
+
+/// Whether the last operation on every client left no client to spare, see
+/// `CockLock::quorum_at_risk`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct QuorumHealth {
+    pub at_risk: bool,
+    /// Whether to refuse locks without an expiry while at risk
+    pub refuse_infinite_leases: bool,
+}
+
+/// What an operation does when some clients fail, see
+/// `CockLockBuilder::with_strictness`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strictness {
+    /// Fail only if no client answers, skipping those that can't be reached
+    #[default]
+    Any,
+    /// Fail unless more than half of the clients answer and agree
+    Quorum,
+    /// Fail if any client errors
+    All,
+}
+
+impl From<Strictness> for Quorum {
+    fn from(strictness: Strictness) -> Self {
+        match strictness {
+            Strictness::Any => Quorum::FirstAvailable,
+            Strictness::Quorum => Quorum::Majority,
+            Strictness::All => Quorum::All,
+        }
+    }
+}
+
+type Statements<'a, T> =
+    dyn FnMut(&mut Client, &CockLockQueries, Option<&Cutover>) -> Result<T, postgres::Error> + 'a;
+
+impl CockLock {
+    /// Run statements on every client, returning the result of each one
+    ///
+    /// Failed statements are retried as the `FailurePolicy` says, but no
+    /// failure stops the other clients from being asked.
+    pub(crate) fn on_every_client<T>(
+        &mut self,
+        operation: Operation,
+        statements: &mut Statements<'_, T>,
+    ) -> Vec<(usize, Result<T, CockLockError>)> {
+        self.reconnect_due();
+        let mut failed_over = false;
+        let mut results = vec![];
+        let clients = self.clients.iter_mut().zip(&self.connectors).enumerate();
+        for (index, (client, connector)) in clients {
+            let mut retries = 0;
+            let result = loop {
+                let started_at = Instant::now();
+                let hooks = (&self.hooks, self.labels[index].as_str());
+                let result = with_failover(
+                    client,
+                    connector.as_ref(),
+                    hooks,
+                    &mut failed_over,
+                    |client| statements(client, &self.queries, self.cutover.as_ref()),
+                );
+                warn_if_slow(
+                    self.slow_query_threshold,
+                    operation,
+                    &self.labels[index],
+                    started_at,
+                );
+                match result {
+                    Err(err)
+                        if self.failure_policy.action(&err) == FailureAction::Retry
+                            && retries < self.failure_policy.max_retries() =>
+                    {
+                        retries += 1;
+                        self.clock.sleep(self.failure_policy.retry_delay());
+                    }
+                    result => {
+                        break result.map_err(|err| {
+                            CockLockError::postgres(err, operation, &self.labels[index])
+                        })
+                    }
+                }
+            };
+            results.push((index, result));
+        }
+
+        if failed_over {
+            self.lose_advisory_locks();
+        }
+        self.track_quorum_health(&results);
+        results
+    }
+
+    /// Whether the quorum is at risk: the clients that answered are just
+    /// enough for it, so one more failing client would lose it
+    ///
+    /// Updated by every operation in `Quorum::Majority` mode. Always false
+    /// in the other modes, where either one client or every client is
+    /// needed anyway.
+    pub fn quorum_at_risk(&self) -> bool {
+        self.quorum_health.at_risk
+    }
+
+    /// Report when the clients that answered drop to the quorum or recover
+    fn track_quorum_health<T>(&mut self, results: &[(usize, Result<T, CockLockError>)]) {
+        let clients = self.clients.len();
+        let required = self.quorum.required(clients);
+        if self.quorum != Quorum::Majority || required == clients {
+            return;
+        }
+        let healthy = results.iter().filter(|(_, result)| result.is_ok()).count();
+        let at_risk = healthy <= required;
+        match (self.quorum_health.at_risk, at_risk) {
+            (false, true) => {
+                log::warn!(
+                    target: "cocklock::quorum",
+                    "Only {healthy} of {clients} clients answered, {required} are needed"
+                );
+                self.hooks
+                    .reached(|| Transition::QuorumAtRisk { healthy, required });
+            }
+            (true, false) => self.hooks.reached(|| Transition::QuorumRestored),
+            _ => {}
+        }
+        self.quorum_health.at_risk = at_risk;
+    }
+
+    /// Refuse a lock without an expiry while the quorum is at risk, if the
+    /// instance was built to
+    pub(crate) fn check_quorum_health(
+        &self,
+        lock_name: &str,
+        timeout_ms: i64,
+    ) -> Result<(), CockLockError> {
+        let health = self.quorum_health;
+        if timeout_ms == 0 && health.at_risk && health.refuse_infinite_leases {
+            return Err(CockLockError::QuorumAtRisk(lock_name.to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Run statements on every client and keep the result of the first client
+    /// that granted the operation, if a quorum of them did
+    pub(crate) fn on_quorum<T, F, G>(
+        &mut self,
+        operation: Operation,
+        mut statements: F,
+        granted: G,
+    ) -> (Option<usize>, Result<T, CockLockError>)
+    where
+        F: FnMut(&mut Client, &CockLockQueries, Option<&Cutover>) -> Result<T, postgres::Error>,
+        G: Fn(&T) -> bool,
+    {
+        let mut results = self.on_every_client(operation, &mut statements);
+        let granted_by = results
+            .iter()
+            .filter(|(_, result)| result.as_ref().is_ok_and(&granted))
+            .count();
+        if let Err(err) = self.decide(&mut results, granted_by) {
+            return (None, Err(err));
+        }
+
+        results
+            .into_iter()
+            .find(|(_, result)| result.as_ref().is_ok_and(&granted))
+            .map(|(index, result)| (Some(index), result))
+            .unwrap_or((None, Err(CockLockError::NotAvailable)))
+    }
+
+    /// Take a lock on every client, releasing it again from the clients that
+    /// granted it if they are too few
+    ///
+    /// Locks that were already held by the instance, whether left as they
+    /// were or extended, count as granted but are never rolled back. The lock
+    /// counts as already held if no client took it anew, otherwise the grant
+    /// of the first client that did is returned.
+    /// As in Redlock, a lease of `timeout_ms` that ran out while the clients
+    /// were asked, allowing for clock drift, is rolled back as well.
+    pub(crate) fn lock_on_quorum<F>(
+        &mut self,
+        unlock_params: &[&(dyn ToSql + Sync)],
+        timeout_ms: i64,
+        mut statements: F,
+    ) -> (Option<usize>, Result<Grant, CockLockError>)
+    where
+        F: FnMut(&mut Client, &CockLockQueries, Option<&Cutover>) -> Result<Grant, postgres::Error>,
+    {
+        let granted = |grant: &Grant| *grant != Grant::Refused;
+        let started_at = self.clock.now();
+        let mut results = self.on_every_client(Operation::Lock, &mut statements);
+        let granted_by: Vec<usize> = results
+            .iter()
+            .filter(|(_, result)| result.as_ref().is_ok_and(granted))
+            .map(|(index, _)| *index)
+            .collect();
+
+        let mut decision = self.decide(&mut results, granted_by.len());
+        let elapsed = self.clock.now().saturating_duration_since(started_at);
+        if decision.is_ok() && timeout_ms != 0 && validity(timeout_ms, elapsed).is_none() {
+            log::warn!(
+                target: "cocklock::quorum",
+                "A quorum took {elapsed:?} to grant a lease of {timeout_ms}ms, \
+                 too long to be sure it is still valid"
+            );
+            decision = Err(CockLockError::NotAvailable);
+        }
+        match decision {
+            Ok(()) => {
+                let grants: Vec<Grant> = results
+                    .iter()
+                    .filter_map(|(_, result)| result.as_ref().ok().copied())
+                    .filter(granted)
+                    .collect();
+                let grant = grants
+                    .iter()
+                    .find(|grant| matches!(grant, Grant::Taken(_)))
+                    .or(grants.first())
+                    .copied()
+                    .unwrap_or(Grant::Refused);
+                (granted_by.first().copied(), Ok(grant))
+            }
+            Err(err) => {
+                let taken = results
+                    .iter()
+                    .filter(|(_, result)| matches!(result, Ok(Grant::Taken(_))))
+                    .map(|(index, _)| *index);
+                let mut failed_over = false;
+                for index in taken {
+                    let connector = self.connectors[index].as_ref();
+                    let cutover = self.cutover.as_ref();
+                    // Whatever can't be rolled back runs out with its timeout
+                    let hooks = (&self.hooks, self.labels[index].as_str());
+                    let client = &mut self.clients[index];
+                    let _ = with_failover(client, connector, hooks, &mut failed_over, |client| {
+                        if let Some(cutover) = cutover {
+                            client.execute(&cutover.queries.unlock, unlock_params)?;
+                        }
+                        client.execute(&self.queries.unlock, unlock_params)
+                    });
+                }
+                if failed_over {
+                    self.lose_advisory_locks();
+                }
+                (None, Err(err))
+            }
+        }
+    }
+
+    /// Extend locks on every client, returning the ones a quorum extended
+    pub(crate) fn extend_on_quorum<F>(
+        &mut self,
+        mut statements: F,
+    ) -> (Option<usize>, Result<HashSet<String>, CockLockError>)
+    where
+        F: FnMut(
+            &mut Client,
+            &CockLockQueries,
+            Option<&Cutover>,
+        ) -> Result<HashSet<String>, postgres::Error>,
+    {
+        let mut results = self.on_every_client(Operation::Extend, &mut statements);
+        let answered = results.iter().filter(|(_, result)| result.is_ok()).count();
+        if let Err(err) = self.decide(&mut results, answered) {
+            return (None, Err(err));
+        }
+
+        let mut extended_by: HashMap<&String, usize> = HashMap::new();
+        for lock_name in results
+            .iter()
+            .flat_map(|(_, result)| result.iter().flatten())
+        {
+            *extended_by.entry(lock_name).or_default() += 1;
+        }
+        let required = self.quorum.required(self.clients.len());
+        let extended = extended_by
+            .into_iter()
+            .filter(|(_, clients)| *clients >= required)
+            .map(|(lock_name, _)| lock_name.clone())
+            .collect();
+        let client = results.iter().find(|(_, result)| result.is_ok());
+        (client.map(|(index, _)| *index), Ok(extended))
+    }
+
+    /// Whether `granted` clients are a quorum, otherwise why not
+    ///
+    /// If too few clients answered to ever reach a quorum, the errors are
+    /// taken out of the results and returned together as
+    /// `CockLockError::NoClientsAvailable`.
+    fn decide<T>(
+        &self,
+        results: &mut Vec<(usize, Result<T, CockLockError>)>,
+        granted: usize,
+    ) -> Result<(), CockLockError> {
+        let required = self.quorum.required(self.clients.len());
+        if granted >= required {
+            return Ok(());
+        }
+
+        let answered = results.iter().filter(|(_, result)| result.is_ok()).count();
+        if answered >= required {
+            return Err(CockLockError::NotAvailable);
+        }
+        let mut failures = vec![];
+        let mut other = None;
+        for (index, result) in std::mem::take(results) {
+            match result {
+                Ok(value) => results.push((index, Ok(value))),
+                Err(CockLockError::PostgresError(err, _, _)) => failures.push((index, err)),
+                Err(err) => {
+                    other.get_or_insert(err);
+                }
+            }
+        }
+        match other {
+            Some(err) => Err(err),
+            None => Err(CockLockError::NoClientsAvailable(failures)),
+        }
+    }
+}
+
+/// How long a lease of `timeout_ms` granted by a quorum is still valid once
+/// `elapsed` passed since the clients were asked, `None` if it isn't anymore
+///
+/// Allows for the clocks of the databases drifting apart by 1% of the lease
+/// plus 2ms, like Redlock does.
+pub(crate) fn validity(timeout_ms: i64, elapsed: Duration) -> Option<Duration> {
+    let lease = Duration::from_millis(timeout_ms.unsigned_abs());
+    let drift = lease / 100 + Duration::from_millis(2);
+    lease
+        .checked_sub(elapsed + drift)
+        .filter(|validity| !validity.is_zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{validity, Quorum, Strictness};
+
+    #[test]
+    fn majorities_need_more_than_half() {
+        assert_eq!(Quorum::FirstAvailable.required(5), 1);
+        assert_eq!(Quorum::Majority.required(1), 1);
+        assert_eq!(Quorum::Majority.required(2), 2);
+        assert_eq!(Quorum::Majority.required(3), 2);
+        assert_eq!(Quorum::Majority.required(4), 3);
+        assert_eq!(Quorum::from(Strictness::All).required(4), 4);
+    }
+
+    #[test]
+    fn slow_quorums_leave_no_validity() {
+        assert_eq!(
+            validity(10_000, Duration::from_millis(1_000)),
+            Some(Duration::from_millis(8_898))
+        );
+        assert_eq!(validity(10_000, Duration::from_millis(9_898)), None);
+        assert_eq!(validity(100, Duration::from_millis(200)), None);
+    }
+}
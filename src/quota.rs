@@ -0,0 +1,97 @@
+use std::time::Instant;
+
+use postgres::types::ToSql;
+
+use crate::errors::CockLockError;
+use crate::lock::{CockLock, Operation};
+
+/// The most locks an instance may hold at once, see
+/// `CockLockBuilder::with_lock_quota`
+///
+/// Guards shared clusters against a service that leaks leases. The quota is
+/// checked against the locks the instance knows it holds (`held_locks`)
+/// before any statement runs, which costs nothing but misses locks of earlier
+/// runs with the same ID and of other instances sharing it. Enforcing it in
+/// the database as well closes those gaps, at the cost of counting the locks
+/// of the instance with every acquisition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockQuota {
+    pub max_locks: usize,
+    /// Whether the lock statement checks the quota too
+    pub in_database: bool,
+}
+
+impl LockQuota {
+    pub fn new(max_locks: usize) -> Self {
+        Self {
+            max_locks,
+            in_database: false,
+        }
+    }
+
+    /// Also check the quota in the lock statement
+    pub fn in_database(mut self) -> Self {
+        self.in_database = true;
+        self
+    }
+}
+
+impl CockLock {
+    /// Reject a new lock if the instance already holds its quota
+    ///
+    /// Locking a name the instance holds again is always allowed.
+    pub(crate) fn check_quota(&self, lock_name: &str, now: Instant) -> Result<(), CockLockError> {
+        let Some(quota) = self.quota else {
+            return Ok(());
+        };
+        let held: Vec<_> = self
+            .held
+            .locks()
+            .into_iter()
+            .filter(|lock| !lock.is_expired(now))
+            .collect();
+        if held.len() >= quota.max_locks && held.iter().all(|lock| lock.lock_name != lock_name) {
+            return Err(CockLockError::QuotaExceeded(quota.max_locks));
+        }
+        Ok(())
+    }
+
+    /// Tell whether a lock was refused by the quota check of the database
+    /// rather than held by someone else
+    pub(crate) fn quota_refused(&mut self) -> bool {
+        let Some(quota) = self.quota.filter(|quota| quota.in_database) else {
+            return false;
+        };
+        let id = self.id;
+        let params: &[&(dyn ToSql + Sync)] = &[&id];
+        let (_, result) = self.on_available_client(Operation::List, |client, queries, _| {
+            client.query(&queries.owned_locks, params)
+        });
+        result.is_ok_and(|rows| rows.len() >= quota.max_locks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{Backend, Dialect};
+    use crate::lock::{AcquisitionMode, CockLockQueries};
+    use crate::table::Table;
+
+    #[test]
+    fn quota_conditions_wrap_the_lock_condition() {
+        for backend in [Backend::Postgres, Backend::CockroachDb] {
+            let dialect = Dialect::new(Some(backend), &[]).unwrap();
+            let table = Table::new(None, "locks");
+            let lock = CockLockQueries::new(&table, dialect, AcquisitionMode::Upsert)
+                .enforcing_quota(&table)
+                .lock;
+            let condition =
+                &lock[lock.find("\nwhere\n").unwrap()..lock.find("on conflict").unwrap()];
+            assert!(condition.contains(") < $7\n    and (\n"));
+            assert_eq!(
+                condition.matches('(').count(),
+                condition.matches(')').count()
+            );
+        }
+    }
+}
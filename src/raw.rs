@@ -0,0 +1,88 @@
+//! Statements of your own against the lock table
+//!
+//! For building primitives the crate doesn't offer on top of its plumbing:
+//! the statements run on the first reachable client with the same failover,
+//! retries and journal entries as the built-in operations. They are only as
+//! stable as the schema of the lock table, which may change between
+//! versions.
+//!
+//! `TABLE_NAME` in a statement is replaced with the quoted name of the lock
+//! table, qualified with its schema if one was set, as is the part of names
+//! like `TABLE_NAME_epochs`. `$1` is bound to the ID of the instance, so the
+//! statement can't mistake whose locks it touches. During a cutover only the
+//! new table is used, and in append-only mode `TABLE_NAME` is a view.
+
+use std::time::Instant;
+
+use postgres::types::ToSql;
+use postgres::Row;
+
+use crate::errors::CockLockError;
+use crate::lock::{CockLock, Operation};
+
+impl CockLock {
+    /// Run a statement against the lock table, with the ID of the instance
+    /// as `$1` and `params` from `$2` on
+    pub fn raw_query(
+        &mut self,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, CockLockError> {
+        self.require_table("raw statements")?;
+        let started_at = Instant::now();
+        let statement = self.table().fill(statement);
+        let id = self.id;
+        let mut all_params: Vec<&(dyn ToSql + Sync)> = vec![&id];
+        all_params.extend_from_slice(params);
+        let (client, result) = self.on_available_client(Operation::Raw, |client, _, _| {
+            client.query(&statement, &all_params)
+        });
+
+        let client = client.map(|index| (index, self.labels[index].as_str()));
+        self.journal
+            .record(Operation::Raw, "", client, started_at, &result);
+        result
+    }
+
+    /// Run a statement against the lock table if the instance holds the lock
+    /// `lock_name`, with the ID of the instance as `$1`, the lock name as `$2`
+    /// and `params` from `$3` on
+    ///
+    /// The lock is checked in the same transaction, which fails with
+    /// `NotHeld` if the instance doesn't hold it. A lease can still run out
+    /// while the statement runs, so statements writing to the lock's row
+    /// should match `client_id = $1` too.
+    pub fn raw_query_owned<T: ToString>(
+        &mut self,
+        lock_name: T,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, CockLockError> {
+        self.require_table("raw statements")?;
+        let started_at = Instant::now();
+        let lock_name = lock_name.to_string();
+        let statement = self.table().fill(statement);
+        let id = self.id;
+        let mut all_params: Vec<&(dyn ToSql + Sync)> = vec![&id, &lock_name];
+        all_params.extend_from_slice(params);
+        let (client, result) = self.on_available_client(Operation::Raw, |client, queries, _| {
+            let mut transaction = client.transaction()?;
+            if transaction
+                .query_opt(&queries.held, &all_params[..2])?
+                .is_none()
+            {
+                return Ok(None);
+            }
+            let rows = transaction.query(&statement, &all_params)?;
+            transaction.commit()?;
+            Ok(Some(rows))
+        });
+
+        let result =
+            result.and_then(|rows| rows.ok_or_else(|| CockLockError::NotHeld(lock_name.clone())));
+        let client = client.map(|index| (index, self.labels[index].as_str()));
+        self.journal
+            .record(Operation::Raw, &lock_name, client, started_at, &result);
+        result
+    }
+}
@@ -0,0 +1,62 @@
+use crate::errors::CockLockError;
+use crate::listing::LockInfo;
+use crate::lock::{CockLock, Operation};
+
+/// What to do with the locks a restarted instance still holds from its
+/// previous run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReconcilePolicy {
+    /// Release them, the work they protected is assumed to be abandoned
+    Release,
+    /// Keep them and reset their timeouts to the ones they were locked with,
+    /// to resume the work they protected
+    Reclaim,
+    /// Leave them untouched, they run out on their own
+    Report,
+}
+
+impl CockLock {
+    /// Deal with the locks held by this instance when it starts
+    ///
+    /// Only useful with a stable ID set through
+    /// `CockLockBuilder::with_client_id`, otherwise a new instance holds no
+    /// locks. Unfinished calls of `lock_all` are rolled back first, see
+    /// `recover_intents`. `on_stale` is called with every lock found before
+    /// the policy is applied to it. Locks that can't be reclaimed, because
+    /// they were taken by a version that didn't record their timeout, are left
+    /// untouched.
+    pub fn reconcile_on_start<F>(
+        &mut self,
+        policy: ReconcilePolicy,
+        mut on_stale: F,
+    ) -> Result<(), CockLockError>
+    where
+        F: FnMut(&LockInfo),
+    {
+        self.require_table("reconciling locks")?;
+        self.recover_intents()?;
+
+        let id = self.id;
+        let (_, result) = self.on_available_client(Operation::List, |client, queries, _| {
+            client.query(&queries.owned_locks, &[&id])
+        });
+
+        for row in result? {
+            let lock = self.lock_info_from_row(&row);
+            on_stale(&lock);
+
+            let result = match policy {
+                ReconcilePolicy::Release => self.unlock(&lock.lock_name),
+                ReconcilePolicy::Reclaim => self.refresh(&lock.lock_name),
+                ReconcilePolicy::Report => Ok(()),
+            };
+            match result {
+                // The lock ran out in the meantime or can't be refreshed
+                Ok(()) | Err(CockLockError::NotAvailable) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+//! Reconnecting clients whose connection was closed
+//!
+//! A client whose server restarted or whose connection was cut stays closed,
+//! and every operation would skip it from then on. Clients given as
+//! connection strings or configs remember how they were connected, so the
+//! closed ones are connected again before each operation. Failed attempts
+//! back off, so that an unreachable server doesn't hold up every operation
+//! with a connection attempt.
+
+use std::time::Instant;
+
+use crate::backend::Backend;
+use crate::errors::CockLockError;
+use crate::health::probe;
+use crate::hooks::Transition;
+use crate::lock::{CockLock, Operation};
+use crate::pool::ClientHandle;
+use crate::retry::RetryPolicy;
+#[cfg(feature = "webhooks")]
+use crate::webhooks::LockEvent;
+
+/// Where reconnecting each client stands, see
+/// `CockLockBuilder::with_reconnection`
+#[derive(Debug, Clone)]
+pub(crate) struct Reconnection {
+    /// `None` if closed clients stay closed
+    backoff: Option<RetryPolicy>,
+    clients: Vec<Backoff>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Backoff {
+    failures: u32,
+    next_attempt_at: Option<Instant>,
+}
+
+impl Reconnection {
+    pub(crate) fn new(backoff: Option<RetryPolicy>, clients: usize) -> Self {
+        Self {
+            backoff,
+            clients: vec![Backoff::default(); clients],
+        }
+    }
+
+    /// The same settings for another instance, which starts without failures
+    pub(crate) fn emptied(&self) -> Self {
+        Self::new(self.backoff, self.clients.len())
+    }
+}
+
+impl CockLock {
+    /// Reconnect every closed client right away, whether or not its back-off
+    /// passed
+    ///
+    /// Returns the labels of the clients that were reconnected. If some of
+    /// them couldn't be, the first error is returned once all of them were
+    /// tried. Clients checked out of a pool are replaced by another checkout,
+    /// clients added as objects are never reconnected.
+    pub fn reconnect(&mut self) -> Result<Vec<String>, CockLockError> {
+        let mut reconnected = vec![];
+        let mut first_error = None;
+        for index in 0..self.clients.len() {
+            if !self.clients[index].is_closed() || !self.can_reconnect(index) {
+                continue;
+            }
+            match self.reconnect_client(index) {
+                Ok(()) => reconnected.push(self.labels[index].clone()),
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(reconnected),
+        }
+    }
+
+    /// Reconnect the closed clients whose back-off passed, done before the
+    /// statements of every operation
+    ///
+    /// Clients that still can't be reached are skipped by the operation as
+    /// before.
+    pub(crate) fn reconnect_due(&mut self) {
+        if self.reconnection.backoff.is_none() {
+            return;
+        }
+        let now = self.clock.now();
+        for index in 0..self.clients.len() {
+            let due = self.reconnection.clients[index]
+                .next_attempt_at
+                .is_none_or(|next_attempt_at| now >= next_attempt_at);
+            if due && self.clients[index].is_closed() && self.can_reconnect(index) {
+                let _ = self.reconnect_client(index);
+            }
+        }
+    }
+
+    /// Whether the client at `index` can be connected again, which clients
+    /// added as objects can't
+    fn can_reconnect(&self, index: usize) -> bool {
+        self.connectors[index].is_some() || self.checkouts[index].is_some()
+    }
+
+    fn reconnect_client(&mut self, index: usize) -> Result<(), CockLockError> {
+        if !self.can_reconnect(index) {
+            return Ok(());
+        }
+        let label = &self.labels[index];
+        self.hooks
+            .reached(|| Transition::Reconnecting(label.clone()));
+        let connected = match (&self.connectors[index], &self.checkouts[index]) {
+            (Some(connector), _) => connector
+                .connect()
+                .and_then(|mut client| {
+                    probe(&mut client, Some(connector), self.probe_timeout).map(|_| client)
+                })
+                .map(ClientHandle::from)
+                .map_err(|err| CockLockError::postgres(err, Operation::Connect, label)),
+            // The pool tests the connection it hands out, and drops the
+            // closed one once it is given back
+            (None, Some(checkout)) => checkout(),
+            (None, None) => unreachable!(),
+        };
+        match connected {
+            Ok(client) => {
+                self.clients[index] = client;
+                self.reconnection.clients[index] = Backoff::default();
+                self.hooks
+                    .reached(|| Transition::Reconnected(label.clone()));
+                log::info!(target: "cocklock::reconnect", "Reconnected {label}");
+                self.lose_advisory_locks();
+                Ok(())
+            }
+            Err(err) => {
+                let policy = self.reconnection.backoff.unwrap_or_default();
+                let backoff = &mut self.reconnection.clients[index];
+                let delay = policy.delay(backoff.failures, &mut self.rng);
+                backoff.failures = backoff.failures.saturating_add(1);
+                backoff.next_attempt_at = Some(self.clock.now() + delay);
+                log::warn!(
+                    target: "cocklock::reconnect",
+                    "Failed to reconnect {label}, trying again in {delay:?}: {err}"
+                );
+                Err(err)
+            }
+        }
+    }
+
+    /// Forget the locks of the instance once a connection was replaced, if it
+    /// uses advisory locks, which ended with the connection that held them
+    pub(crate) fn lose_advisory_locks(&mut self) {
+        if self.dialect.backend != Backend::Advisory {
+            return;
+        }
+        for lock in self.held.locks() {
+            log::warn!(
+                target: "cocklock::reconnect",
+                "Lost the advisory lock {} along with its connection",
+                lock.lock_name
+            );
+            self.held.released(&lock.lock_name);
+            self.hooks
+                .reached(|| Transition::LeaseLost(lock.lock_name.clone()));
+            #[cfg(feature = "webhooks")]
+            self.webhooks
+                .fire(LockEvent::Expired, &lock.lock_name, self.id);
+        }
+    }
+}
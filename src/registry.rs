@@ -0,0 +1,133 @@
+//! Detecting processes that share a client ID
+//!
+//! Locks belong to the client ID, so two processes started with the same ID,
+//! say from a copied configuration, both believe they hold every lock either
+//! of them takes and nothing ever fails. With duplicate detection every
+//! process registers its ID in `<table>_instances` under a random incarnation
+//! of its own and keeps the registration fresh with heartbeats. Finding the
+//! ID registered by another incarnation that was seen recently means that
+//! another live process uses it. The incarnation is shared by the whole
+//! process, since its instances may share an ID on purpose, e.g. those of
+//! `CockLockBuilder::build_shared`.
+
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use postgres::types::ToSql;
+use uuid::Uuid;
+
+use crate::errors::CockLockError;
+use crate::lock::{lease_ms, CockLock, Operation};
+
+/// The incarnation of this process, drawn on first use
+static INCARNATION: OnceLock<Uuid> = OnceLock::new();
+
+/// See `CockLockBuilder::with_duplicate_detection`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DuplicateDetection {
+    /// How long a registration stands for a live process after its last
+    /// heartbeat
+    stale_after: Duration,
+    last_heartbeat: Option<Instant>,
+}
+
+impl DuplicateDetection {
+    pub(crate) fn new(stale_after: Duration) -> Self {
+        Self {
+            stale_after,
+            last_heartbeat: None,
+        }
+    }
+
+    /// Whether a third of `stale_after` passed since the last heartbeat
+    fn is_due(&self, now: Instant) -> bool {
+        self.last_heartbeat
+            .is_none_or(|last_heartbeat| now >= last_heartbeat + self.stale_after / 3)
+    }
+}
+
+impl CockLock {
+    /// Register the ID of the instance for this process, failing with
+    /// `DuplicateInstance` if another live process registered it
+    ///
+    /// Does nothing without `CockLockBuilder::with_duplicate_detection`.
+    /// Instances send a heartbeat when they are built and when they take a
+    /// lock once a third of the stale period passed, so instances that rarely
+    /// lock should call this periodically.
+    pub fn heartbeat(&mut self) -> Result<(), CockLockError> {
+        let detection = match self.duplicate_detection {
+            Some(detection) => detection,
+            None => return Ok(()),
+        };
+        let sent_at = self.clock.now();
+        let id = self.id;
+        let incarnation = *INCARNATION.get_or_init(Uuid::new_v4);
+        let stale_after_ms = lease_ms(detection.stale_after);
+        let params: &[&(dyn ToSql + Sync)] = &[&id, &incarnation, &stale_after_ms];
+        let results = self.on_every_client(Operation::Heartbeat, &mut |client, queries, _| {
+            Ok(client.query_opt(&queries.heartbeat, params)?.is_some())
+        });
+
+        let mut duplicate = false;
+        let mut first_error = None;
+        for (_, result) in results {
+            match result {
+                Ok(registered) => duplicate |= !registered,
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        if duplicate {
+            log::error!(
+                target: "cocklock::registry",
+                "Another live process uses the client ID {id}, \
+                 its locks don't exclude the locks of this one"
+            );
+            return Err(CockLockError::DuplicateInstance(id));
+        }
+        if let Some(err) = first_error {
+            return Err(err);
+        }
+        self.duplicate_detection = Some(DuplicateDetection {
+            last_heartbeat: Some(sent_at),
+            ..detection
+        });
+        Ok(())
+    }
+
+    /// Send a heartbeat if one is due, only failing if it found a duplicate
+    ///
+    /// Other errors are left to the statements that follow it.
+    pub(crate) fn heartbeat_if_due(&mut self) -> Result<(), CockLockError> {
+        let now = self.clock.now();
+        if !self
+            .duplicate_detection
+            .is_some_and(|detection| detection.is_due(now))
+        {
+            return Ok(());
+        }
+        match self.heartbeat() {
+            Err(err @ CockLockError::DuplicateInstance(_)) => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::DuplicateDetection;
+
+    #[test]
+    fn heartbeats_are_due_every_third_of_the_stale_period() {
+        let started_at = Instant::now();
+        let mut detection = DuplicateDetection::new(Duration::from_secs(30));
+        assert!(detection.is_due(started_at));
+
+        detection.last_heartbeat = Some(started_at);
+        assert!(!detection.is_due(started_at + Duration::from_secs(9)));
+        assert!(detection.is_due(started_at + Duration::from_secs(10)));
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::deterministic::{Clock, SystemClock};
+use crate::errors::CockLockError;
+use crate::hooks::Transition;
+use crate::lock::{lease_ms, CockLock};
+
+/// The most locks renewed by a single statement unless configured otherwise
+pub static DEFAULT_MAX_BATCH_SIZE: usize = 500;
+
+/// Keeps a large number of locks alive while keeping the database load flat
+///
+/// Every scheduled lock is renewed once per `interval`. Instead of renewing
+/// all locks at the same moment, each lock gets a fixed slot within the
+/// interval and the locks that are due together are renewed with one
+/// `CockLock::extend_many` statement per batch.
+///
+/// The scheduler doesn't run by itself: call `run_pending` whenever
+/// `next_due` has passed. The interval should be well below the lease
+/// timeout, a third of it is a good start.
+pub struct RenewalScheduler {
+    timeout_ms: i64,
+    interval: Duration,
+    max_batch_size: usize,
+    due: BTreeMap<String, Instant>,
+    clock: Arc<dyn Clock>,
+}
+
+impl RenewalScheduler {
+    /// Create a scheduler renewing leases to `timeout_ms` every `interval`
+    ///
+    /// Fails with `CockLockError::ZeroInterval` if `interval` is zero, which
+    /// would renew every lock on every call of `run_pending`.
+    pub fn new(timeout_ms: i32, interval: Duration) -> Result<Self, CockLockError> {
+        Self::with_lease_ms(timeout_ms.into(), interval)
+    }
+
+    /// Create a scheduler renewing leases to `lease` every `interval`, with
+    /// leases rounded like the ones of `CockLock::lock_for`
+    pub fn for_lease(lease: Duration, interval: Duration) -> Result<Self, CockLockError> {
+        Self::with_lease_ms(lease_ms(lease), interval)
+    }
+
+    fn with_lease_ms(timeout_ms: i64, interval: Duration) -> Result<Self, CockLockError> {
+        if interval.is_zero() {
+            return Err(CockLockError::ZeroInterval(
+                "the renewal scheduler".to_owned(),
+            ));
+        }
+        Ok(Self {
+            timeout_ms,
+            interval,
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            due: BTreeMap::new(),
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Change the most locks renewed by a single statement
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size.max(1);
+        self
+    }
+
+    /// Replace the clock that decides when renewals are due, e.g. with a
+    /// `VirtualClock` in tests
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Start renewing a lock that is held by the instance
+    pub fn add<T: ToString>(&mut self, lock_name: T) {
+        let lock_name = lock_name.to_string();
+        let due = self.clock.now() + self.slot(&lock_name);
+        self.due.insert(lock_name, due);
+    }
+
+    /// Stop renewing a lock, returns whether it was scheduled
+    pub fn remove<T: ToString>(&mut self, lock_name: T) -> bool {
+        self.due.remove(&lock_name.to_string()).is_some()
+    }
+
+    /// The number of scheduled locks
+    pub fn len(&self) -> usize {
+        self.due.len()
+    }
+
+    /// Whether no locks are scheduled
+    pub fn is_empty(&self) -> bool {
+        self.due.is_empty()
+    }
+
+    /// When the next renewal is due, `None` if no locks are scheduled
+    pub fn next_due(&self) -> Option<Instant> {
+        self.due.values().min().copied()
+    }
+
+    /// Renew all locks that are due
+    ///
+    /// Returns the names of the locks that could not be renewed because they
+    /// are no longer held by the instance. Those locks are unscheduled.
+    pub fn run_pending(&mut self, cock_lock: &mut CockLock) -> Result<Vec<String>, CockLockError> {
+        let now = self.clock.now();
+        let due: Vec<String> = self
+            .due
+            .iter()
+            .filter(|(_, due)| **due <= now)
+            .map(|(lock_name, _)| lock_name.clone())
+            .collect();
+
+        let mut lost = vec![];
+        for batch in due.chunks(self.max_batch_size) {
+            for lock_name in batch {
+                cock_lock
+                    .hooks
+                    .reached(|| Transition::Renewing(lock_name.clone()));
+            }
+            let extended = cock_lock.extend_many_as(cock_lock.id, batch, self.timeout_ms)?;
+            for (lock_name, is_extended) in extended {
+                if is_extended {
+                    cock_lock
+                        .hooks
+                        .reached(|| Transition::Renewed(lock_name.clone()));
+                    // When renewals fell behind, continue from now rather than
+                    // catching up with a burst of statements
+                    if let Some(due) = self.due.get_mut(&lock_name) {
+                        *due += self.interval;
+                        if *due <= now {
+                            *due = now + self.interval;
+                        }
+                    }
+                } else {
+                    cock_lock
+                        .hooks
+                        .reached(|| Transition::LeaseLost(lock_name.clone()));
+                    self.due.remove(&lock_name);
+                    lost.push(lock_name);
+                }
+            }
+        }
+
+        Ok(lost)
+    }
+
+    /// The offset of a lock within the interval, derived from its name so it
+    /// stays the same when the lock is scheduled again
+    fn slot(&self, lock_name: &str) -> Duration {
+        let mut hasher = DefaultHasher::new();
+        lock_name.hash(&mut hasher);
+        self.interval
+            .mul_f64((hasher.finish() % 1_000) as f64 / 1_000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RenewalScheduler;
+    use crate::deterministic::{Clock, VirtualClock};
+    use crate::errors::CockLockError;
+
+    #[test]
+    fn renewals_follow_the_clock() {
+        let clock = VirtualClock::new();
+        let interval = Duration::from_secs(10);
+        let mut scheduler = RenewalScheduler::new(30_000, interval)
+            .unwrap()
+            .with_clock(clock.clone());
+        scheduler.add("lock");
+
+        let slot = scheduler.slot("lock");
+        assert_eq!(scheduler.next_due(), Some(clock.now() + slot));
+        clock.advance(interval);
+        assert!(scheduler.next_due().unwrap() <= clock.now());
+    }
+
+    #[test]
+    fn renewals_are_spread_over_the_interval() {
+        let interval = Duration::from_secs(10);
+        let mut scheduler = RenewalScheduler::new(30_000, interval).unwrap();
+        for index in 0..100 {
+            scheduler.add(format!("lock-{index}"));
+        }
+
+        let slots: Vec<Duration> = (0..100)
+            .map(|index| scheduler.slot(&format!("lock-{index}")))
+            .collect();
+        assert!(slots.iter().all(|slot| *slot < interval));
+        assert!(slots.iter().any(|slot| *slot < interval / 2));
+        assert!(slots.iter().any(|slot| *slot >= interval / 2));
+        assert_eq!(scheduler.len(), 100);
+    }
+
+    #[test]
+    fn intervals_must_not_be_zero() {
+        assert!(matches!(
+            RenewalScheduler::new(30_000, Duration::ZERO),
+            Err(CockLockError::ZeroInterval(_))
+        ));
+    }
+}
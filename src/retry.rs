@@ -0,0 +1,216 @@
+use std::time::Duration;
+
+use crate::abort::{AbortHandle, ABORT_POLL_INTERVAL};
+use crate::deterministic::SeededRng;
+use crate::errors::CockLockError;
+use crate::fencing::FencingToken;
+use crate::guard::DEFAULT_RELEASE_TIMEOUT;
+use crate::lock::CockLock;
+use crate::notify::Notifier;
+
+/// How `CockLock::lock_wait` backs off between attempts
+///
+/// The delay starts at `base_delay` and doubles with every attempt up to
+/// `max_delay`. Jitter shortens each delay by a random share of up to
+/// `jitter`, so that instances waiting for the same lock spread out instead
+/// of retrying in lockstep.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Between 0 (no jitter) and 1 (delays anywhere from 0 to the full delay),
+    /// only set through `with_jitter` to keep it in that range
+    jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(2),
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set the jitter, clamped to between 0 and 1; NaN turns jitter off
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = if jitter.is_nan() {
+            0.0
+        } else {
+            jitter.clamp(0.0, 1.0)
+        };
+        self
+    }
+
+    pub fn jitter(&self) -> f64 {
+        self.jitter
+    }
+
+    /// The delay after the attempt with the given number, counting from 0
+    pub(crate) fn delay(&self, attempt: u32, rng: &mut SeededRng) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let random = (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        delay.mul_f64(1.0 - self.jitter * random)
+    }
+}
+
+impl CockLock {
+    /// Try to create a lock like `lock`, retrying while it is held by someone
+    /// else for up to `wait`
+    ///
+    /// The attempts back off according to the `RetryPolicy` of the builder,
+    /// with unlock notifications turned on a release of the lock ends the
+    /// back-off early. Fails with `CockLockError::NotAvailable` if the lock is
+    /// still held once `wait` passed, other errors are returned right away.
+    pub fn lock_wait<T: ToString>(
+        &mut self,
+        lock_name: T,
+        timeout_ms: i32,
+        wait: Duration,
+    ) -> Result<FencingToken, CockLockError> {
+        self.wait_for_lock(&lock_name.to_string(), timeout_ms, wait, None)
+    }
+
+    /// Like `lock_wait`, but giving up with `CockLockError::Aborted` as soon
+    /// as `abort` is aborted from another thread
+    ///
+    /// The back-off checks for aborts every few milliseconds. A lock that
+    /// was taken while the abort came in is released again before returning,
+    /// as are the clients that granted it when `Quorum::Majority` falls
+    /// short.
+    pub fn lock_wait_abortable<T: ToString>(
+        &mut self,
+        lock_name: T,
+        timeout_ms: i32,
+        wait: Duration,
+        abort: &AbortHandle,
+    ) -> Result<FencingToken, CockLockError> {
+        self.wait_for_lock(&lock_name.to_string(), timeout_ms, wait, Some(abort))
+    }
+
+    fn wait_for_lock(
+        &mut self,
+        lock_name: &str,
+        timeout_ms: i32,
+        wait: Duration,
+        abort: Option<&AbortHandle>,
+    ) -> Result<FencingToken, CockLockError> {
+        let lock_name = lock_name.to_owned();
+        let aborted = || abort.is_some_and(AbortHandle::is_aborted);
+        let deadline = self.clock.now() + wait;
+        let mut attempt = 0;
+        loop {
+            if aborted() {
+                return Err(CockLockError::Aborted);
+            }
+            match self.lock(&lock_name, timeout_ms) {
+                Err(CockLockError::NotAvailable) => {}
+                Ok(_) if aborted() => {
+                    let _ = self.unlock_within(&lock_name, DEFAULT_RELEASE_TIMEOUT);
+                    return Err(CockLockError::Aborted);
+                }
+                result => return result,
+            }
+
+            let remaining = deadline.saturating_duration_since(self.clock.now());
+            if remaining.is_zero() {
+                return Err(CockLockError::NotAvailable);
+            }
+            let delay = self
+                .retry_policy
+                .delay(attempt, &mut self.rng)
+                .min(remaining);
+            match abort {
+                Some(abort) => self.wait_abortably(&lock_name, delay, abort),
+                None => self.wait_for_release(&lock_name, delay),
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Wait like `wait_for_release` in slices of `ABORT_POLL_INTERVAL`,
+    /// stopping early once `abort` is aborted
+    fn wait_abortably(&mut self, lock_name: &str, delay: Duration, abort: &AbortHandle) {
+        let deadline = self.clock.now() + delay;
+        while !abort.is_aborted() {
+            let remaining = deadline.saturating_duration_since(self.clock.now());
+            if remaining.is_zero() {
+                return;
+            }
+            self.wait_for_release(lock_name, remaining.min(ABORT_POLL_INTERVAL));
+        }
+    }
+
+    /// Wait up to `delay` before trying a lock again, less if its release is
+    /// announced
+    ///
+    /// Without unlock notifications, or if the listener fails, this simply
+    /// sleeps.
+    pub(crate) fn wait_for_release(&mut self, lock_name: &str, delay: Duration) {
+        if self.unlock_notifications && self.listener.is_none() {
+            self.listener = self.listen_notifier().ok();
+        }
+        match &mut self.listener {
+            Some(listener) => {
+                if listener.wait(lock_name, delay).is_err() {
+                    self.listener = None;
+                }
+            }
+            None => self.clock.sleep(delay),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+    use crate::deterministic::SeededRng;
+
+    #[test]
+    fn delays_grow_up_to_the_maximum() {
+        let mut rng = SeededRng::new(7);
+        let policy = RetryPolicy::new()
+            .with_base_delay(Duration::from_millis(100))
+            .with_max_delay(Duration::from_millis(500))
+            .with_jitter(0.0);
+        let delays: Vec<_> = (0..5)
+            .map(|attempt| policy.delay(attempt, &mut rng))
+            .collect();
+        assert_eq!(delays, [100, 200, 400, 500, 500].map(Duration::from_millis));
+
+        let jittered = policy.with_jitter(0.5);
+        for attempt in 0..100 {
+            let delay = jittered.delay(attempt % 3, &mut rng);
+            let full = policy.delay(attempt % 3, &mut rng);
+            assert!(delay <= full && delay >= full / 2);
+        }
+    }
+
+    #[test]
+    fn jitter_stays_between_0_and_1() {
+        assert_eq!(RetryPolicy::new().with_jitter(f64::NAN).jitter(), 0.0);
+        assert_eq!(RetryPolicy::new().with_jitter(-1.0).jitter(), 0.0);
+        assert_eq!(RetryPolicy::new().with_jitter(3.0).jitter(), 1.0);
+    }
+}
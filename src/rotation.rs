@@ -0,0 +1,223 @@
+//! Duties that rotate across a fleet
+//!
+//! Some recurring work, like polling an upstream API, is best done by one
+//! instance at a time but shouldn't stick to the same instance forever. A
+//! `Rotation` hands the lock out in turns: a turn is a lease that can't be
+//! extended, so it ends after the slice at the latest, and an instance that
+//! finds the lock taken queues up as its next holder with a standby claim,
+//! so the instance whose turn just ended can't take it straight back.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::deterministic::Clock;
+use crate::errors::CockLockError;
+use crate::fencing::FencingToken;
+use crate::lock::{lease_ms, CockLock};
+
+/// A lock held in turns of at most `slice_ms`, see `CockLock::take_turn`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rotation {
+    lock_name: String,
+    slice_ms: i32,
+    claim_ms: i64,
+}
+
+impl Rotation {
+    /// Turns of `slice_ms` on the lock `lock_name`, with claims of waiting
+    /// instances lasting two slices
+    pub fn new<T: ToString>(lock_name: T, slice_ms: i32) -> Self {
+        Self {
+            lock_name: lock_name.to_string(),
+            slice_ms,
+            claim_ms: i64::from(slice_ms) * 2,
+        }
+    }
+
+    /// Change how long a waiting instance stays next in line without asking
+    /// again, which should cover the time between its attempts
+    pub fn with_claim(mut self, claim_ms: i32) -> Self {
+        self.claim_ms = claim_ms.into();
+        self
+    }
+
+    /// Change how long a waiting instance stays next in line like
+    /// `with_claim`, rounded like the lease of `CockLock::lock_for`
+    pub fn with_claim_for(mut self, claim: Duration) -> Self {
+        self.claim_ms = lease_ms(claim);
+        self
+    }
+
+    pub fn lock_name(&self) -> &str {
+        &self.lock_name
+    }
+
+    pub fn slice_ms(&self) -> i32 {
+        self.slice_ms
+    }
+}
+
+/// The turn of this instance, see `CockLock::take_turn`
+#[derive(Debug, Clone)]
+pub struct Turn {
+    lock_name: String,
+    token: FencingToken,
+    ends_at: Instant,
+    clock: Arc<dyn Clock>,
+}
+
+impl Turn {
+    pub fn lock_name(&self) -> &str {
+        &self.lock_name
+    }
+
+    pub fn token(&self) -> FencingToken {
+        self.token
+    }
+
+    /// The time left of the turn on the clock of the instance, counted from
+    /// before the lock was taken
+    pub fn remaining(&self) -> Duration {
+        self.ends_at.saturating_duration_since(self.clock.now())
+    }
+
+    /// Whether the turn ran out, after which another instance may hold the
+    /// lock
+    pub fn is_over(&self) -> bool {
+        self.remaining().is_zero()
+    }
+}
+
+impl CockLock {
+    /// Take a turn of the rotation if it is this instance's, `None` if not
+    ///
+    /// While another instance has its turn, this instance is registered as
+    /// next in line unless some other instance already is, so calling this
+    /// regularly from every instance passes the lock around. The turn must
+    /// not be extended; end it early with `end_turn` once the work is done.
+    /// Until then, relocking, extending or refreshing the lock past the end
+    /// of the turn fails with `CockLockError::LeaseTooLong`. Fails with
+    /// `CockLockError::AlreadyHeldByUs` while this instance's turn lasts.
+    pub fn take_turn(&mut self, rotation: &Rotation) -> Result<Option<Turn>, CockLockError> {
+        if rotation.slice_ms == 0 {
+            return Err(CockLockError::InfiniteLease(rotation.lock_name.clone()));
+        }
+        self.require_table("rotations")?;
+        if self.turn_ends_at(&rotation.lock_name).is_some() {
+            return Err(CockLockError::AlreadyHeldByUs);
+        }
+        let started_at = self.clock.now();
+        let slice = Duration::from_millis(rotation.slice_ms.unsigned_abs().into());
+        match self.try_acquire(&rotation.lock_name, rotation.slice_ms) {
+            Ok(token) => {
+                let ends_at = started_at + slice;
+                self.turns.insert(rotation.lock_name.clone(), ends_at);
+                Ok(Some(Turn {
+                    lock_name: rotation.lock_name.clone(),
+                    token,
+                    ends_at,
+                    clock: self.clock.clone(),
+                }))
+            }
+            Err(CockLockError::NotAvailable) => {
+                match self.claim_next_turn(rotation.lock_name.clone(), rotation.claim_ms) {
+                    Ok(()) | Err(CockLockError::NotAvailable) => Ok(None),
+                    Err(err) => Err(err),
+                }
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// End a turn before its slice ran out, letting the next instance in
+    /// line take over
+    pub fn end_turn(&mut self, turn: Turn) -> Result<(), CockLockError> {
+        self.turns.remove(&turn.lock_name);
+        self.unlock(turn.lock_name)
+    }
+
+    /// When the turn of this instance on a lock it still holds ends, if it
+    /// took the lock for a turn that lasts
+    pub(crate) fn turn_ends_at(&self, lock_name: &str) -> Option<Instant> {
+        let ends_at = self.turns.get(lock_name)?;
+        if ends_at > self.clock.now() && self.held.get(lock_name).is_some() {
+            return Some(ends_at);
+        }
+        self.turns.remove(lock_name);
+        None
+    }
+
+    /// Reject a lease of `timeout_ms` from now on a lock that would outlast
+    /// the turn of this instance, so that a turn can't be stretched by
+    /// relocking, extending or refreshing the lock
+    pub(crate) fn check_turn(&self, lock_name: &str, timeout_ms: i64) -> Result<(), CockLockError> {
+        let Some(ends_at) = self.turn_ends_at(lock_name) else {
+            return Ok(());
+        };
+        let left_ms = ends_at
+            .saturating_duration_since(self.clock.now())
+            .as_millis();
+        let left_ms = i64::try_from(left_ms).unwrap_or(i64::MAX);
+        if timeout_ms == 0 || timeout_ms > left_ms {
+            return Err(CockLockError::LeaseTooLong(lock_name.to_owned(), left_ms));
+        }
+        Ok(())
+    }
+
+    /// Run `duty` if it is this instance's turn, ending the turn afterwards
+    ///
+    /// Returns `None` without running `duty` if it isn't. `duty` gets the
+    /// turn to check how much of it is left, and should stop once it is
+    /// over.
+    pub fn run_turn<R, F: FnOnce(&Turn) -> R>(
+        &mut self,
+        rotation: &Rotation,
+        duty: F,
+    ) -> Result<Option<R>, CockLockError> {
+        let turn = match self.take_turn(rotation)? {
+            Some(turn) => turn,
+            None => return Ok(None),
+        };
+        let result = duty(&turn);
+        if turn.is_over() {
+            // The lock may be someone else's by now
+            return Ok(Some(result));
+        }
+        self.end_turn(turn)?;
+        Ok(Some(result))
+    }
+}
+
+/// When the turns of an instance end, by lock name
+///
+/// Shared between the workers of a `SharedCockLock` like `HeldLocks`.
+#[derive(Debug, Default)]
+pub(crate) struct TurnEnds {
+    ends: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl TurnEnds {
+    /// The same turns, for another worker of a shared instance
+    pub fn share(&self) -> Self {
+        Self {
+            ends: Arc::clone(&self.ends),
+        }
+    }
+
+    fn locked(&self) -> std::sync::MutexGuard<'_, HashMap<String, Instant>> {
+        self.ends.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn insert(&self, lock_name: String, ends_at: Instant) {
+        self.locked().insert(lock_name, ends_at);
+    }
+
+    pub fn remove(&self, lock_name: &str) {
+        self.locked().remove(lock_name);
+    }
+
+    pub fn get(&self, lock_name: &str) -> Option<Instant> {
+        self.locked().get(lock_name).copied()
+    }
+}
@@ -0,0 +1,206 @@
+//! Realistic lock usage to run against a real cluster
+//!
+//! Each scenario drives a `CockLock` the way a typical application would and
+//! returns a `ScenarioReport` of what happened. Running the same scenario
+//! from several processes at once, as the `scenario_*` examples do, shows
+//! whether a configuration holds up before it goes to production.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::errors::CockLockError;
+use crate::identity::Identity;
+use crate::lock::CockLock;
+
+/// What a scenario run saw
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScenarioReport {
+    pub scenario: &'static str,
+    /// The ID of the instance that ran the scenario
+    pub instance: Identity,
+    /// The lock operations attempted
+    pub attempts: u32,
+    /// The attempts that got what they asked for
+    pub acquired: u32,
+    /// The attempts that found the lock held by someone else
+    pub contended: u32,
+    /// The attempts that failed with an error
+    pub failed: u32,
+    /// The error of the last failed attempt
+    pub last_error: Option<String>,
+    /// The longest a single attempt took
+    pub slowest: Duration,
+    pub elapsed: Duration,
+}
+
+impl ScenarioReport {
+    fn new(scenario: &'static str, instance: Identity) -> Self {
+        Self {
+            scenario,
+            instance,
+            attempts: 0,
+            acquired: 0,
+            contended: 0,
+            failed: 0,
+            last_error: None,
+            slowest: Duration::ZERO,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Count the outcome of an attempt that started at `started_at`
+    fn record<T>(&mut self, started_at: Instant, result: &Result<T, CockLockError>) {
+        self.attempts += 1;
+        self.slowest = self.slowest.max(started_at.elapsed());
+        match result {
+            Ok(_) => self.acquired += 1,
+            Err(CockLockError::NotAvailable) => self.contended += 1,
+            Err(err) => {
+                self.failed += 1;
+                self.last_error = Some(err.to_string());
+            }
+        }
+    }
+}
+
+impl fmt::Display for ScenarioReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on {}: {} attempts, {} acquired, {} contended, {} failed, slowest {}ms, \
+             {}ms in total",
+            self.scenario,
+            self.instance,
+            self.attempts,
+            self.acquired,
+            self.contended,
+            self.failed,
+            self.slowest.as_millis(),
+            self.elapsed.as_millis(),
+        )?;
+        if let Some(err) = &self.last_error {
+            write!(f, " (last error: {err})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Campaign for a leadership lock once per `interval` for `rounds` rounds
+///
+/// The leader keeps its lease by locking again each round, everyone else
+/// takes over once it stops. `acquired` counts the rounds this instance led,
+/// `on_round` is told after each round whether it did.
+pub fn leader_election<F: FnMut(u32, bool)>(
+    cock_lock: &mut CockLock,
+    lock_name: &str,
+    lease_ms: i32,
+    interval: Duration,
+    rounds: u32,
+    mut on_round: F,
+) -> ScenarioReport {
+    let started_at = Instant::now();
+    let mut report = ScenarioReport::new("leader election", cock_lock.id);
+    for round in 0..rounds {
+        let attempt_started_at = Instant::now();
+        let result = cock_lock.lock(lock_name, lease_ms);
+        report.record(attempt_started_at, &result);
+        on_round(round, result.is_ok());
+        cock_lock.clock.sleep(interval);
+    }
+
+    // Step down so the others don't wait for the lease to run out
+    let _ = cock_lock.unlock(lock_name);
+    report.elapsed = started_at.elapsed();
+    report
+}
+
+/// Work through a shared list of jobs, each taken by a single instance
+///
+/// Every job is claimed with a lock named `<queue>/<job>` that is kept until
+/// its lease of `lease_ms` runs out, so a job is done once within that time
+/// however many instances run the queue. `acquired` counts the jobs this
+/// instance did.
+pub fn job_queue<T: ToString, F: FnMut(&str)>(
+    cock_lock: &mut CockLock,
+    queue: &str,
+    jobs: &[T],
+    lease_ms: i32,
+    mut work: F,
+) -> ScenarioReport {
+    let started_at = Instant::now();
+    let mut report = ScenarioReport::new("job queue", cock_lock.id);
+    for job in jobs {
+        let job = job.to_string();
+        let attempt_started_at = Instant::now();
+        let result = cock_lock.lock(format!("{queue}/{job}"), lease_ms);
+        report.record(attempt_started_at, &result);
+        if result.is_ok() {
+            work(&job);
+        }
+    }
+
+    report.elapsed = started_at.elapsed();
+    report
+}
+
+/// Wait at a barrier until `parties` instances arrived or `timeout` passed,
+/// see `CockLock::wait_at_barrier`
+///
+/// `acquired` is 1 if every party arrived in time, `contended` is 1 if not.
+pub fn barrier(
+    cock_lock: &mut CockLock,
+    barrier: &str,
+    parties: usize,
+    lease_ms: i32,
+    timeout: Duration,
+) -> ScenarioReport {
+    let started_at = Instant::now();
+    let mut report = ScenarioReport::new("barrier", cock_lock.id);
+    let result = cock_lock
+        .wait_at_barrier(barrier, parties, lease_ms, timeout)
+        .and_then(|all_arrived| {
+            if all_arrived {
+                Ok(())
+            } else {
+                Err(CockLockError::NotAvailable)
+            }
+        });
+    report.record(started_at, &result);
+
+    report.elapsed = started_at.elapsed();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use uuid::Uuid;
+
+    use super::ScenarioReport;
+    use crate::errors::CockLockError;
+
+    #[test]
+    fn reports_count_each_outcome() {
+        let mut report = ScenarioReport::new("test", Uuid::nil().into());
+        report.record(Instant::now(), &Ok(()));
+        report.record::<()>(Instant::now(), &Err(CockLockError::NotAvailable));
+        report.record::<()>(
+            Instant::now(),
+            &Err(CockLockError::NoClientsAvailable(vec![])),
+        );
+        report.elapsed = Duration::from_millis(5);
+
+        assert_eq!(
+            (
+                report.attempts,
+                report.acquired,
+                report.contended,
+                report.failed
+            ),
+            (3, 1, 1, 1)
+        );
+        assert!(report.to_string().contains("3 attempts"));
+        assert!(report.to_string().contains("last error"));
+    }
+}
@@ -0,0 +1,99 @@
+//! Freshness tokens for destructive maintenance
+//!
+//! Two operators cleaning up or renaming the same table at once would each
+//! act on the table as they last saw it. `CockLock::verify` hands out a token
+//! describing the lock table on every client, and `clean_up` and
+//! `rename_table` refuse to run once the table no longer matches it, e.g.
+//! because another operator dropped, recreated or migrated it meanwhile.
+
+use postgres::GenericClient;
+
+use crate::errors::CockLockError;
+use crate::lock::{CockLock, Operation};
+use crate::queries::PG_SCHEMA_FINGERPRINT_QUERY;
+
+/// The lock table as `CockLock::verify` found it, required by the
+/// operations that drop or replace it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaToken {
+    table_name: String,
+    fingerprints: Vec<Fingerprint>,
+}
+
+/// The identity and columns of the lock table on one client; a table that
+/// was dropped and created again gets a new identity
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Fingerprint {
+    /// 0 where the table doesn't exist
+    table_oid: i64,
+    columns: String,
+}
+
+impl SchemaToken {
+    /// Whether the lock table on the client at `index` is still the way the
+    /// token describes it, read through `client`
+    ///
+    /// Meant to be called in the transaction that changes the table, once the
+    /// table is locked, so that it can't change between the check and the
+    /// change.
+    pub(crate) fn matches<C: GenericClient>(
+        &self,
+        client: &mut C,
+        index: usize,
+        (table_name, schema): (&str, Option<&str>),
+    ) -> Result<bool, postgres::Error> {
+        Ok(self.fingerprints.get(index) == Some(&fingerprint(client, table_name, schema)?))
+    }
+}
+
+impl CockLock {
+    /// Read the lock table on every client, returning a token for
+    /// `clean_up` and `rename_table`
+    ///
+    /// Get a fresh token right before each of them; a token only stays
+    /// valid as long as nobody changes the table.
+    pub fn verify(&mut self) -> Result<SchemaToken, CockLockError> {
+        Ok(SchemaToken {
+            table_name: self.table().key(),
+            fingerprints: self.fingerprints(Operation::Verify)?,
+        })
+    }
+
+    /// Fail with `StaleSchemaToken` unless the lock table is still the way
+    /// `token` describes it on every client
+    pub(crate) fn check_schema_token(
+        &mut self,
+        token: &SchemaToken,
+        operation: Operation,
+    ) -> Result<(), CockLockError> {
+        if token.table_name != self.table().key()
+            || token.fingerprints != self.fingerprints(operation)?
+        {
+            return Err(CockLockError::StaleSchemaToken(self.table_name.clone()));
+        }
+        Ok(())
+    }
+
+    fn fingerprints(&mut self, operation: Operation) -> Result<Vec<Fingerprint>, CockLockError> {
+        let mut fingerprints = vec![];
+        for (client, label) in self.clients.iter_mut().zip(&self.labels) {
+            fingerprints.push(
+                fingerprint(&mut **client, &self.table_name, self.schema.as_deref())
+                    .map_err(|err| CockLockError::postgres(err, operation, label))?,
+            );
+        }
+        Ok(fingerprints)
+    }
+}
+
+fn fingerprint<C: GenericClient>(
+    client: &mut C,
+    table_name: &str,
+    schema: Option<&str>,
+) -> Result<Fingerprint, postgres::Error> {
+    let row = client.query_one(PG_SCHEMA_FINGERPRINT_QUERY, &[&table_name, &schema])?;
+    Ok(Fingerprint {
+        table_oid: row.get("table_oid"),
+        columns: row.get("columns"),
+    })
+}
@@ -0,0 +1,141 @@
+//! Everything coordinated through a lock table at once, for dashboards
+//!
+//! A dashboard rendering locks, queues and instances one call at a time would
+//! show a mix of moments and hit every client several times per refresh.
+//! `CockLock::snapshot` reads all of it from each client in a single read-only
+//! transaction instead, and keeps going when a client fails, so a page can
+//! still show the others.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::identity::Identity;
+use crate::listing::LockInfo;
+use crate::lock::{CockLock, Operation};
+
+/// The instance next in line for a lock, see `CockLock::register_standby`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StandbyInfo {
+    pub lock_name: String,
+    pub holder: Identity,
+    /// The time left until the claim runs out unless registered again
+    pub remaining_ms: i64,
+}
+
+/// An instance that registered its ID, see
+/// `CockLockBuilder::with_duplicate_detection`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InstanceInfo {
+    pub client_id: Identity,
+    /// The time since its last heartbeat
+    pub silent_ms: i64,
+}
+
+/// What one client knows, or why it couldn't tell
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ClientSnapshot {
+    pub label: String,
+    /// The active locks by name; the holders of election locks are the
+    /// leaders
+    pub locks: Vec<LockInfo>,
+    pub standbys: Vec<StandbyInfo>,
+    pub instances: Vec<InstanceInfo>,
+    /// The error reading the client failed with, leaving the rest empty
+    pub error: Option<String>,
+}
+
+/// The coordination state of every client, see `CockLock::snapshot`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Snapshot {
+    pub table_name: String,
+    pub clients: Vec<ClientSnapshot>,
+}
+
+impl Snapshot {
+    /// How many clients could be read
+    pub fn reachable(&self) -> usize {
+        self.clients
+            .iter()
+            .filter(|client| client.error.is_none())
+            .count()
+    }
+}
+
+impl CockLock {
+    /// Read the locks, standbys and registered instances of every client
+    ///
+    /// Each client is read in one read-only transaction, so its part of the
+    /// snapshot is consistent in itself. Clients that fail are reported in
+    /// their `ClientSnapshot::error` rather than failing the snapshot, as is
+    /// every client of an instance using advisory locks.
+    pub fn snapshot(&mut self) -> Snapshot {
+        if let Err(err) = self.require_table("snapshots") {
+            return Snapshot {
+                table_name: self.table_name.clone(),
+                clients: self
+                    .labels
+                    .iter()
+                    .map(|label| ClientSnapshot {
+                        label: label.clone(),
+                        error: Some(err.to_string()),
+                        ..ClientSnapshot::default()
+                    })
+                    .collect(),
+            };
+        }
+        let results = self.on_every_client(Operation::List, &mut |client, queries, _| {
+            let mut transaction = client.build_transaction().read_only(true).start()?;
+            let locks = transaction.query(&queries.list_prefix, &[&"%"])?;
+            let standbys = transaction.query(&queries.standbys, &[])?;
+            let instances = transaction.query(&queries.instances, &[])?;
+            transaction.commit()?;
+            Ok((locks, standbys, instances))
+        });
+
+        let clients = results
+            .into_iter()
+            .map(|(index, result)| {
+                let label = self.labels[index].clone();
+                match result {
+                    Ok((locks, standbys, instances)) => ClientSnapshot {
+                        label,
+                        locks: locks
+                            .iter()
+                            .map(|row| self.lock_info_from_row(row))
+                            .collect(),
+                        standbys: standbys
+                            .iter()
+                            .map(|row| StandbyInfo {
+                                lock_name: row.get("lock_name"),
+                                holder: row.get("client_id"),
+                                remaining_ms: row.get("remaining_ms"),
+                            })
+                            .collect(),
+                        instances: instances
+                            .iter()
+                            .map(|row| InstanceInfo {
+                                client_id: row.get("client_id"),
+                                silent_ms: row.get("silent_ms"),
+                            })
+                            .collect(),
+                        error: None,
+                    },
+                    Err(err) => ClientSnapshot {
+                        label,
+                        error: Some(err.to_string()),
+                        ..ClientSnapshot::default()
+                    },
+                }
+            })
+            .collect();
+
+        Snapshot {
+            table_name: self.table_name.clone(),
+            clients,
+        }
+    }
+}
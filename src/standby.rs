@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use postgres::types::ToSql;
+
+use crate::errors::CockLockError;
+use crate::fencing::FencingToken;
+use crate::lock::{lease_ms, AcquisitionMode, CockLock, Operation};
+
+impl CockLock {
+    /// Register this instance as next in line for a lock held by someone else
+    ///
+    /// While the claim lasts, the lock can't be taken by anyone but this
+    /// instance once its holder releases it or lets it expire, so a standby
+    /// for a leader-style lock takes over with a single `lock` call instead of
+    /// racing every other candidate. The claim runs out after `timeout_ms`
+    /// unless registered again, and is dropped once this instance takes the
+    /// lock.
+    ///
+    /// A lock that is free is taken right away with a lease of `timeout_ms`
+    /// like `try_acquire` does, and its fencing token returned; `None` means
+    /// the instance was registered as next in line. The standby isn't told
+    /// when the holder lets go: it still has to call `lock` itself, and once
+    /// its claim ran out it races every other candidate again.
+    ///
+    /// Fails with `CockLockError::NotAvailable` if another instance is
+    /// already next in line, and with `CockLockError::AlreadyHeldByUs` if
+    /// this instance holds the lock.
+    pub fn register_standby<T: ToString>(
+        &mut self,
+        lock_name: T,
+        timeout_ms: i32,
+    ) -> Result<Option<FencingToken>, CockLockError> {
+        self.register_standby_ms(lock_name.to_string(), timeout_ms.into())
+    }
+
+    /// Register this instance as next in line like `register_standby`, with
+    /// a claim and lease of `timeout` that are rounded like the lease of
+    /// `lock_for`
+    pub fn register_standby_for<T: ToString>(
+        &mut self,
+        lock_name: T,
+        timeout: Duration,
+    ) -> Result<Option<FencingToken>, CockLockError> {
+        self.register_standby_ms(lock_name.to_string(), lease_ms(timeout))
+    }
+
+    fn register_standby_ms(
+        &mut self,
+        lock_name: String,
+        timeout_ms: i64,
+    ) -> Result<Option<FencingToken>, CockLockError> {
+        self.check_standby_support()?;
+        match self.try_acquire_ms(lock_name.clone(), timeout_ms) {
+            Ok(token) => return Ok(Some(token)),
+            Err(CockLockError::NotAvailable) => {}
+            Err(err) => return Err(err),
+        }
+        self.claim_next_turn(lock_name, timeout_ms).map(|()| None)
+    }
+
+    /// Register the claim of `register_standby` without trying to take the
+    /// lock first
+    pub(crate) fn claim_next_turn(
+        &mut self,
+        lock_name: String,
+        timeout_ms: i64,
+    ) -> Result<(), CockLockError> {
+        self.check_standby_support()?;
+        let id = self.id;
+        let params: &[&(dyn ToSql + Sync)] = &[&id, &lock_name, &timeout_ms];
+        let (_, result) = self.on_available_client(Operation::Standby, |client, queries, _| {
+            client.execute(&queries.register_standby, params)
+        });
+
+        match result? {
+            0 => Err(CockLockError::NotAvailable),
+            _ => {
+                self.standbys.insert(lock_name);
+                Ok(())
+            }
+        }
+    }
+
+    /// Give up the claim of `register_standby`, returns whether there was one
+    pub fn cancel_standby<T: ToString>(&mut self, lock_name: T) -> Result<bool, CockLockError> {
+        self.check_standby_support()?;
+        let lock_name = lock_name.to_string();
+        let id = self.id;
+        let params: &[&(dyn ToSql + Sync)] = &[&id, &lock_name];
+        let (_, result) = self.on_available_client(Operation::Standby, |client, queries, _| {
+            client.execute(&queries.cancel_standby, params)
+        });
+
+        self.standbys.remove(&lock_name);
+        Ok(result? > 0)
+    }
+
+    /// The append-only lock statements don't look at claims
+    fn check_standby_support(&self) -> Result<(), CockLockError> {
+        self.require_table("standbys")?;
+        if self.acquisition_mode == AcquisitionMode::AppendOnly {
+            return Err(CockLockError::Unsupported(
+                "standbys in append-only mode".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+//! Filling the lock table into statements
+//!
+//! The statements in `queries` refer to the lock table and the objects
+//! around it as `TABLE_NAME`, `TABLE_NAME_epochs`, `_lock_reap_TABLE_NAME`
+//! and so on. Each of those words becomes a quoted identifier, qualified with
+//! the schema if there is one, so any table name works and none can change
+//! the statement. Inside string literals, e.g. `nextval('TABLE_NAME_seq')`,
+//! the identifier is escaped as part of the literal. `TABLE_KEY` stands for
+//! the plain name where the table only serves as a key, e.g. of a
+//! notification channel.
+//!
+//! `NAMESPACE` becomes the namespace of the instance as a string literal, an
+//! empty one without a namespace. The tables next to the lock table key their
+//! rows by lock name alone, there `NAMESPACE_KEY || lock_name` keeps the
+//! namespaces apart while leaving the keys of the empty namespace as they
+//! were.
+
+/// The table placeholder in statements
+pub(crate) static TABLE_PLACEHOLDER: &str = "TABLE_NAME";
+
+static KEY_PLACEHOLDER: &str = "TABLE_KEY";
+
+static NAMESPACE_PLACEHOLDER: &str = "NAMESPACE";
+
+static NAMESPACE_KEY_PLACEHOLDER: &str = "NAMESPACE_KEY";
+
+/// Separates the namespace from the lock name in the keys of the tables next
+/// to the lock table
+static NAMESPACE_SEPARATOR: char = '\u{1f}';
+
+/// A lock table, in the schema of the connection unless given one, as seen
+/// from a namespace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Table {
+    pub schema: Option<String>,
+    pub name: String,
+    pub namespace: Option<String>,
+}
+
+impl Table {
+    pub fn new(schema: Option<&str>, name: &str) -> Self {
+        Self {
+            schema: schema.map(str::to_owned),
+            name: name.to_owned(),
+            namespace: None,
+        }
+    }
+
+    /// The same table seen from `namespace`
+    pub fn in_namespace(self, namespace: Option<&str>) -> Self {
+        Self {
+            namespace: namespace.map(str::to_owned),
+            ..self
+        }
+    }
+
+    /// The same table under another name, in the same schema and namespace
+    pub fn renamed(&self, name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            ..self.clone()
+        }
+    }
+
+    /// The key of `lock_name` in the tables next to the lock table, which is
+    /// the name itself outside of a namespace
+    pub fn namespaced(&self, lock_name: &str) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{namespace}{NAMESPACE_SEPARATOR}{lock_name}"),
+            None => lock_name.to_owned(),
+        }
+    }
+
+    /// The name identifying the table in keys, qualified with the schema if
+    /// there is one
+    pub fn key(&self) -> String {
+        match &self.schema {
+            Some(schema) => format!("{schema}.{}", self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Fill this table into every `TABLE_NAME` of `statement`
+    pub fn fill(&self, statement: &str) -> String {
+        fill(statement, &[(TABLE_PLACEHOLDER, self)])
+    }
+
+    /// The identifier of an object named after the table
+    fn identifier(&self, object: &str) -> String {
+        // Indexes always live in the schema of their table and can't be
+        // qualified when created, nor can the constraints they back
+        match &self.schema {
+            Some(schema) if !object.ends_with("_idx") && !object.ends_with("_key") => {
+                format!("{}.{}", quote_identifier(schema), quote_identifier(object))
+            }
+            _ => quote_identifier(object),
+        }
+    }
+
+    /// The namespace as a string literal
+    fn namespace_literal(&self) -> String {
+        quote_literal(self.namespace.as_deref().unwrap_or_default())
+    }
+
+    /// What `NAMESPACE_KEY` stands for, the prefix of the keys of lock names
+    /// in the tables next to the lock table
+    fn namespace_key(&self) -> String {
+        match &self.namespace {
+            Some(namespace) => format!(
+                "({} || chr({}))",
+                quote_literal(namespace),
+                u32::from(NAMESPACE_SEPARATOR)
+            ),
+            None => quote_literal(""),
+        }
+    }
+}
+
+/// Quote `literal` as a string
+fn quote_literal(literal: &str) -> String {
+    format!("'{}'", literal.replace('\'', "''"))
+}
+
+/// Quote `identifier` so that it is taken as it is
+pub(crate) fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Fill the tables into the words containing their placeholders, in a
+/// single pass so that names containing a placeholder stay as they are
+///
+/// `TABLE_KEY` and the namespace placeholders are filled from the first
+/// table.
+pub(crate) fn fill(statement: &str, tables: &[(&str, &Table)]) -> String {
+    let mut filled = String::with_capacity(statement.len());
+    let mut in_literal = false;
+    let mut word = String::new();
+    let flush = |word: &mut String, filled: &mut String, in_literal: bool| {
+        if word.is_empty() {
+            return;
+        }
+        let first = tables.first().map(|(_, table)| table);
+        let replacement = if word == KEY_PLACEHOLDER {
+            first.map(|table| table.key())
+        } else if word == NAMESPACE_PLACEHOLDER {
+            first.map(|table| table.namespace_literal())
+        } else if word == NAMESPACE_KEY_PLACEHOLDER {
+            first.map(|table| table.namespace_key())
+        } else {
+            tables.iter().find_map(|(placeholder, table)| {
+                word.find(placeholder).map(|start| {
+                    let object = format!(
+                        "{}{}{}",
+                        &word[..start],
+                        table.name,
+                        &word[start + placeholder.len()..]
+                    );
+                    table.identifier(&object)
+                })
+            })
+        };
+        match replacement {
+            Some(replacement) if in_literal => filled.push_str(&replacement.replace('\'', "''")),
+            Some(replacement) => filled.push_str(&replacement),
+            None => filled.push_str(word),
+        }
+        word.clear();
+    };
+
+    for character in statement.chars() {
+        if character.is_ascii_alphanumeric() || character == '_' {
+            word.push(character);
+            continue;
+        }
+        flush(&mut word, &mut filled, in_literal);
+        if character == '\'' {
+            in_literal = !in_literal;
+        }
+        filled.push(character);
+    }
+    flush(&mut word, &mut filled, in_literal);
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fill, Table};
+
+    #[test]
+    fn tables_are_filled_in_as_quoted_identifiers() {
+        let table = Table::new(None, "_locks");
+        assert_eq!(
+            table.fill("select * from TABLE_NAME join TABLE_NAME_epochs using (lock_name);"),
+            "select * from \"_locks\" join \"_locks_epochs\" using (lock_name);"
+        );
+
+        let table = Table::new(Some("Billing"), "Locks\"; drop table users; --");
+        assert_eq!(
+            table.fill("create index if not exists TABLE_NAME_idx on TABLE_NAME (lock_name);"),
+            "create index if not exists \"Locks\"\"; drop table users; --_idx\" \
+             on \"Billing\".\"Locks\"\"; drop table users; --\" (lock_name);"
+        );
+        assert_eq!(
+            Table::new(Some("s"), "it's").fill(
+                "select nextval('TABLE_NAME_seq'), pg_notify('cocklock:TABLE_KEY', 'TABLE_NAME');"
+            ),
+            "select nextval('\"s\".\"it''s_seq\"'), pg_notify('cocklock:s.it''s', '\"s\".\"it''s\"');"
+        );
+    }
+
+    #[test]
+    fn placeholders_in_names_are_left_alone() {
+        let old = Table::new(None, "TABLE_NAME");
+        let new = Table::new(None, "new");
+        assert_eq!(
+            fill(
+                "insert into TABLE_NAME select * from SOURCE_TABLE;",
+                &[("TABLE_NAME", &new), ("SOURCE_TABLE", &old)]
+            ),
+            "insert into \"new\" select * from \"TABLE_NAME\";"
+        );
+    }
+
+    #[test]
+    fn namespaces_are_filled_in_as_literals() {
+        let statement = "select from TABLE_NAME_standbys \
+                         where namespace = NAMESPACE and lock_name = NAMESPACE_KEY || $1;";
+        assert_eq!(
+            Table::new(None, "_locks").fill(statement),
+            "select from \"_locks_standbys\" where namespace = '' and lock_name = '' || $1;"
+        );
+
+        let table = Table::new(None, "_locks").in_namespace(Some("it's"));
+        assert_eq!(
+            table.fill(statement),
+            "select from \"_locks_standbys\" \
+             where namespace = 'it''s' and lock_name = ('it''s' || chr(31)) || $1;"
+        );
+        assert_eq!(table.namespaced("job"), "it's\u{1f}job");
+        assert_eq!(table.renamed("_new").namespace.as_deref(), Some("it's"));
+    }
+}
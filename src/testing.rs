@@ -0,0 +1,292 @@
+//! An in-memory model of a set of lock databases, for tests without containers
+//!
+//! `SimCluster` models the lock table of every client database along with a
+//! virtual clock, so that tests can move time forward, skew the clock of a
+//! database, cut it off or slow it down and observe the effect on locking
+//! deterministically. `SimClient` picks databases the way `CockLock` does:
+//! the first reachable one answers. The model follows the semantics of the
+//! lock queries, it doesn't run them.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use uuid::Uuid;
+
+use crate::deterministic::SeededRng;
+use crate::errors::CockLockError;
+
+/// A lock row: its holder and the node-local time it expires at
+#[derive(Debug, Clone, Copy)]
+struct SimLock {
+    holder: Uuid,
+    expires_at_ms: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+struct SimNode {
+    locks: HashMap<String, SimLock>,
+    skew_ms: i64,
+    latency_ms: i64,
+    partitioned: bool,
+}
+
+impl SimNode {
+    fn is_active(lock: &SimLock, now_ms: i64) -> bool {
+        lock.expires_at_ms
+            .is_none_or(|expires_at| expires_at >= now_ms)
+    }
+
+    fn expiry(now_ms: i64, timeout_ms: i32) -> Option<i64> {
+        (timeout_ms != 0).then(|| now_ms + i64::from(timeout_ms))
+    }
+
+    fn lock(&mut self, holder: Uuid, lock_name: &str, timeout_ms: i32, now_ms: i64) -> bool {
+        self.locks.retain(|_, lock| Self::is_active(lock, now_ms));
+        match self.locks.get_mut(lock_name) {
+            Some(lock) if lock.holder != holder => false,
+            Some(lock) => {
+                lock.expires_at_ms = Self::expiry(now_ms, timeout_ms);
+                true
+            }
+            None => {
+                let expires_at_ms = Self::expiry(now_ms, timeout_ms);
+                let lock = SimLock {
+                    holder,
+                    expires_at_ms,
+                };
+                self.locks.insert(lock_name.to_owned(), lock);
+                true
+            }
+        }
+    }
+
+    fn extend(&mut self, holder: Uuid, lock_name: &str, timeout_ms: i32, now_ms: i64) -> bool {
+        self.locks.retain(|_, lock| Self::is_active(lock, now_ms));
+        match self.locks.get_mut(lock_name) {
+            Some(lock) if lock.holder == holder => {
+                lock.expires_at_ms = Self::expiry(now_ms, timeout_ms);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn unlock(&mut self, holder: Uuid, lock_name: &str) -> bool {
+        match self.locks.get(lock_name) {
+            Some(lock) if lock.holder == holder => {
+                self.locks.remove(lock_name);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct SimState {
+    now_ms: i64,
+    nodes: Vec<SimNode>,
+    /// Hands out the client IDs of a seeded cluster
+    rng: Option<SeededRng>,
+}
+
+/// A set of simulated lock databases sharing a virtual clock
+///
+/// Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct SimCluster {
+    state: Arc<Mutex<SimState>>,
+}
+
+impl SimCluster {
+    /// A cluster of `nodes` databases, all reachable, without skew or latency
+    pub fn new(nodes: usize) -> Self {
+        let state = SimState {
+            now_ms: 0,
+            nodes: (0..nodes).map(|_| SimNode::default()).collect(),
+            rng: None,
+        };
+        Self {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// A cluster whose clients get the same IDs, in the same order, on every
+    /// run with the same seed
+    pub fn with_seed(nodes: usize, seed: u64) -> Self {
+        let cluster = Self::new(nodes);
+        cluster.state.lock().unwrap().rng = Some(SeededRng::new(seed));
+        cluster
+    }
+
+    /// The virtual time in milliseconds since the cluster was created
+    pub fn now_ms(&self) -> i64 {
+        self.state.lock().unwrap().now_ms
+    }
+
+    /// Move the virtual clock forward
+    pub fn advance(&self, ms: i64) {
+        self.state.lock().unwrap().now_ms += ms;
+    }
+
+    /// Make the clock of a node run ahead (or behind, if negative) of the
+    /// virtual clock
+    pub fn set_skew(&self, node: usize, skew_ms: i64) {
+        self.state.lock().unwrap().nodes[node].skew_ms = skew_ms;
+    }
+
+    /// Make every statement on a node take some virtual time
+    pub fn set_latency(&self, node: usize, latency_ms: i64) {
+        self.state.lock().unwrap().nodes[node].latency_ms = latency_ms;
+    }
+
+    /// Cut a node off from every client
+    pub fn partition(&self, node: usize) {
+        self.state.lock().unwrap().nodes[node].partitioned = true;
+    }
+
+    /// Make a partitioned node reachable again
+    pub fn heal(&self, node: usize) {
+        self.state.lock().unwrap().nodes[node].partitioned = false;
+    }
+
+    /// The holder of a lock on a node, regardless of partitions
+    pub fn holder(&self, node: usize, lock_name: &str) -> Option<Uuid> {
+        let state = self.state.lock().unwrap();
+        let now_ms = state.now_ms + state.nodes[node].skew_ms;
+        state.nodes[node]
+            .locks
+            .get(lock_name)
+            .filter(|lock| SimNode::is_active(lock, now_ms))
+            .map(|lock| lock.holder)
+    }
+
+    /// A new client with a random ID, or the next seeded one
+    pub fn client(&self) -> SimClient {
+        let id = match &mut self.state.lock().unwrap().rng {
+            Some(rng) => rng.uuid(),
+            None => Uuid::new_v4(),
+        };
+        SimClient {
+            id,
+            cluster: self.clone(),
+        }
+    }
+
+    /// Run an operation on the first reachable node, like `CockLock` does
+    fn on_available_node<F>(&self, mut operation: F) -> Result<(), CockLockError>
+    where
+        F: FnMut(&mut SimNode, i64) -> bool,
+    {
+        let mut state = self.state.lock().unwrap();
+        let SimState { now_ms, nodes, .. } = &mut *state;
+        let node = match nodes.iter_mut().find(|node| !node.partitioned) {
+            Some(node) => node,
+            None => return Err(CockLockError::NoClientsAvailable(vec![])),
+        };
+
+        *now_ms += node.latency_ms;
+        if operation(node, *now_ms + node.skew_ms) {
+            Ok(())
+        } else {
+            Err(CockLockError::NotAvailable)
+        }
+    }
+}
+
+/// A client of a `SimCluster`, with the locking methods of `CockLock`
+#[derive(Debug, Clone)]
+pub struct SimClient {
+    id: Uuid,
+    cluster: SimCluster,
+}
+
+impl SimClient {
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn lock<T: ToString>(&self, lock_name: T, timeout_ms: i32) -> Result<(), CockLockError> {
+        let lock_name = lock_name.to_string();
+        self.cluster
+            .on_available_node(|node, now_ms| node.lock(self.id, &lock_name, timeout_ms, now_ms))
+    }
+
+    pub fn extend<T: ToString>(&self, lock_name: T, timeout_ms: i32) -> Result<(), CockLockError> {
+        let lock_name = lock_name.to_string();
+        self.cluster
+            .on_available_node(|node, now_ms| node.extend(self.id, &lock_name, timeout_ms, now_ms))
+    }
+
+    pub fn unlock<T: ToString>(&self, lock_name: T) -> Result<(), CockLockError> {
+        let lock_name = lock_name.to_string();
+        self.cluster
+            .on_available_node(|node, _| node.unlock(self.id, &lock_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SimCluster;
+    use crate::errors::CockLockError;
+
+    #[test]
+    fn locks_expire_on_the_virtual_clock() {
+        let cluster = SimCluster::new(1);
+        let alice = cluster.client();
+        let bob = cluster.client();
+
+        alice.lock("task", 1_000).unwrap();
+        assert!(matches!(
+            bob.lock("task", 1_000),
+            Err(CockLockError::NotAvailable)
+        ));
+        cluster.advance(1_001);
+        assert!(bob.lock("task", 1_000).is_ok());
+        assert!(alice.extend("task", 1_000).is_err());
+    }
+
+    #[test]
+    fn partitions_fail_over_to_the_next_node() {
+        let cluster = SimCluster::new(2);
+        let alice = cluster.client();
+        let bob = cluster.client();
+
+        alice.lock("task", 1_000).unwrap();
+        cluster.partition(0);
+
+        // The second node never saw the lock
+        assert!(bob.lock("task", 1_000).is_ok());
+        assert_eq!(cluster.holder(0, "task"), Some(alice.id()));
+        assert_eq!(cluster.holder(1, "task"), Some(bob.id()));
+
+        cluster.partition(1);
+        assert!(matches!(
+            alice.unlock("task"),
+            Err(CockLockError::NoClientsAvailable(_))
+        ));
+    }
+
+    #[test]
+    fn seeded_clusters_hand_out_the_same_ids() {
+        let first = SimCluster::with_seed(1, 7);
+        let second = SimCluster::with_seed(1, 7);
+        assert_eq!(first.client().id(), second.client().id());
+        assert_ne!(first.client().id(), first.client().id());
+    }
+
+    #[test]
+    fn skew_and_latency_shift_expiry() {
+        let cluster = SimCluster::new(1);
+        let alice = cluster.client();
+
+        cluster.set_latency(0, 300);
+        alice.lock("task", 1_000).unwrap();
+        assert_eq!(cluster.now_ms(), 300);
+
+        // A node whose clock runs ahead sees the lock expire early
+        cluster.set_skew(0, 1_500);
+        assert_eq!(cluster.holder(0, "task"), None);
+        assert!(alice.extend("task", 1_000).is_err());
+    }
+}
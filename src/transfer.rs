@@ -0,0 +1,97 @@
+//! Handing a lock over to another holder
+//!
+//! Releasing a lock and letting the next holder take it leaves a moment in
+//! which anybody may take it. A transfer changes the holder of the lock in a
+//! single statement that only succeeds while the lock is still held by the
+//! instance giving it away, so the lock is never free in between.
+
+use std::time::Instant;
+
+use postgres::types::ToSql;
+use postgres::Client;
+
+use crate::cutover::Cutover;
+use crate::delegation::LeaseHandle;
+use crate::errors::CockLockError;
+use crate::fencing::FencingToken;
+use crate::identity::Identity;
+use crate::lock::{CockLock, CockLockQueries, Operation};
+use crate::quorum::Quorum;
+
+impl CockLock {
+    /// Hand a lock held by the instance to `new_holder`
+    ///
+    /// The lock keeps its lease and gets a new fencing token, which is
+    /// returned for the new holder, so that writes of the instance are fenced
+    /// off from then on. Fails with `CockLockError::NotHeld` if the instance
+    /// doesn't hold the lock.
+    ///
+    /// The new holder renews the lock like any other, its `held_locks` only
+    /// list the lock once it did.
+    pub fn transfer<T: ToString, I: Into<Identity>>(
+        &mut self,
+        lock_name: T,
+        new_holder: I,
+    ) -> Result<FencingToken, CockLockError> {
+        self.transfer_as(self.id, lock_name.to_string(), new_holder.into())
+    }
+
+    /// Hand the lock of a handle to `new_holder`, on behalf of the holder of
+    /// the handle
+    ///
+    /// Lets a coordinator move a lock between workers, see `transfer`.
+    pub fn transfer_from<I: Into<Identity>>(
+        &mut self,
+        handle: &LeaseHandle,
+        new_holder: I,
+    ) -> Result<FencingToken, CockLockError> {
+        self.transfer_as(handle.holder, handle.lock_name.clone(), new_holder.into())
+    }
+
+    fn transfer_as(
+        &mut self,
+        holder: Identity,
+        lock_name: String,
+        new_holder: Identity,
+    ) -> Result<FencingToken, CockLockError> {
+        self.require_table("transfers")?;
+        let started_at = Instant::now();
+        let params: &[&(dyn ToSql + Sync)] = &[&holder, &lock_name, &new_holder];
+        let statements =
+            |client: &mut Client, queries: &CockLockQueries, cutover: Option<&Cutover>| {
+                let mut transaction = client.transaction()?;
+                let mut token = None;
+                if let Some(cutover) = cutover {
+                    match transaction.query_opt(&cutover.queries.transfer, params)? {
+                        Some(row) => token = Some(row.get::<_, i64>(0)),
+                        None => return Ok(None),
+                    }
+                }
+                let row = match transaction.query_opt(&queries.transfer, params)? {
+                    Some(row) => row,
+                    None => return Ok(None),
+                };
+                transaction.commit()?;
+                Ok(Some(token.unwrap_or(0).max(row.get(0))))
+            };
+        let (client, result) = match self.quorum {
+            Quorum::FirstAvailable => self.on_available_client(Operation::Transfer, statements),
+            Quorum::Majority | Quorum::All => {
+                self.on_quorum(Operation::Transfer, statements, Option::is_some)
+            }
+        };
+
+        let result = result.and_then(|token| {
+            token
+                .map(FencingToken::new)
+                .ok_or_else(|| CockLockError::NotHeld(lock_name.clone()))
+        });
+        if result.is_ok() && holder == self.id && new_holder != self.id {
+            self.held.released(&lock_name);
+        }
+        let client = client.map(|index| (index, self.labels[index].as_str()));
+        self.journal
+            .record(Operation::Transfer, &lock_name, client, started_at, &result);
+        result
+    }
+}
@@ -0,0 +1,130 @@
+//! Flagging suspicious uses of locks at runtime
+//!
+//! Some mistakes work most of the time and only bite under load: releasing
+//! locks that were never taken, holding locks about as long as their lease,
+//! or nesting critical sections on one lock, where the inner unlock releases
+//! the lock for the outer one too. With strict usage the instance checks its
+//! own operations for these patterns and reports them, meant for staging
+//! rather than production.
+
+use std::fmt::{self, Display};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::lock::{CockLock, RelockPolicy};
+
+/// Holding a lock for more than this share of its lease is flagged
+const LEASE_USE_LIMIT: f64 = 0.8;
+
+/// Called with every suspicious use, see `CockLockBuilder::with_usage_callback`
+pub type UsageCallback = Arc<dyn Fn(&UsageWarning) + Send + Sync>;
+
+/// A suspicious use of a lock, reported in strict usage mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UsageWarning {
+    /// A lock was unlocked that the instance didn't hold as far as it knows:
+    /// it was never locked, already unlocked, or is left from a previous run
+    UnlockedWithoutLocking(String),
+    /// A lock was unlocked `held` after it was last taken or renewed, close
+    /// to or past its timeout, so another instance may have taken it midway
+    LeaseTooShort {
+        lock_name: String,
+        timeout_ms: i64,
+        held: Duration,
+    },
+    /// A lock held by the instance was locked again, which extends the lease
+    /// both times share; set a `RelockPolicy` if that's intended
+    Relocked(String),
+}
+
+impl Display for UsageWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UsageWarning::UnlockedWithoutLocking(lock_name) => {
+                write!(f, "Unlocked {lock_name:?}, which wasn't locked")
+            }
+            UsageWarning::LeaseTooShort {
+                lock_name,
+                timeout_ms,
+                held,
+            } => write!(
+                f,
+                "Held {lock_name:?} for {held:?} of its {timeout_ms}ms lease, \
+                 use a longer timeout or renew it"
+            ),
+            UsageWarning::Relocked(lock_name) => {
+                write!(f, "Locked {lock_name:?} again while holding it")
+            }
+        }
+    }
+}
+
+/// See `CockLockBuilder::with_strict_usage`
+#[derive(Clone, Default)]
+pub(crate) struct StrictUsage {
+    callback: Option<UsageCallback>,
+}
+
+impl StrictUsage {
+    pub(crate) fn new(callback: Option<UsageCallback>) -> Self {
+        Self { callback }
+    }
+
+    fn report(&self, warning: UsageWarning) {
+        log::warn!(target: "cocklock::usage", "{warning}");
+        if let Some(callback) = &self.callback {
+            callback(&warning);
+        }
+    }
+}
+
+impl CockLock {
+    /// Flag locking a name held already, unless a `RelockPolicy` other than
+    /// the default says what that does
+    pub(crate) fn check_relock_usage(&self, lock_name: &str, relock_policy: RelockPolicy) {
+        let strict_usage = match &self.strict_usage {
+            Some(strict_usage) => strict_usage,
+            None => return,
+        };
+        let now = self.clock.now();
+        let held = self
+            .held
+            .get(lock_name)
+            .is_some_and(|lock| !lock.is_expired(now));
+        if held && relock_policy == RelockPolicy::Extend {
+            strict_usage.report(UsageWarning::Relocked(lock_name.to_owned()));
+        }
+    }
+
+    /// Flag unlocking a name that isn't held, or that was held for most of
+    /// its lease
+    pub(crate) fn check_unlock_usage(&self, lock_name: &str) {
+        let strict_usage = match &self.strict_usage {
+            Some(strict_usage) => strict_usage,
+            None => return,
+        };
+        let lock = match self.held.get(lock_name) {
+            Some(lock) => lock,
+            None => {
+                strict_usage.report(UsageWarning::UnlockedWithoutLocking(lock_name.to_owned()));
+                return;
+            }
+        };
+        let expires_at = match lock.expires_at {
+            Some(expires_at) => expires_at,
+            None => return,
+        };
+        let lease = Duration::from_millis(lock.timeout_ms.unsigned_abs());
+        let held = self
+            .clock
+            .now()
+            .saturating_duration_since(expires_at - lease);
+        if held >= lease.mul_f64(LEASE_USE_LIMIT) {
+            strict_usage.report(UsageWarning::LeaseTooShort {
+                lock_name: lock_name.to_owned(),
+                timeout_ms: lock.timeout_ms,
+                held,
+            });
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! HTTP callbacks for critical locks
+//!
+//! Some locks matter to people and systems that have no business reading
+//! the lock table, like a chat channel that wants to know when the migration
+//! lock is taken. A `Webhook` posts a small JSON document to a URL whenever
+//! one of its locks is acquired, released or found expired by this
+//! instance. Deliveries happen on a background thread, so a slow or
+//! unreachable endpoint never holds up the lock operations; failed
+//! deliveries are logged with the target `cocklock::webhooks` and dropped.
+
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::thread::spawn;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::identity::Identity;
+
+/// How long a delivery may take before it is given up
+pub static DEFAULT_WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What happened to a lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockEvent {
+    Acquired,
+    Released,
+    /// The instance found its lease gone when extending or refreshing it,
+    /// including the renewals of keep-alives and schedulers, or lost its
+    /// advisory locks along with a connection
+    ///
+    /// A lease that runs out while nobody touches it, or that another
+    /// instance reaps, is only reported once this instance tries to renew
+    /// it; a lock that is never renewed again is never reported.
+    Expired,
+}
+
+/// A URL to notify about some locks, see `CockLockBuilder::with_webhook`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    url: String,
+    lock_names: Vec<String>,
+    events: Vec<LockEvent>,
+}
+
+impl Webhook {
+    /// Post to `url` on every event of the locks added with
+    /// `with_lock_name`
+    pub fn new<T: ToString>(url: T) -> Self {
+        Self {
+            url: url.to_string(),
+            lock_names: vec![],
+            events: vec![LockEvent::Acquired, LockEvent::Released, LockEvent::Expired],
+        }
+    }
+
+    /// Notify about the lock `lock_name`
+    pub fn with_lock_name<T: ToString>(mut self, lock_name: T) -> Self {
+        self.lock_names.push(lock_name.to_string());
+        self
+    }
+
+    /// Only notify about these events
+    pub fn with_events(mut self, events: &[LockEvent]) -> Self {
+        self.events = events.to_vec();
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn wants(&self, event: LockEvent, lock_name: &str) -> bool {
+        self.events.contains(&event) && self.lock_names.iter().any(|name| name == lock_name)
+    }
+}
+
+/// The document posted to a webhook
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub event: LockEvent,
+    pub lock_name: String,
+    pub client_id: Identity,
+    /// Milliseconds since the Unix epoch on the clock of the instance
+    pub at_ms: u128,
+}
+
+/// The webhooks of an instance and the thread delivering to them
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Webhooks {
+    webhooks: Arc<Vec<Webhook>>,
+    deliveries: Option<Sender<(String, WebhookPayload)>>,
+}
+
+impl Webhooks {
+    /// Start delivering to `webhooks`, no thread is started without any
+    pub fn new(webhooks: Vec<Webhook>) -> Self {
+        if webhooks.is_empty() {
+            return Self::default();
+        }
+        let (deliveries, pending) = channel::<(String, WebhookPayload)>();
+        spawn(move || {
+            let agent = ureq::AgentBuilder::new()
+                .timeout(DEFAULT_WEBHOOK_TIMEOUT)
+                .build();
+            // Ends once every instance sharing the webhooks is dropped
+            for (url, payload) in pending {
+                let body = match serde_json::to_string(&payload) {
+                    Ok(body) => body,
+                    Err(err) => {
+                        log::warn!(target: "cocklock::webhooks", "{err}");
+                        continue;
+                    }
+                };
+                let response = agent
+                    .post(&url)
+                    .set("Content-Type", "application/json")
+                    .send_string(&body);
+                if let Err(err) = response {
+                    log::warn!(
+                        target: "cocklock::webhooks",
+                        "Failed to notify {url} that {} was {:?}: {err}",
+                        payload.lock_name,
+                        payload.event
+                    );
+                }
+            }
+        });
+        Self {
+            webhooks: Arc::new(webhooks),
+            deliveries: Some(deliveries),
+        }
+    }
+
+    /// Queue a delivery to every webhook interested in the event
+    pub fn fire(&self, event: LockEvent, lock_name: &str, client_id: Identity) {
+        let deliveries = match &self.deliveries {
+            Some(deliveries) => deliveries,
+            None => return,
+        };
+        let at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis());
+        for webhook in self.webhooks.iter() {
+            if webhook.wants(event, lock_name) {
+                let payload = WebhookPayload {
+                    event,
+                    lock_name: lock_name.to_owned(),
+                    client_id,
+                    at_ms,
+                };
+                let _ = deliveries.send((webhook.url.clone(), payload));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    use super::{LockEvent, Webhook, WebhookPayload, Webhooks};
+    use crate::identity::Identity;
+
+    #[test]
+    fn events_of_configured_locks_are_posted() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hooks", listener.local_addr().unwrap());
+        let webhooks = Webhooks::new(vec![Webhook::new(url)
+            .with_lock_name("migration")
+            .with_events(&[LockEvent::Acquired])]);
+        let client_id = Identity::random();
+
+        webhooks.fire(LockEvent::Released, "migration", client_id);
+        webhooks.fire(LockEvent::Acquired, "other", client_id);
+        webhooks.fire(LockEvent::Acquired, "migration", client_id);
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                content_length = length.trim().parse().unwrap();
+            }
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        stream
+            .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+
+        let payload: WebhookPayload = serde_json::from_slice(&body).unwrap();
+        assert_eq!(payload.event, LockEvent::Acquired);
+        assert_eq!(payload.lock_name, "migration");
+        assert_eq!(payload.client_id, client_id);
+    }
+}